@@ -0,0 +1,50 @@
+//! Benchmarks of the per-call overhead of evaluating procedures.
+//!
+//! Each program is lexed and parsed once, so only interpretation is measured.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use logolang_lib::interpreter::Interpreter;
+use logolang_lib::lexer::tokenize_str;
+use logolang_lib::parser::{AstNode, Parser};
+use std::collections::HashMap;
+use unsvg::Image;
+
+/// A binary tree of depth 12, drawn by 8191 recursive calls
+const RECURSIVE_TREE: &str = "TO Tree \"depth \"len
+    IF GT :depth \"0 [
+        FORWARD :len
+        TURN \"-20
+        Tree - :depth \"1 * :len \"0.7
+        TURN \"40
+        Tree - :depth \"1 * :len \"0.7
+        TURN \"-20
+        BACK :len
+    ]
+END
+
+PENDOWN
+Tree \"12 \"60
+";
+
+/// Lexes and parses a program as rslogo does by default
+fn parse(program: &str) -> Vec<AstNode> {
+    let tokens = tokenize_str(program, &HashMap::new(), "//").unwrap();
+    Parser::new().parse(tokens).unwrap()
+}
+
+/// Runs a program on a new 400x400 image
+fn run(ast: &Vec<AstNode>) {
+    let mut image = Image::new(400, 400);
+    Interpreter::new(&mut image).run(black_box(ast)).unwrap();
+}
+
+fn procedure_calls(c: &mut Criterion) {
+    let tree = parse(RECURSIVE_TREE);
+    c.bench_function("recursive tree", |b| b.iter(|| run(&tree)));
+
+    let hot_loop = parse(include_str!("../../logo_examples/6_97_hot_procedure.lg"));
+    c.bench_function("procedure called in a loop", |b| b.iter(|| run(&hot_loop)));
+}
+
+criterion_group!(benches, procedure_calls);
+criterion_main!(benches);
@@ -0,0 +1,392 @@
+//! Module for static analysis of parsed RSLOGO programs.
+//!
+//! Analyses report likely mistakes as warnings, without preventing the program from running.
+//!
+//! # Examples
+//!
+//! ```
+//! use logolang_lib::analysis::call_graph;
+//! use logolang_lib::parser::AstNode;
+//! use std::rc::Rc;
+//!
+//! let ast = vec![
+//!     AstNode::Procedure {
+//!         name: String::from("SIDE"),
+//!         params: Rc::new(vec![]),
+//!         body: Rc::new(vec![AstNode::PenStatusUpdate(true)]),
+//!     },
+//!     AstNode::Procedure {
+//!         name: String::from("SQUARE"),
+//!         params: Rc::new(vec![]),
+//!         body: Rc::new(vec![AstNode::ProcedureRef {
+//!             name_ref: String::from("SIDE"),
+//!             args: Rc::new(vec![]),
+//!             line: 5,
+//!         }]),
+//!     },
+//! ];
+//!
+//! let graph = call_graph(&ast);
+//! assert_eq!(graph["SQUARE"], vec![String::from("SIDE")]);
+//! assert!(graph["SIDE"].is_empty());
+//! ```
+
+use crate::parser::AstNode;
+use crate::visitor::{walk_all, Visitor};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// Finds statements which can never be evaluated because they follow an unconditional STOP
+/// or OUTPUT in the same block. A STOP nested inside an IF, IFELSE, WHILE, REPEAT, FOR or
+/// FOREACH is conditional, so it only makes the rest of that inner block unreachable.
+pub fn unreachable_statements(ast: &[AstNode]) -> Vec<String> {
+    let mut finder = UnreachableFinder {
+        warnings: Vec::new(),
+    };
+    finder.check_block(ast);
+    walk_all(ast, &mut finder);
+    finder.warnings
+}
+
+/// Collects a warning for each block containing statements after a STOP or OUTPUT
+struct UnreachableFinder {
+    warnings: Vec<String>,
+}
+
+impl UnreachableFinder {
+    /// Checks the statements directly within a block, ignoring nested blocks
+    fn check_block(&mut self, block: &[AstNode]) {
+        let stop = block.iter().enumerate().find_map(|(i, node)| match node {
+            AstNode::Stop { line } => Some((i, line, "STOP")),
+            AstNode::Output { line, .. } => Some((i, line, "OUTPUT")),
+            _ => None,
+        });
+
+        if let Some((i, line, keyword)) = stop {
+            let unreachable = block.len() - i - 1;
+            if unreachable > 0 {
+                self.warnings.push(format!(
+                    "[Line {}]: {} statement(s) after {} will never be evaluated.",
+                    line, unreachable, keyword
+                ));
+            }
+        }
+    }
+}
+
+impl Visitor for UnreachableFinder {
+    fn visit_if_stmnt(&mut self, node: &AstNode) {
+        if let AstNode::IfStmnt { body, .. } = node {
+            self.check_block(body);
+        }
+    }
+
+    fn visit_if_else_stmnt(&mut self, node: &AstNode) {
+        if let AstNode::IfElseStmnt {
+            then_body,
+            else_body,
+            ..
+        } = node
+        {
+            self.check_block(then_body);
+            self.check_block(else_body);
+        }
+    }
+
+    fn visit_while_stmnt(&mut self, node: &AstNode) {
+        if let AstNode::WhileStmnt { body, .. } = node {
+            self.check_block(body);
+        }
+    }
+
+    fn visit_repeat_stmnt(&mut self, node: &AstNode) {
+        if let AstNode::RepeatStmnt { body, .. } = node {
+            self.check_block(body);
+        }
+    }
+
+    fn visit_for_stmnt(&mut self, node: &AstNode) {
+        if let AstNode::ForStmnt { body, .. } = node {
+            self.check_block(body);
+        }
+    }
+
+    fn visit_for_each_stmnt(&mut self, node: &AstNode) {
+        if let AstNode::ForEachStmnt { body, .. } = node {
+            self.check_block(body);
+        }
+    }
+
+    fn visit_procedure(&mut self, node: &AstNode) {
+        if let AstNode::Procedure { body, .. } = node {
+            self.check_block(body);
+        }
+    }
+}
+
+/// Builds the call graph of a program, mapping each defined procedure to the procedures called
+/// anywhere within its body, in the order of their first call. Every defined procedure appears
+/// as a key, even if it makes no calls.
+pub fn call_graph(ast: &[AstNode]) -> HashMap<String, Vec<String>> {
+    let mut collector = CallCollector {
+        graph: HashMap::new(),
+    };
+    walk_all(ast, &mut collector);
+    collector.graph
+}
+
+/// Finds the groups of procedures which can call themselves, directly or through each other.
+/// Each cycle is sorted by name, and the cycles are sorted by their first procedure.
+pub fn recursive_cycles(graph: &HashMap<String, Vec<String>>) -> Vec<Vec<String>> {
+    let reachable: HashMap<&String, HashSet<&String>> = graph
+        .keys()
+        .map(|name| (name, reachable_from(graph, name)))
+        .collect();
+
+    let mut names: Vec<&String> = graph.keys().collect();
+    names.sort();
+
+    let mut seen: HashSet<&String> = HashSet::new();
+    let mut cycles = Vec::new();
+    for name in names.iter().copied() {
+        if seen.contains(name) || !reachable[name].contains(name) {
+            continue;
+        }
+
+        // Procedures reachable from each other belong to the same cycle
+        let cycle: Vec<String> = names
+            .iter()
+            .copied()
+            .filter(|other| reachable[name].contains(other) && reachable[other].contains(name))
+            .cloned()
+            .collect();
+        seen.extend(names.iter().copied().filter(|other| cycle.contains(other)));
+        cycles.push(cycle);
+    }
+
+    cycles
+}
+
+/// Formats a call graph in the DOT language, with procedures and calls in sorted order
+pub fn call_graph_dot(graph: &HashMap<String, Vec<String>>) -> String {
+    let mut names: Vec<&String> = graph.keys().collect();
+    names.sort();
+
+    let mut dot = String::from("digraph calls {\n");
+    for name in names {
+        dot.push_str(&format!("    \"{}\";\n", name));
+        let mut callees = graph[name].clone();
+        callees.sort();
+        for callee in callees {
+            dot.push_str(&format!("    \"{}\" -> \"{}\";\n", name, callee));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Returns every procedure which can be reached by one or more calls from the given procedure
+fn reachable_from<'a>(graph: &'a HashMap<String, Vec<String>>, name: &str) -> HashSet<&'a String> {
+    let mut reached = HashSet::new();
+    let mut pending: Vec<&String> = graph.get(name).into_iter().flatten().collect();
+
+    while let Some(callee) = pending.pop() {
+        if let Some((callee, calls)) = graph.get_key_value(callee) {
+            if reached.insert(callee) {
+                pending.extend(calls);
+            }
+        }
+    }
+
+    reached
+}
+
+/// Records the calls made within the body of each procedure definition
+struct CallCollector {
+    graph: HashMap<String, Vec<String>>,
+}
+
+impl Visitor for CallCollector {
+    fn visit_procedure(&mut self, node: &AstNode) {
+        if let AstNode::Procedure { name, body, .. } = node {
+            let mut calls = CallFinder(Vec::new());
+            walk_all(body, &mut calls);
+            self.graph.insert(name.clone(), calls.0);
+        }
+    }
+}
+
+/// Collects the names of the procedures called within a block, without duplicates
+struct CallFinder(Vec<String>);
+
+impl Visitor for CallFinder {
+    fn visit_procedure_ref(&mut self, node: &AstNode) {
+        if let AstNode::ProcedureRef { name_ref, .. } = node {
+            if !self.0.contains(name_ref) {
+                self.0.push(name_ref.clone());
+            }
+        }
+    }
+}
+
+/// Size and structure measurements of a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// Number of nodes of each kind, including expressions nested within statements
+    pub node_counts: BTreeMap<&'static str, usize>,
+    /// Deepest nesting of IF, IFELSE, WHILE, REPEAT, FOR and FOREACH blocks, where 0 means no
+    /// blocks are used
+    pub max_nesting_depth: usize,
+    /// Number of procedure definitions
+    pub num_procedures: usize,
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Procedures: {}", self.num_procedures)?;
+        writeln!(f, "Max nesting depth: {}", self.max_nesting_depth)?;
+        writeln!(f, "Node counts:")?;
+        for (kind, count) in &self.node_counts {
+            writeln!(f, "    {}: {}", kind, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Measures the size and structure of a program.
+///
+/// # Examples
+///
+/// ```
+/// use logolang_lib::analysis::metrics;
+/// use logolang_lib::parser::{AstNode, Direction};
+///
+/// let forward = AstNode::DrawInstruction {
+///     direction: Direction::FORWARD,
+///     num_pixels: Box::new(AstNode::Num(10.0)),
+///     line: 3,
+/// };
+/// let ast = vec![AstNode::WhileStmnt {
+///     condition: Box::new(AstNode::IdentRef(String::from("go"))),
+///     body: Box::new(vec![AstNode::IfStmnt {
+///         condition: Box::new(AstNode::IdentRef(String::from("go"))),
+///         body: Box::new(vec![forward]),
+///         line: 2,
+///     }]),
+///     line: 1,
+/// }];
+///
+/// let metrics = metrics(&ast);
+/// assert_eq!(metrics.max_nesting_depth, 2);
+/// assert_eq!(metrics.num_procedures, 0);
+/// assert_eq!(metrics.node_counts["IdentRef"], 2);
+/// assert_eq!(metrics.node_counts["DrawInstruction"], 1);
+/// assert_eq!(metrics.node_counts["Num"], 1);
+/// ```
+pub fn metrics(ast: &[AstNode]) -> Metrics {
+    let mut counter = NodeCounter(BTreeMap::new());
+    walk_all(ast, &mut counter);
+
+    Metrics {
+        num_procedures: counter.0.get("Procedure").copied().unwrap_or(0),
+        node_counts: counter.0,
+        max_nesting_depth: nesting_depth(ast),
+    }
+}
+
+/// Returns the deepest nesting of IF, IFELSE, WHILE, REPEAT, FOR and FOREACH blocks within a
+/// block. Procedure bodies are measured from their own definition, as they are not nested within
+/// the caller's blocks.
+fn nesting_depth(block: &[AstNode]) -> usize {
+    block
+        .iter()
+        .map(|node| match node {
+            AstNode::IfStmnt { body, .. }
+            | AstNode::WhileStmnt { body, .. }
+            | AstNode::RepeatStmnt { body, .. }
+            | AstNode::ForStmnt { body, .. }
+            | AstNode::ForEachStmnt { body, .. } => 1 + nesting_depth(body),
+            AstNode::IfElseStmnt {
+                then_body,
+                else_body,
+                ..
+            } => 1 + nesting_depth(then_body).max(nesting_depth(else_body)),
+            AstNode::Procedure { body, .. } => nesting_depth(body),
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Counts the nodes of each kind in the program
+struct NodeCounter(BTreeMap<&'static str, usize>);
+
+impl Visitor for NodeCounter {
+    fn visit_node(&mut self, node: &AstNode) {
+        *self.0.entry(node_kind(node)).or_insert(0) += 1;
+    }
+}
+
+/// Returns the name of a node's kind
+pub(crate) fn node_kind(node: &AstNode) -> &'static str {
+    match node {
+        AstNode::MakeStmnt { .. } => "MakeStmnt",
+        AstNode::ArithExpr { .. } => "ArithExpr",
+        AstNode::CompExpr { .. } => "CompExpr",
+        AstNode::BoolExpr { .. } => "BoolExpr",
+        AstNode::TrigExpr { .. } => "TrigExpr",
+        AstNode::RoundExpr { .. } => "RoundExpr",
+        AstNode::Negate { .. } => "Negate",
+        AstNode::Abs { .. } => "Abs",
+        AstNode::Sqrt { .. } => "Sqrt",
+        AstNode::NotExpr { .. } => "NotExpr",
+        AstNode::Random { .. } => "Random",
+        AstNode::IdentRef(_) => "IdentRef",
+        AstNode::AddAssign { .. } => "AddAssign",
+        AstNode::Ident { .. } => "Ident",
+        AstNode::Num(_) => "Num",
+        AstNode::BoolLiteral(_) => "BoolLiteral",
+        AstNode::IfStmnt { .. } => "IfStmnt",
+        AstNode::IfElseStmnt { .. } => "IfElseStmnt",
+        AstNode::WhileStmnt { .. } => "WhileStmnt",
+        AstNode::RepeatStmnt { .. } => "RepeatStmnt",
+        AstNode::ForStmnt { .. } => "ForStmnt",
+        AstNode::ForEachStmnt { .. } => "ForEachStmnt",
+        AstNode::PenStatusUpdate(_) => "PenStatusUpdate",
+        AstNode::TurtleVisibility(_) => "TurtleVisibility",
+        AstNode::AngleUnits(_) => "AngleUnits",
+        AstNode::PenColorUpdate { .. } => "PenColorUpdate",
+        AstNode::PenRgbUpdate { .. } => "PenRgbUpdate",
+        AstNode::PenOpacityUpdate { .. } => "PenOpacityUpdate",
+        AstNode::PenWidthUpdate { .. } => "PenWidthUpdate",
+        AstNode::PenDashUpdate { .. } => "PenDashUpdate",
+        AstNode::BackgroundUpdate { .. } => "BackgroundUpdate",
+        AstNode::SavePen { .. } => "SavePen",
+        AstNode::LoadPen { .. } => "LoadPen",
+        AstNode::Mark { .. } => "Mark",
+        AstNode::GoMark { .. } => "GoMark",
+        AstNode::PenPosUpdate { .. } => "PenPosUpdate",
+        AstNode::Query(_) => "Query",
+        AstNode::Procedure { .. } => "Procedure",
+        AstNode::Stop { .. } => "Stop",
+        AstNode::Output { .. } => "Output",
+        AstNode::ProcedureRef { .. } => "ProcedureRef",
+        AstNode::DrawInstruction { .. } => "DrawInstruction",
+        AstNode::DrawAt { .. } => "DrawAt",
+        AstNode::IfVal { .. } => "IfVal",
+        AstNode::ForwardDots { .. } => "ForwardDots",
+        AstNode::Star { .. } => "Star",
+        AstNode::CurveTo { .. } => "CurveTo",
+        AstNode::Arc { .. } => "Arc",
+        AstNode::SetPosition { .. } => "SetPosition",
+        AstNode::Label { .. } => "Label",
+        AstNode::Fill { .. } => "Fill",
+        AstNode::Stamp { .. } => "Stamp",
+        AstNode::ResetAll { .. } => "ResetAll",
+        AstNode::Home { .. } => "Home",
+        AstNode::ClearScreen { .. } => "ClearScreen",
+        AstNode::ShowPath { .. } => "ShowPath",
+        AstNode::Axes { .. } => "Axes",
+        AstNode::Word(_) => "Word",
+    }
+}
@@ -0,0 +1,419 @@
+//! Module for checking parsed RSLOGO programs without running them.
+//!
+//! The parser checks the types of literal arguments, but variables and procedure calls are only
+//! resolved by the interpreter. `check` resolves them statically instead, so a program may be
+//! validated, for example by an editor, without an image to draw on.
+//!
+//! # Examples
+//!
+//! ```
+//! use logolang_lib::checker::check;
+//! use logolang_lib::parser::{AstNode, Direction};
+//!
+//! // FORWARD :length, before length is made
+//! let ast = vec![AstNode::DrawInstruction {
+//!     direction: Direction::FORWARD,
+//!     num_pixels: Box::new(AstNode::IdentRef(String::from("length"))),
+//!     line: 1,
+//! }];
+//!
+//! let error = check(&ast).unwrap_err();
+//! assert!(error.to_string().contains("Variable length does not exist."));
+//! ```
+
+use crate::logolang_errors::InterpreterError;
+use crate::parser::{AstNode, CompOp};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+/// Checks a program for errors the interpreter would raise when resolving variables and
+/// procedures, without evaluating it:
+/// - variables must be made before they are referenced
+/// - procedures must be defined, and called with as many arguments as they have parameters
+/// - variables must hold a value of the type each use requires, where it is known
+///
+/// Every statement is checked as though it runs, in program order, so a variable made within
+/// an IF body counts as made afterwards, and a variable made anywhere within a loop counts as
+/// made throughout its body. As procedures see the variables of their callers, a procedure body
+/// may refer to any variable made anywhere in the program. Variables preset by the embedder,
+/// with `Interpreter::preset_variable`, are not known to be made.
+/// Returns the first error found.
+///
+/// ```
+/// use logolang_lib::checker::check;
+/// use logolang_lib::parser::AstNode;
+/// use std::rc::Rc;
+///
+/// // TO Square "side ... END, then Square "10 "20
+/// let square = AstNode::Procedure {
+///     name: String::from("Square"),
+///     params: Rc::new(vec![String::from("side")]),
+///     body: Rc::new(vec![]),
+/// };
+/// let bind_side = |value| AstNode::MakeStmnt {
+///     var: String::from("side"),
+///     expr: Box::new(AstNode::Num(value)),
+///     line: 4,
+/// };
+/// let call = AstNode::ProcedureRef {
+///     name_ref: String::from("Square"),
+///     args: Rc::new(vec![bind_side(10.0), bind_side(20.0)]),
+///     line: 4,
+/// };
+///
+/// let error = check(&[square, call]).unwrap_err();
+/// assert!(error.to_string().contains("Procedure Square expects 1 argument(s), but received 2."));
+/// ```
+pub fn check(ast: &[AstNode]) -> Result<(), InterpreterError> {
+    let mut procedures = HashMap::new();
+    for node in ast {
+        if let AstNode::Procedure { name, params, .. } = node {
+            procedures.insert(name.clone(), params.len());
+        }
+    }
+
+    let mut checker = Checker {
+        procedures,
+        all_variables: bound_variables(ast),
+        variables: HashMap::new(),
+    };
+    checker.block(ast)
+}
+
+/// The type of a value, as bound to a variable
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValueType {
+    Number,
+    Bool,
+    Word,
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueType::Number => write!(f, "number"),
+            ValueType::Bool => write!(f, "boolean"),
+            ValueType::Word => write!(f, "word"),
+        }
+    }
+}
+
+/// Tracks the variables in scope while checking a block, as the interpreter would bind them
+struct Checker {
+    /// Number of parameters of each procedure defined at the top level
+    procedures: HashMap<String, usize>,
+    /// Every variable bound anywhere in the program, including procedure parameters
+    all_variables: Vec<String>,
+    /// Variables which may be bound, with their type if every binding so far agrees on it
+    variables: HashMap<String, Option<ValueType>>,
+}
+
+impl Checker {
+    /// Checks each statement of a block in order
+    fn block(&mut self, block: &[AstNode]) -> Result<(), InterpreterError> {
+        block.iter().try_for_each(|node| self.statement(node))
+    }
+
+    /// Checks the body of a loop, which may run after its own bindings have been made
+    fn loop_body(&mut self, body: &[AstNode]) -> Result<(), InterpreterError> {
+        for var in bound_variables(body) {
+            self.variables.entry(var).or_insert(None);
+        }
+        self.block(body)
+    }
+
+    /// Checks a single statement
+    fn statement(&mut self, node: &AstNode) -> Result<(), InterpreterError> {
+        let line = node.line().unwrap_or_default();
+        match node {
+            AstNode::MakeStmnt { var, expr, .. } => {
+                let value_type = self.expr(expr, None, line)?;
+                self.bind(var, value_type);
+            }
+            AstNode::AddAssign { var_name, expr, .. } => {
+                self.ident_ref(var_name, Some(ValueType::Number), line)?;
+                self.expr(expr, Some(ValueType::Number), line)?;
+            }
+            // An unbound word on its own binds a variable of the same name
+            AstNode::Word(word) => self.bind(word, Some(ValueType::Word)),
+            AstNode::IfStmnt {
+                condition, body, ..
+            } => {
+                self.expr(condition, Some(ValueType::Bool), line)?;
+                self.block(body)?;
+            }
+            AstNode::IfElseStmnt {
+                condition,
+                then_body,
+                else_body,
+                ..
+            } => {
+                self.expr(condition, Some(ValueType::Bool), line)?;
+                self.block(then_body)?;
+                self.block(else_body)?;
+            }
+            AstNode::WhileStmnt {
+                condition, body, ..
+            } => {
+                self.expr(condition, Some(ValueType::Bool), line)?;
+                self.loop_body(body)?;
+            }
+            AstNode::RepeatStmnt { count, body, .. } => {
+                self.expr(count, Some(ValueType::Number), line)?;
+                self.loop_body(body)?;
+            }
+            AstNode::ForStmnt {
+                var,
+                start,
+                end,
+                step,
+                body,
+                ..
+            } => {
+                for bound in [start, end, step] {
+                    self.expr(bound, Some(ValueType::Number), line)?;
+                }
+                self.bind(var, Some(ValueType::Number));
+                self.loop_body(body)?;
+            }
+            AstNode::ForEachStmnt {
+                var, values, body, ..
+            } => {
+                for value in values {
+                    self.expr(value, Some(ValueType::Number), line)?;
+                }
+                self.bind(var, Some(ValueType::Number));
+                self.loop_body(body)?;
+            }
+            AstNode::Procedure { params, body, .. } => {
+                // Any variable may have been bound by a caller, so only the types of variables
+                // made within the body are known
+                let callee_variables = self
+                    .all_variables
+                    .iter()
+                    .chain(params.iter())
+                    .map(|var| (var.clone(), None))
+                    .collect();
+                let caller_variables = std::mem::replace(&mut self.variables, callee_variables);
+                let result = self.block(body);
+                self.variables = caller_variables;
+                result?;
+            }
+            AstNode::Output { value, .. } => {
+                self.expr(value, None, line)?;
+            }
+            AstNode::Label { text, .. } => {
+                self.expr(text, Some(ValueType::Word), line)?;
+            }
+            AstNode::ResetAll { .. } => self.variables.clear(),
+            // Every other statement takes only numeric arguments, if any, and expressions may
+            // be evaluated as statements
+            _ => {
+                self.expr(node, None, line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks an expression, which must be of the expected type if one is given.
+    /// Returns the type of the expression, if it is known.
+    fn expr(
+        &mut self,
+        node: &AstNode,
+        expected: Option<ValueType>,
+        line: i32,
+    ) -> Result<Option<ValueType>, InterpreterError> {
+        let line = node.line().unwrap_or(line);
+        let value_type = match node {
+            AstNode::IdentRef(var) => return self.ident_ref(var, expected, line),
+            // The parser ensures the branches suit the expected type, unless they are variables
+            AstNode::IfVal {
+                condition,
+                then_val,
+                else_val,
+                ..
+            } => {
+                self.expr(condition, Some(ValueType::Bool), line)?;
+                let then_type = self.expr(then_val, expected, line)?;
+                let else_type = self.expr(else_val, expected, line)?;
+                then_type.filter(|_| then_type == else_type)
+            }
+            AstNode::ProcedureRef { name_ref, args, .. } => {
+                self.procedure_ref(name_ref, args, line)?;
+                // Procedures may OUTPUT a value of any type
+                None
+            }
+            AstNode::CompExpr {
+                operator,
+                left,
+                right,
+                ..
+            } => {
+                // Only LT and GT require numbers, as values of any types may be compared equal
+                let operand_type = match operator {
+                    CompOp::LT | CompOp::GT => Some(ValueType::Number),
+                    _ => None,
+                };
+                self.expr(left, operand_type, line)?;
+                self.expr(right, operand_type, line)?;
+                Some(ValueType::Bool)
+            }
+            AstNode::BoolExpr { left, right, .. } => {
+                self.expr(left, Some(ValueType::Bool), line)?;
+                self.expr(right, Some(ValueType::Bool), line)?;
+                Some(ValueType::Bool)
+            }
+            AstNode::NotExpr { operand, .. } => {
+                self.expr(operand, Some(ValueType::Bool), line)?;
+                Some(ValueType::Bool)
+            }
+            AstNode::BoolLiteral(_) => Some(ValueType::Bool),
+            AstNode::Word(_) => Some(ValueType::Word),
+            // Every other expression is numeric, taking only numeric operands, if any
+            // (as do the remaining statements)
+            _ => {
+                for operand in numeric_args(node) {
+                    self.expr(operand, Some(ValueType::Number), line)?;
+                }
+                Some(ValueType::Number)
+            }
+        };
+        Ok(value_type)
+    }
+
+    /// Checks a variable reference, returning the type of the variable if it is known
+    fn ident_ref(
+        &self,
+        var: &str,
+        expected: Option<ValueType>,
+        line: i32,
+    ) -> Result<Option<ValueType>, InterpreterError> {
+        let value_type = *self
+            .variables
+            .get(var)
+            .ok_or_else(|| InterpreterError::InvalidVariableRef(var.to_string()))
+            .with_context(|| format!("[Line {}]: Invalid reference to :{}.\n", line, var))?;
+
+        match (value_type, expected) {
+            (Some(actual), Some(expected)) if actual != expected => {
+                Err(InterpreterError::VariableTypeError(format!(
+                    "[Line {}]: variable '{}' holds a {} and cannot be used as a {} here",
+                    line, var, actual, expected
+                )))
+            }
+            _ => Ok(value_type),
+        }
+    }
+
+    /// Checks a procedure call is to a defined procedure, with valid arguments for each of its
+    /// parameters
+    fn procedure_ref(
+        &mut self,
+        name: &str,
+        args: &[AstNode],
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let Some(&num_params) = self.procedures.get(name) else {
+            return Err(InterpreterError::InvalidProcedureRef(format!(
+                "[Line {}]: Referenced Procedure {} does not exist.",
+                line, name
+            )));
+        };
+
+        if args.len() != num_params {
+            return Err(InterpreterError::InvalidProcedureRef(format!(
+                "[Line {}]: Procedure {} expects {} argument(s), but received {}.",
+                line,
+                name,
+                num_params,
+                args.len()
+            )));
+        }
+
+        // Arguments are evaluated in the caller's scope, and bound in the procedure's
+        for arg in args {
+            if let AstNode::MakeStmnt { expr, .. } = arg {
+                self.expr(expr, None, line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Helper fn: Records a binding of a variable, forgetting its type if it differs from
+    /// that of an earlier binding
+    fn bind(&mut self, var: &str, value_type: Option<ValueType>) {
+        self.variables
+            .entry(var.to_string())
+            .and_modify(|known| *known = known.filter(|&known| Some(known) == value_type))
+            .or_insert(value_type);
+    }
+}
+
+/// Returns the numeric arguments of a statement, or the numeric operands of an expression
+fn numeric_args(node: &AstNode) -> Vec<&AstNode> {
+    match node {
+        AstNode::ArithExpr { left, right, .. } => vec![left, right],
+        AstNode::TrigExpr { operand, .. }
+        | AstNode::RoundExpr { operand, .. }
+        | AstNode::Negate { operand, .. }
+        | AstNode::Abs { operand, .. }
+        | AstNode::Sqrt { operand, .. } => vec![operand],
+        AstNode::Random { max, .. } => vec![max],
+        AstNode::PenColorUpdate { color, .. } | AstNode::BackgroundUpdate { color, .. } => {
+            vec![color]
+        }
+        AstNode::PenRgbUpdate {
+            red, green, blue, ..
+        } => vec![red, green, blue],
+        AstNode::PenOpacityUpdate { opacity, .. } => vec![opacity],
+        AstNode::PenWidthUpdate { width, .. } => vec![width],
+        AstNode::PenDashUpdate { on, off, .. } => vec![on, off],
+        AstNode::PenPosUpdate { value, .. } => vec![value],
+        AstNode::DrawInstruction { num_pixels, .. } => vec![num_pixels],
+        AstNode::DrawAt { angle, length, .. } => vec![angle, length],
+        AstNode::ForwardDots {
+            length, spacing, ..
+        } => vec![length, spacing],
+        AstNode::Star { points, length, .. } => vec![points, length],
+        AstNode::CurveTo { x, y, .. } | AstNode::SetPosition { x, y, .. } => vec![x, y],
+        AstNode::Arc {
+            radius, degrees, ..
+        } => vec![radius, degrees],
+        _ => vec![],
+    }
+    .into_iter()
+    .map(|arg| &**arg)
+    .collect()
+}
+
+/// Returns the variables which may be bound by the statements of a block, including within
+/// nested blocks and procedure bodies, and the parameters of any procedures defined
+fn bound_variables(block: &[AstNode]) -> Vec<String> {
+    let mut variables = Vec::new();
+    for node in block {
+        match node {
+            AstNode::MakeStmnt { var, .. } | AstNode::Word(var) => variables.push(var.clone()),
+            AstNode::ForStmnt { var, body, .. } | AstNode::ForEachStmnt { var, body, .. } => {
+                variables.push(var.clone());
+                variables.extend(bound_variables(body));
+            }
+            AstNode::IfStmnt { body, .. }
+            | AstNode::WhileStmnt { body, .. }
+            | AstNode::RepeatStmnt { body, .. } => variables.extend(bound_variables(body)),
+            AstNode::IfElseStmnt {
+                then_body,
+                else_body,
+                ..
+            } => {
+                variables.extend(bound_variables(then_body));
+                variables.extend(bound_variables(else_body));
+            }
+            AstNode::Procedure { params, body, .. } => {
+                variables.extend(params.iter().cloned());
+                variables.extend(bound_variables(body));
+            }
+            _ => (),
+        }
+    }
+    variables
+}
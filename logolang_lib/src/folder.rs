@@ -0,0 +1,333 @@
+//! Module for folding constant expressions in a parsed RSLOGO AST before interpretation.
+//!
+//! Arithmetic, comparison and boolean expressions whose operands are all literals are replaced
+//! by the literal they evaluate to, so they are not re-evaluated on every iteration of a loop.
+//! Folding works from the innermost expressions outwards, so nested constant trees collapse to
+//! a single node. Expressions referring to variables, queries, procedures or RANDOM are never
+//! folded.
+//!
+//! Expressions which would raise an error when evaluated are left in place, so the error is
+//! still reported, with its line, when the program runs. Arithmetic is only folded when its
+//! result is a finite number: division by zero and other results which are not finite depend
+//! on the interpreter's overflow mode.
+//!
+//! # Examples
+//!
+//! ```
+//! use logolang_lib::folder::fold_constants;
+//! use logolang_lib::lexer::tokenize_str;
+//! use logolang_lib::parser::{AstNode, Parser};
+//! use std::collections::HashMap;
+//!
+//! let parse = |program: &str| {
+//!     let tokens = tokenize_str(program, &HashMap::new(), "//").unwrap();
+//!     fold_constants(Parser::new().parse(tokens).unwrap())
+//! };
+//!
+//! // A constant tree folds to a single number
+//! let ast = parse("FORWARD * \"2 * \"3 \"4\n");
+//! let AstNode::DrawInstruction { num_pixels, .. } = &ast[0] else {
+//!     panic!("Expected a draw instruction");
+//! };
+//! assert!(matches!(**num_pixels, AstNode::Num(24.0)));
+//!
+//! // Comparisons and boolean operators fold to a boolean literal
+//! let ast = parse("IF AND EQ \"1 \"1 LT \"2 \"1 [\n FORWARD \"1\n]\n");
+//! let AstNode::IfStmnt { condition, .. } = &ast[0] else {
+//!     panic!("Expected an if statement");
+//! };
+//! assert!(matches!(**condition, AstNode::BoolLiteral(false)));
+//!
+//! // Only the constant part of a tree referring to a variable is folded
+//! let ast = parse("MAKE \"x \"1\nFORWARD + :x * \"3 \"4\n");
+//! let AstNode::DrawInstruction { num_pixels, .. } = &ast[1] else {
+//!     panic!("Expected a draw instruction");
+//! };
+//! let AstNode::ArithExpr { left, right, .. } = &**num_pixels else {
+//!     panic!("Expected the addition to be left intact");
+//! };
+//! assert!(matches!(**left, AstNode::IdentRef(_)));
+//! assert!(matches!(**right, AstNode::Num(12.0)));
+//!
+//! // Division by zero is left to be reported when the program runs
+//! let ast = parse("FORWARD / \"1 - \"2 \"2\n");
+//! let AstNode::DrawInstruction { num_pixels, .. } = &ast[0] else {
+//!     panic!("Expected a draw instruction");
+//! };
+//! let AstNode::ArithExpr { right, .. } = &**num_pixels else {
+//!     panic!("Expected the division to be left intact");
+//! };
+//! assert!(matches!(**right, AstNode::Num(0.0)));
+//! ```
+
+use crate::interpreter::Value;
+use crate::parser::{AstNode, BoolOp, CompOp};
+use std::rc::Rc;
+
+/// Folds every constant expression throughout the program.
+pub fn fold_constants(mut ast: Vec<AstNode>) -> Vec<AstNode> {
+    fold_all(&mut ast);
+    ast
+}
+
+/// Folds the constant expressions within each node of a block
+fn fold_all(nodes: &mut [AstNode]) {
+    nodes.iter_mut().for_each(fold);
+}
+
+/// Folds the constant expressions within a node, then the node itself if it has become constant
+fn fold(node: &mut AstNode) {
+    match node {
+        AstNode::ArithExpr { left, right, .. }
+        | AstNode::CompExpr { left, right, .. }
+        | AstNode::BoolExpr { left, right, .. } => {
+            fold(left);
+            fold(right);
+        }
+        AstNode::MakeStmnt { expr, .. } | AstNode::AddAssign { expr, .. } => fold(expr),
+        AstNode::TrigExpr { operand, .. }
+        | AstNode::RoundExpr { operand, .. }
+        | AstNode::Negate { operand, .. }
+        | AstNode::NotExpr { operand, .. }
+        | AstNode::Abs { operand, .. }
+        | AstNode::Sqrt { operand, .. } => fold(operand),
+        AstNode::Random { max, .. } => fold(max),
+        AstNode::IfStmnt {
+            condition, body, ..
+        }
+        | AstNode::WhileStmnt {
+            condition, body, ..
+        } => {
+            fold(condition);
+            fold_all(body);
+        }
+        AstNode::IfElseStmnt {
+            condition,
+            then_body,
+            else_body,
+            ..
+        } => {
+            fold(condition);
+            fold_all(then_body);
+            fold_all(else_body);
+        }
+        AstNode::RepeatStmnt { count, body, .. } => {
+            fold(count);
+            fold_all(body);
+        }
+        AstNode::ForStmnt {
+            start,
+            end,
+            step,
+            body,
+            ..
+        } => {
+            fold(start);
+            fold(end);
+            fold(step);
+            fold_all(body);
+        }
+        AstNode::ForEachStmnt { values, body, .. } => {
+            fold_all(values);
+            fold_all(body);
+        }
+        AstNode::PenColorUpdate { color, .. } | AstNode::BackgroundUpdate { color, .. } => {
+            fold(color)
+        }
+        AstNode::PenRgbUpdate {
+            red, green, blue, ..
+        } => {
+            fold(red);
+            fold(green);
+            fold(blue);
+        }
+        AstNode::PenOpacityUpdate { opacity, .. } => fold(opacity),
+        AstNode::PenWidthUpdate { width, .. } => fold(width),
+        AstNode::PenDashUpdate { on, off, .. } => {
+            fold(on);
+            fold(off);
+        }
+        AstNode::PenPosUpdate { value, .. } | AstNode::Output { value, .. } => fold(value),
+        AstNode::Procedure { body, .. } => fold_all(Rc::make_mut(body).as_mut_slice()),
+        AstNode::ProcedureRef { args, .. } => fold_all(Rc::make_mut(args).as_mut_slice()),
+        AstNode::DrawInstruction { num_pixels, .. } => fold(num_pixels),
+        AstNode::DrawAt { angle, length, .. } => {
+            fold(angle);
+            fold(length);
+        }
+        AstNode::IfVal {
+            condition,
+            then_val,
+            else_val,
+            ..
+        } => {
+            fold(condition);
+            fold(then_val);
+            fold(else_val);
+        }
+        AstNode::ForwardDots {
+            length, spacing, ..
+        } => {
+            fold(length);
+            fold(spacing);
+        }
+        AstNode::Star { points, length, .. } => {
+            fold(points);
+            fold(length);
+        }
+        AstNode::CurveTo { x, y, .. } | AstNode::SetPosition { x, y, .. } => {
+            fold(x);
+            fold(y);
+        }
+        AstNode::Arc {
+            radius, degrees, ..
+        } => {
+            fold(radius);
+            fold(degrees);
+        }
+        AstNode::Label { text, .. } => fold(text),
+        AstNode::IdentRef(_)
+        | AstNode::Ident { .. }
+        | AstNode::Num(_)
+        | AstNode::BoolLiteral(_)
+        | AstNode::Word(_)
+        | AstNode::PenStatusUpdate(_)
+        | AstNode::TurtleVisibility(_)
+        | AstNode::AngleUnits(_)
+        | AstNode::SavePen { .. }
+        | AstNode::LoadPen { .. }
+        | AstNode::Mark { .. }
+        | AstNode::GoMark { .. }
+        | AstNode::Query(_)
+        | AstNode::Stop { .. }
+        | AstNode::ResetAll { .. }
+        | AstNode::Fill { .. }
+        | AstNode::Stamp { .. }
+        | AstNode::Home { .. }
+        | AstNode::ClearScreen { .. }
+        | AstNode::ShowPath { .. }
+        | AstNode::Axes { .. } => (),
+    }
+
+    if let Some(literal) = constant_value(node) {
+        *node = literal;
+    }
+}
+
+/// Returns the literal a node evaluates to, if its operands are literals and evaluating it
+/// cannot raise an error
+fn constant_value(node: &AstNode) -> Option<AstNode> {
+    match node {
+        AstNode::ArithExpr {
+            operator,
+            left,
+            right,
+            ..
+        } => match (&**left, &**right) {
+            (AstNode::Num(left), AstNode::Num(right)) => {
+                let result = operator.apply(*left, *right);
+                result.is_finite().then_some(AstNode::Num(result))
+            }
+            _ => None,
+        },
+        AstNode::CompExpr {
+            operator,
+            left,
+            right,
+            ..
+        } => {
+            let (left, right) = (literal_value(left)?, literal_value(right)?);
+            let result = match (operator, &left, &right) {
+                (CompOp::EQ, ..) => left == right,
+                (CompOp::NE, ..) => left != right,
+                // Only numbers can be ordered
+                (CompOp::LT, Value::Float(left), Value::Float(right)) => left < right,
+                (CompOp::GT, Value::Float(left), Value::Float(right)) => left > right,
+                _ => return None,
+            };
+            Some(AstNode::BoolLiteral(result))
+        }
+        AstNode::BoolExpr {
+            operator,
+            left,
+            right,
+            ..
+        } => match (&**left, &**right) {
+            (AstNode::BoolLiteral(left), AstNode::BoolLiteral(right)) => {
+                Some(AstNode::BoolLiteral(match operator {
+                    BoolOp::AND => *left && *right,
+                    BoolOp::OR => *left || *right,
+                }))
+            }
+            _ => None,
+        },
+        AstNode::NotExpr { operand, .. } => match &**operand {
+            AstNode::BoolLiteral(value) => Some(AstNode::BoolLiteral(!value)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the value of a literal node
+fn literal_value(node: &AstNode) -> Option<Value> {
+    match node {
+        AstNode::Num(num) => Some(Value::Float(*num)),
+        AstNode::BoolLiteral(value) => Some(Value::Bool(*value)),
+        AstNode::Word(word) => Some(Value::Word(word.clone())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gcode::Segment;
+    use crate::interpreter::Interpreter;
+    use crate::lexer::tokenize_str;
+    use crate::parser::Parser;
+    use std::collections::HashMap;
+    use std::fs;
+    use unsvg::Image;
+
+    /// Returns the PNG bytes and drawn segments of a program run on a 200x200 image with a
+    /// fixed seed, or its error message
+    fn render(ast: &Vec<AstNode>) -> Result<(Vec<u8>, Vec<Segment>), String> {
+        let mut image = Image::new(200, 200);
+        let mut interpreter = Interpreter::with_seed(&mut image, 7);
+        interpreter.run(ast).map_err(|error| {
+            // Backtraces differ between the two runs, so only messages are compared
+            let message = error.to_string();
+            match message.split_once("Stack backtrace:") {
+                Some((message, _)) => message.to_string(),
+                None => message,
+            }
+        })?;
+        Ok((
+            interpreter.raster_png_bytes(),
+            interpreter.drawn_segments().to_vec(),
+        ))
+    }
+
+    #[test]
+    fn folding_leaves_every_example_unchanged() {
+        let examples = concat!(env!("CARGO_MANIFEST_DIR"), "/../logo_examples");
+        let mut checked = 0;
+        for entry in fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            let program = fs::read_to_string(&path).unwrap();
+            // Examples needing command line options, or failing to parse, have nothing to fold
+            let Ok(tokens) = tokenize_str(&program, &HashMap::new(), "//") else {
+                continue;
+            };
+            let Ok(ast) = Parser::new().parse(tokens) else {
+                continue;
+            };
+
+            let folded = fold_constants(ast.clone());
+            assert_eq!(render(&folded), render(&ast), "{}", path.display());
+            checked += 1;
+        }
+        assert!(checked > 100, "only {} examples were checked", checked);
+    }
+}
@@ -0,0 +1,71 @@
+//! Module for translating drawn lines into G-code for pen plotters and CNC machines.
+//!
+//! Lines are taken from `Interpreter::drawn_segments`. Each line is drawn with a G1 move with
+//! the pen lowered, and the pen is raised for a G0 travel move whenever a line does not start
+//! where the previous one ended. The pen is raised and lowered with Z moves.
+//!
+//! Image coordinates have their origin at the top left with y increasing downwards, while
+//! plotters have their origin at the bottom left, so y coordinates are flipped using the
+//! image height before scaling to millimetres.
+//!
+//! # Examples
+//!
+//! ```
+//! use logolang_lib::gcode::to_gcode;
+//!
+//! let square = [
+//!     ((0.0, 0.0), (10.0, 0.0)),
+//!     ((10.0, 0.0), (10.0, 10.0)),
+//!     ((10.0, 10.0), (0.0, 10.0)),
+//!     ((0.0, 10.0), (0.0, 0.0)),
+//! ];
+//!
+//! let gcode = to_gcode(&square, 10.0, 1.0);
+//! assert_eq!(gcode.lines().filter(|l| l.starts_with("G1 X")).count(), 4);
+//! ```
+
+/// A straight line from a start point to an end point, in image coordinates
+pub type Segment = ((f32, f32), (f32, f32));
+
+/// Height of the pen above the paper while travelling, in millimetres
+const PEN_UP_Z: f32 = 5.0;
+/// Height of the pen while drawing, in millimetres
+const PEN_DOWN_Z: f32 = 0.0;
+/// Largest gap between the end of one line and the start of the next which is still drawn
+/// without lifting the pen, in image pixels
+const JOIN_TOLERANCE: f32 = 0.01;
+
+/// Translates drawn lines into a G-code program. `height` is the height of the image the lines
+/// were drawn on, and `mm_per_pixel` the size of an image pixel on the plotter.
+pub fn to_gcode(segments: &[Segment], height: f32, mm_per_pixel: f32) -> String {
+    let to_mm = |(x, y): (f32, f32)| (x * mm_per_pixel, (height - y) * mm_per_pixel);
+
+    // Millimetres, absolute positioning, pen raised
+    let mut gcode = String::from("G21\nG90\n");
+    gcode.push_str(&format!("G0 Z{:.3}\n", PEN_UP_Z));
+
+    let mut position: Option<(f32, f32)> = None;
+    for &(start, end) in segments {
+        let joined = position.is_some_and(|(x, y)| {
+            (x - start.0).abs() <= JOIN_TOLERANCE && (y - start.1).abs() <= JOIN_TOLERANCE
+        });
+
+        if !joined {
+            if position.is_some() {
+                gcode.push_str(&format!("G0 Z{:.3}\n", PEN_UP_Z));
+            }
+            let (x, y) = to_mm(start);
+            gcode.push_str(&format!("G0 X{:.3} Y{:.3}\n", x, y));
+            gcode.push_str(&format!("G1 Z{:.3}\n", PEN_DOWN_Z));
+        }
+
+        let (x, y) = to_mm(end);
+        gcode.push_str(&format!("G1 X{:.3} Y{:.3}\n", x, y));
+        position = Some(end);
+    }
+
+    // Raise the pen and return to the origin
+    gcode.push_str(&format!("G0 Z{:.3}\n", PEN_UP_Z));
+    gcode.push_str("G0 X0 Y0\n");
+    gcode
+}
@@ -0,0 +1,77 @@
+//! Module providing the stroke font behind LABEL.
+//!
+//! unsvg can only draw straight lines, so each character is described as a set of strokes
+//! (polylines) on a small grid. Points are given as (column, row) with the origin at the top
+//! left of the character cell, columns running 0..=GLYPH_WIDTH and rows 0..=GLYPH_HEIGHT.
+
+/// Width of a character cell, in grid units
+pub(crate) const GLYPH_WIDTH: u8 = 4;
+/// Height of a character cell, in grid units
+pub(crate) const GLYPH_HEIGHT: u8 = 6;
+/// Horizontal distance between the starts of neighbouring characters, in grid units
+pub(crate) const GLYPH_ADVANCE: u8 = GLYPH_WIDTH + 2;
+
+/// A polyline through points on the character grid
+pub(crate) type Stroke = &'static [(u8, u8)];
+
+/// Returns the strokes which draw a character, or None if the font has no glyph for it.
+/// Letters are drawn in upper case regardless of the case they are given in.
+pub(crate) fn glyph(character: char) -> Option<&'static [Stroke]> {
+    let strokes: &'static [Stroke] = match character.to_ascii_uppercase() {
+        'A' => &[&[(0, 6), (0, 2), (2, 0), (4, 2), (4, 6)], &[(0, 3), (4, 3)]],
+        'B' => &[
+            &[(0, 0), (0, 6), (3, 6), (4, 5), (4, 4), (3, 3), (0, 3)],
+            &[(0, 0), (3, 0), (4, 1), (4, 2), (3, 3)],
+        ],
+        'C' => &[&[(4, 0), (0, 0), (0, 6), (4, 6)]],
+        'D' => &[&[(0, 0), (0, 6), (2, 6), (4, 4), (4, 2), (2, 0), (0, 0)]],
+        'E' => &[&[(4, 0), (0, 0), (0, 6), (4, 6)], &[(0, 3), (3, 3)]],
+        'F' => &[&[(4, 0), (0, 0), (0, 6)], &[(0, 3), (3, 3)]],
+        'G' => &[&[(4, 1), (4, 0), (0, 0), (0, 6), (4, 6), (4, 3), (2, 3)]],
+        'H' => &[&[(0, 0), (0, 6)], &[(4, 0), (4, 6)], &[(0, 3), (4, 3)]],
+        'I' => &[&[(0, 0), (4, 0)], &[(2, 0), (2, 6)], &[(0, 6), (4, 6)]],
+        'J' => &[&[(4, 0), (4, 6), (0, 6), (0, 4)]],
+        'K' => &[&[(0, 0), (0, 6)], &[(4, 0), (0, 3), (4, 6)]],
+        'L' => &[&[(0, 0), (0, 6), (4, 6)]],
+        'M' => &[&[(0, 6), (0, 0), (2, 3), (4, 0), (4, 6)]],
+        'N' => &[&[(0, 6), (0, 0), (4, 6), (4, 0)]],
+        'O' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)]],
+        'P' => &[&[(0, 6), (0, 0), (4, 0), (4, 3), (0, 3)]],
+        'Q' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)], &[(2, 4), (4, 6)]],
+        'R' => &[&[(0, 6), (0, 0), (4, 0), (4, 3), (0, 3), (4, 6)]],
+        'S' => &[&[
+            (4, 1),
+            (3, 0),
+            (1, 0),
+            (0, 1),
+            (0, 2),
+            (1, 3),
+            (3, 3),
+            (4, 4),
+            (4, 5),
+            (3, 6),
+            (1, 6),
+            (0, 5),
+        ]],
+        'T' => &[&[(0, 0), (4, 0)], &[(2, 0), (2, 6)]],
+        'U' => &[&[(0, 0), (0, 6), (4, 6), (4, 0)]],
+        'V' => &[&[(0, 0), (2, 6), (4, 0)]],
+        'W' => &[&[(0, 0), (1, 6), (2, 3), (3, 6), (4, 0)]],
+        'X' => &[&[(0, 0), (4, 6)], &[(4, 0), (0, 6)]],
+        'Y' => &[&[(0, 0), (2, 3), (4, 0)], &[(2, 3), (2, 6)]],
+        'Z' => &[&[(0, 0), (4, 0), (0, 6), (4, 6)]],
+        // Zero is slashed to tell it apart from O
+        '0' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)], &[(0, 6), (4, 0)]],
+        '1' => &[&[(1, 1), (2, 0), (2, 6)], &[(0, 6), (4, 6)]],
+        '2' => &[&[(0, 0), (4, 0), (4, 3), (0, 3), (0, 6), (4, 6)]],
+        '3' => &[&[(0, 0), (4, 0), (4, 6), (0, 6)], &[(0, 3), (4, 3)]],
+        '4' => &[&[(0, 0), (0, 3), (4, 3)], &[(4, 0), (4, 6)]],
+        '5' => &[&[(4, 0), (0, 0), (0, 3), (4, 3), (4, 6), (0, 6)]],
+        '6' => &[&[(4, 0), (0, 0), (0, 6), (4, 6), (4, 3), (0, 3)]],
+        '7' => &[&[(0, 0), (4, 0), (1, 6)]],
+        '8' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)], &[(0, 3), (4, 3)]],
+        '9' => &[&[(4, 3), (0, 3), (0, 0), (4, 0), (4, 6), (0, 6)]],
+        _ => return None,
+    };
+    Some(strokes)
+}
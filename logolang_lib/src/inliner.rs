@@ -0,0 +1,679 @@
+//! Module for flattening procedure calls in a parsed RSLOGO AST before interpretation.
+//!
+//! Each call to a procedure defined at the top level of the program is replaced by the
+//! argument bindings and body of that procedure, removing the lookup and call overhead of
+//! `eval_procedure` in tight loops. The procedure definitions themselves are kept, so
+//! procedures which are not inlined still resolve as usual. Programs which use RESETALL are
+//! left unchanged, as it removes procedure definitions while the program runs, and procedures
+//! which use STOP or OUTPUT are not inlined, as it would then end the caller rather than the
+//! procedure. Calls used as expressions are never inlined.
+//!
+//! Each call evaluates its procedure's body in a new scope, so parameters and variables bound
+//! by the body are discarded when the call returns. When a procedure is inlined, its
+//! parameters and the variables its body binds are renamed uniquely for that call, so they
+//! neither overwrite the caller's variables of the same name nor are read in their place.
+//! Procedures are not inlined if their body might read one of its variables before binding it,
+//! as the call would then read the caller's variable, or if it still makes calls once inlined,
+//! as the procedures called would read the body's variables by their original names.
+//!
+//! # Examples
+//!
+//! ```
+//! use logolang_lib::inliner::inline_procedures;
+//! use logolang_lib::parser::AstNode;
+//! use std::rc::Rc;
+//!
+//! let ast = vec![
+//!     AstNode::Procedure {
+//!         name: String::from("DOT"),
+//!         params: Rc::new(vec![]),
+//!         body: Rc::new(vec![AstNode::PenStatusUpdate(true)]),
+//!     },
+//!     AstNode::ProcedureRef {
+//!         name_ref: String::from("DOT"),
+//!         args: Rc::new(vec![]),
+//!         line: 4,
+//!     },
+//! ];
+//!
+//! let inlined = inline_procedures(ast);
+//! assert!(matches!(inlined[1], AstNode::PenStatusUpdate(true)));
+//! ```
+
+use crate::parser::AstNode;
+use crate::visitor::{walk, walk_all, Visitor};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Inlines calls to non-recursive procedures throughout the program.
+pub fn inline_procedures(ast: Vec<AstNode>) -> Vec<AstNode> {
+    let mut reset_finder = ResetFinder(false);
+    walk_all(&ast, &mut reset_finder);
+    if reset_finder.0 {
+        return ast;
+    }
+
+    let mut inliner = Inliner {
+        procedures: HashMap::new(),
+        expanding: HashSet::new(),
+        renamed_calls: 0,
+    };
+    inliner.inline_block(ast, true)
+}
+
+/// Parameters and body of a procedure
+type Definition = (Rc<Vec<String>>, Rc<Vec<AstNode>>);
+
+/// Tracks the procedures which may be inlined at the current point in the program
+struct Inliner {
+    /// Procedures defined at the top level so far
+    procedures: HashMap<String, Definition>,
+    /// Procedures currently being expanded, used to leave recursive calls in place
+    expanding: HashSet<String>,
+    /// Number of calls inlined with renamed variables, used to keep each call's names unique
+    renamed_calls: usize,
+}
+
+/// Separates a renamed variable's original name from the number of the call it belongs to.
+/// Variable names in programs are alphanumeric, so renamed variables cannot clash with them.
+const RENAME_SEPARATOR: char = '@';
+
+impl Inliner {
+    /// Inlines calls within a block of statements, in program order
+    fn inline_block(&mut self, block: Vec<AstNode>, top_level: bool) -> Vec<AstNode> {
+        let mut inlined = Vec::with_capacity(block.len());
+
+        for node in block {
+            match node {
+                AstNode::Procedure { name, params, body } => {
+                    // Procedures defined inside a block may never be evaluated, so only
+                    // top level definitions are known to exist when later calls are made
+                    let mut stop_finder = StopFinder(false);
+                    walk_all(&body, &mut stop_finder);
+
+                    if top_level && !stop_finder.0 {
+                        let procedure = (Rc::clone(&params), Rc::clone(&body));
+                        self.procedures.insert(name.clone(), procedure);
+                    } else {
+                        self.procedures.remove(&name);
+                    }
+                    inlined.push(AstNode::Procedure { name, params, body });
+                }
+                AstNode::ProcedureRef {
+                    name_ref,
+                    args,
+                    line,
+                } => match self.inline_call(&name_ref, &args, line) {
+                    Some(statements) => inlined.extend(statements),
+                    None => inlined.push(AstNode::ProcedureRef {
+                        name_ref,
+                        args,
+                        line,
+                    }),
+                },
+                AstNode::IfStmnt {
+                    condition,
+                    body,
+                    line,
+                } => inlined.push(AstNode::IfStmnt {
+                    condition,
+                    body: Box::new(self.inline_block(*body, false)),
+                    line,
+                }),
+                AstNode::IfElseStmnt {
+                    condition,
+                    then_body,
+                    else_body,
+                    line,
+                } => inlined.push(AstNode::IfElseStmnt {
+                    condition,
+                    then_body: Box::new(self.inline_block(*then_body, false)),
+                    else_body: Box::new(self.inline_block(*else_body, false)),
+                    line,
+                }),
+                AstNode::WhileStmnt {
+                    condition,
+                    body,
+                    line,
+                } => inlined.push(AstNode::WhileStmnt {
+                    condition,
+                    body: Box::new(self.inline_block(*body, false)),
+                    line,
+                }),
+                AstNode::RepeatStmnt { count, body, line } => inlined.push(AstNode::RepeatStmnt {
+                    count,
+                    body: Box::new(self.inline_block(*body, false)),
+                    line,
+                }),
+                AstNode::ForStmnt {
+                    var,
+                    start,
+                    end,
+                    step,
+                    body,
+                    line,
+                } => inlined.push(AstNode::ForStmnt {
+                    var,
+                    start,
+                    end,
+                    step,
+                    body: Box::new(self.inline_block(*body, false)),
+                    line,
+                }),
+                AstNode::ForEachStmnt {
+                    var,
+                    values,
+                    body,
+                    line,
+                } => inlined.push(AstNode::ForEachStmnt {
+                    var,
+                    values,
+                    body: Box::new(self.inline_block(*body, false)),
+                    line,
+                }),
+                other => inlined.push(other),
+            }
+        }
+
+        inlined
+    }
+
+    /// Returns the statements replacing a call to a procedure: the bindings of its arguments
+    /// followed by its inlined body, with their variables renamed for this call.
+    /// Returns None if the call must be left in place.
+    fn inline_call(&mut self, name: &str, args: &[AstNode], line: i32) -> Option<Vec<AstNode>> {
+        let (params, body) = self.procedures.get(name)?;
+        if self.expanding.contains(name) || params.len() != args.len() {
+            return None;
+        }
+        let (params, body) = (Rc::clone(params), Rc::clone(body));
+
+        self.expanding.insert(name.to_string());
+        let mut body = self.inline_block(body.to_vec(), false);
+        self.expanding.remove(name);
+
+        // Variables of calls inlined into the body have already been renamed
+        let mut binding_finder = BindingFinder(params.iter().cloned().collect());
+        walk_all(&body, &mut binding_finder);
+        let locals: HashSet<String> = binding_finder
+            .0
+            .into_iter()
+            .filter(|var| !var.contains(RENAME_SEPARATOR))
+            .collect();
+        if locals.is_empty() {
+            return Some(body);
+        }
+        if !binds_before_use(&body, &locals, &mut params.iter().cloned().collect()) {
+            return None;
+        }
+
+        self.renamed_calls += 1;
+        let renamer = Renamer {
+            names: locals
+                .into_iter()
+                .map(|var| {
+                    let renamed = format!("{}{}{}", var, RENAME_SEPARATOR, self.renamed_calls);
+                    (var, renamed)
+                })
+                .collect(),
+            line,
+        };
+        // Arguments are evaluated in the caller's scope, so only their parameters are renamed
+        let mut statements = args.to_vec();
+        for arg in &mut statements {
+            if let AstNode::MakeStmnt { var, .. } = arg {
+                renamer.rename_var(var);
+            }
+        }
+        renamer.rename_block(&mut body);
+        statements.extend(body);
+        Some(statements)
+    }
+}
+
+/// Returns whether each of a procedure's local variables read by a block is certain to have
+/// been bound by the procedure beforehand, given the variables bound so far. Bindings made
+/// inside loops and conditionals may not happen, so they only count within those blocks.
+/// Blocks making calls are rejected, as the procedures called read variables by name.
+fn binds_before_use(
+    block: &[AstNode],
+    locals: &HashSet<String>,
+    bound: &mut HashSet<String>,
+) -> bool {
+    let reads_bound = |node: &AstNode, bound: &HashSet<String>| {
+        let mut read_finder = ReadFinder::default();
+        walk(node, &mut read_finder);
+        !read_finder.calls
+            && (read_finder.reads.iter()).all(|var| !locals.contains(var) || bound.contains(var))
+    };
+
+    for node in block {
+        let is_bound = match node {
+            AstNode::MakeStmnt { var, expr, .. } => {
+                let is_bound = reads_bound(expr, bound);
+                bound.insert(var.clone());
+                is_bound
+            }
+            AstNode::AddAssign { var_name, expr, .. } => {
+                let is_bound = reads_bound(expr, bound)
+                    && (!locals.contains(var_name) || bound.contains(var_name));
+                bound.insert(var_name.clone());
+                is_bound
+            }
+            // Words given as statements are bound to themselves
+            AstNode::Word(word) => {
+                bound.insert(word.clone());
+                true
+            }
+            AstNode::IfStmnt {
+                condition, body, ..
+            }
+            | AstNode::WhileStmnt {
+                condition, body, ..
+            } => {
+                reads_bound(condition, bound) && binds_before_use(body, locals, &mut bound.clone())
+            }
+            AstNode::IfElseStmnt {
+                condition,
+                then_body,
+                else_body,
+                ..
+            } => {
+                reads_bound(condition, bound)
+                    && binds_before_use(then_body, locals, &mut bound.clone())
+                    && binds_before_use(else_body, locals, &mut bound.clone())
+            }
+            AstNode::RepeatStmnt { count, body, .. } => {
+                reads_bound(count, bound) && binds_before_use(body, locals, &mut bound.clone())
+            }
+            AstNode::ForStmnt {
+                var,
+                start,
+                end,
+                step,
+                body,
+                ..
+            } => {
+                let mut body_bound = bound.clone();
+                body_bound.insert(var.clone());
+                [start, end, step]
+                    .iter()
+                    .all(|value| reads_bound(value, bound))
+                    && binds_before_use(body, locals, &mut body_bound)
+            }
+            AstNode::ForEachStmnt {
+                var, values, body, ..
+            } => {
+                let mut body_bound = bound.clone();
+                body_bound.insert(var.clone());
+                values.iter().all(|value| reads_bound(value, bound))
+                    && binds_before_use(body, locals, &mut body_bound)
+            }
+            // Procedures defined by the body only run once called
+            AstNode::Procedure { .. } => true,
+            other => reads_bound(other, bound),
+        };
+        if !is_bound {
+            return false;
+        }
+    }
+    true
+}
+
+/// Renames the variables of an inlined call
+struct Renamer {
+    /// New name of each of the procedure's variables
+    names: HashMap<String, String>,
+    /// Line of the call
+    line: i32,
+}
+
+impl Renamer {
+    /// Renames the variables throughout a block of statements
+    fn rename_block(&self, block: &mut [AstNode]) {
+        for node in block {
+            match node {
+                // A word given as a statement is bound to itself, so keeps its value
+                AstNode::Word(word) if self.names.contains_key(word) => {
+                    *node = AstNode::MakeStmnt {
+                        var: self.names[word].clone(),
+                        expr: Box::new(AstNode::Word(word.clone())),
+                        line: self.line,
+                    };
+                }
+                _ => self.rename(node),
+            }
+        }
+    }
+
+    /// Renames the variables bound or read within a node
+    fn rename(&self, node: &mut AstNode) {
+        match node {
+            AstNode::IdentRef(var) => self.rename_var(var),
+            AstNode::MakeStmnt { var, expr, .. }
+            | AstNode::AddAssign {
+                var_name: var,
+                expr,
+                ..
+            } => {
+                self.rename_var(var);
+                self.rename(expr);
+            }
+            AstNode::ArithExpr { left, right, .. }
+            | AstNode::CompExpr { left, right, .. }
+            | AstNode::BoolExpr { left, right, .. } => {
+                self.rename(left);
+                self.rename(right);
+            }
+            AstNode::TrigExpr { operand, .. }
+            | AstNode::RoundExpr { operand, .. }
+            | AstNode::Negate { operand, .. }
+            | AstNode::NotExpr { operand, .. }
+            | AstNode::Abs { operand, .. }
+            | AstNode::Sqrt { operand, .. } => self.rename(operand),
+            AstNode::Random { max, .. } => self.rename(max),
+            AstNode::IfStmnt {
+                condition, body, ..
+            }
+            | AstNode::WhileStmnt {
+                condition, body, ..
+            } => {
+                self.rename(condition);
+                self.rename_block(body);
+            }
+            AstNode::IfElseStmnt {
+                condition,
+                then_body,
+                else_body,
+                ..
+            } => {
+                self.rename(condition);
+                self.rename_block(then_body);
+                self.rename_block(else_body);
+            }
+            AstNode::RepeatStmnt { count, body, .. } => {
+                self.rename(count);
+                self.rename_block(body);
+            }
+            AstNode::ForStmnt {
+                var,
+                start,
+                end,
+                step,
+                body,
+                ..
+            } => {
+                self.rename_var(var);
+                self.rename(start);
+                self.rename(end);
+                self.rename(step);
+                self.rename_block(body);
+            }
+            AstNode::ForEachStmnt {
+                var, values, body, ..
+            } => {
+                self.rename_var(var);
+                values.iter_mut().for_each(|value| self.rename(value));
+                self.rename_block(body);
+            }
+            AstNode::PenColorUpdate { color, .. } | AstNode::BackgroundUpdate { color, .. } => {
+                self.rename(color)
+            }
+            AstNode::PenRgbUpdate {
+                red, green, blue, ..
+            } => {
+                self.rename(red);
+                self.rename(green);
+                self.rename(blue);
+            }
+            AstNode::PenOpacityUpdate { opacity, .. } => self.rename(opacity),
+            AstNode::PenWidthUpdate { width, .. } => self.rename(width),
+            AstNode::PenDashUpdate { on, off, .. } => {
+                self.rename(on);
+                self.rename(off);
+            }
+            AstNode::PenPosUpdate { value, .. } | AstNode::Output { value, .. } => {
+                self.rename(value)
+            }
+            AstNode::DrawInstruction { num_pixels, .. } => self.rename(num_pixels),
+            AstNode::DrawAt { angle, length, .. } => {
+                self.rename(angle);
+                self.rename(length);
+            }
+            AstNode::IfVal {
+                condition,
+                then_val,
+                else_val,
+                ..
+            } => {
+                self.rename(condition);
+                self.rename(then_val);
+                self.rename(else_val);
+            }
+            AstNode::ForwardDots {
+                length, spacing, ..
+            } => {
+                self.rename(length);
+                self.rename(spacing);
+            }
+            AstNode::Star { points, length, .. } => {
+                self.rename(points);
+                self.rename(length);
+            }
+            AstNode::CurveTo { x, y, .. } | AstNode::SetPosition { x, y, .. } => {
+                self.rename(x);
+                self.rename(y);
+            }
+            AstNode::Arc {
+                radius, degrees, ..
+            } => {
+                self.rename(radius);
+                self.rename(degrees);
+            }
+            AstNode::Label { text, .. } => self.rename(text),
+            // Procedures defined by the body read variables by name once called, and renamed
+            // bodies make no calls
+            AstNode::Procedure { .. }
+            | AstNode::ProcedureRef { .. }
+            | AstNode::Ident { .. }
+            | AstNode::Num(_)
+            | AstNode::BoolLiteral(_)
+            | AstNode::Word(_)
+            | AstNode::PenStatusUpdate(_)
+            | AstNode::TurtleVisibility(_)
+            | AstNode::AngleUnits(_)
+            | AstNode::SavePen { .. }
+            | AstNode::LoadPen { .. }
+            | AstNode::Mark { .. }
+            | AstNode::GoMark { .. }
+            | AstNode::Query(_)
+            | AstNode::Stop { .. }
+            | AstNode::ResetAll { .. }
+            | AstNode::Fill { .. }
+            | AstNode::Stamp { .. }
+            | AstNode::Home { .. }
+            | AstNode::ClearScreen { .. }
+            | AstNode::ShowPath { .. }
+            | AstNode::Axes { .. } => {}
+        }
+    }
+
+    /// Renames a variable if it belongs to the procedure
+    fn rename_var(&self, var: &mut String) {
+        if let Some(renamed) = self.names.get(var) {
+            *var = renamed.clone();
+        }
+    }
+}
+
+/// Records whether a STOP or OUTPUT appears anywhere in a procedure body
+struct StopFinder(bool);
+
+impl Visitor for StopFinder {
+    fn visit_stop(&mut self, _node: &AstNode) {
+        self.0 = true;
+    }
+
+    fn visit_output(&mut self, _node: &AstNode) {
+        self.0 = true;
+    }
+}
+
+/// Collects the variables bound anywhere in a procedure body, which belong to the procedure's
+/// scope rather than the caller's
+struct BindingFinder(HashSet<String>);
+
+impl Visitor for BindingFinder {
+    fn visit_make_stmnt(&mut self, node: &AstNode) {
+        if let AstNode::MakeStmnt { var, .. } = node {
+            self.0.insert(var.clone());
+        }
+    }
+
+    fn visit_add_assign(&mut self, node: &AstNode) {
+        if let AstNode::AddAssign { var_name, .. } = node {
+            self.0.insert(var_name.clone());
+        }
+    }
+
+    fn visit_for_stmnt(&mut self, node: &AstNode) {
+        if let AstNode::ForStmnt { var, .. } = node {
+            self.0.insert(var.clone());
+        }
+    }
+
+    fn visit_for_each_stmnt(&mut self, node: &AstNode) {
+        if let AstNode::ForEachStmnt { var, .. } = node {
+            self.0.insert(var.clone());
+        }
+    }
+
+    // Words given as statements bind themselves. Words used as values are collected too,
+    // which only makes inlining more cautious.
+    fn visit_word(&mut self, node: &AstNode) {
+        if let AstNode::Word(word) = node {
+            self.0.insert(word.clone());
+        }
+    }
+}
+
+/// Collects the variables read by a node, and records whether it calls a procedure
+#[derive(Default)]
+struct ReadFinder {
+    reads: Vec<String>,
+    calls: bool,
+}
+
+impl Visitor for ReadFinder {
+    fn visit_ident_ref(&mut self, node: &AstNode) {
+        if let AstNode::IdentRef(var) = node {
+            self.reads.push(var.clone());
+        }
+    }
+
+    fn visit_procedure_ref(&mut self, _node: &AstNode) {
+        self.calls = true;
+    }
+}
+
+/// Records whether a RESETALL appears anywhere in the program
+struct ResetFinder(bool);
+
+impl Visitor for ResetFinder {
+    fn visit_reset_all(&mut self, _node: &AstNode) {
+        self.0 = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gcode::Segment;
+    use crate::interpreter::Interpreter;
+    use crate::lexer::tokenize_str;
+    use crate::parser::Parser;
+    use unsvg::Image;
+
+    /// Lexes and parses a program as rslogo does by default
+    fn parse(program: &str) -> Vec<AstNode> {
+        let tokens = tokenize_str(program, &HashMap::new(), "//").unwrap();
+        Parser::new().parse(tokens).unwrap()
+    }
+
+    /// Counts the calls throughout a program, including those in procedure definitions
+    struct CallCounter(usize);
+
+    impl Visitor for CallCounter {
+        fn visit_procedure_ref(&mut self, _node: &AstNode) {
+            self.0 += 1;
+        }
+    }
+
+    /// Returns the number of calls throughout a program
+    fn calls(ast: &[AstNode]) -> usize {
+        let mut counter = CallCounter(0);
+        walk_all(ast, &mut counter);
+        counter.0
+    }
+
+    /// Returns the PNG bytes and drawn segments of a program run on a 200x200 image
+    fn render(ast: &Vec<AstNode>) -> (Vec<u8>, Vec<Segment>) {
+        let mut image = Image::new(200, 200);
+        let mut interpreter = Interpreter::new(&mut image);
+        interpreter.run(ast).unwrap();
+        (
+            interpreter.raster_png_bytes(),
+            interpreter.drawn_segments().to_vec(),
+        )
+    }
+
+    #[test]
+    fn inlined_programs_render_identically() {
+        let program = "MAKE \"size \"3\n\
+                       TO Side \"size \"turn\nMAKE \"half / :size \"2\nFORWARD :half\n\
+                       FORWARD :half\nTURN :turn\nEND\n\
+                       TO Square \"size\nREPEAT \"4 [\nSide :size \"90\n]\nEND\n\
+                       TO Spiral \"size\nIF LT :size \"60 [\nSquare :size\nTURN \"15\n\
+                       Spiral + :size \"6\n]\nEND\n\
+                       PENDOWN\nSpiral \"10\nFOR \"i \"1 \"3 \"1 [\nSide * :i \"10 \"120\n]\n\
+                       FORWARD :size\n";
+        let ast = parse(program);
+        let inlined = inline_procedures(ast.clone());
+        assert!(calls(&inlined) < calls(&ast));
+
+        let (bytes, segments) = render(&ast);
+        assert!(!segments.is_empty());
+        assert_eq!(render(&inlined), (bytes, segments));
+    }
+
+    #[test]
+    fn parameters_and_locals_are_renamed_for_each_call() {
+        let program =
+            "MAKE \"size \"5\nTO Side \"size\nMAKE \"half / :size \"2\nFORWARD :half\nEND\n\
+                       Side \"20\nSide \"10\nFORWARD :size\n";
+        let inlined = inline_procedures(parse(program));
+        assert_eq!(calls(&inlined), 0);
+        let AstNode::MakeStmnt { var, .. } = &inlined[3] else {
+            panic!("Expected the local of the first call to be bound");
+        };
+        assert_eq!(var, "half@1");
+
+        // The parameter binds a new name, so the global of the same name is left unchanged
+        let mut image = Image::new(100, 100);
+        let mut interpreter = Interpreter::new(&mut image);
+        interpreter.run(&inlined).unwrap();
+        assert_eq!(interpreter.position(), (50.0, 30.0));
+    }
+
+    #[test]
+    fn calls_which_could_read_the_callers_variables_are_left_in_place() {
+        // The body reads the caller's size before binding its own
+        let program = "MAKE \"size \"5\nTO Grow\nMAKE \"size + :size \"1\nEND\nGrow\n";
+        assert_eq!(calls(&inline_procedures(parse(program))), 1);
+
+        // The procedure called from the body is not inlined, so would read the body's
+        // variables by name
+        let program = "TO Show\nFORWARD :size\nSTOP\nEND\nTO Side \"size\nShow\nEND\nSide \"10\n";
+        assert_eq!(calls(&inline_procedures(parse(program))), 2);
+    }
+}
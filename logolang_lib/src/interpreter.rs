@@ -1,35 +1,80 @@
-/// Module for interpreting parsed LoGo-lang AST (Abstract Syntax Tree) nodes and executing them.
-///
-/// This module provides an interpreter for LoGo-lang programs represented as AST nodes.
-///
-/// # Examples
-///
-/// ```
-/// use logolang_interpreter::{Interpreter, AstNode, Value};
-///
-/// let ast = vec![
-///     AstNode::MakeStmnt {
-///         var: String::from("x"),
-///         expr: Box::new(AstNode::Num(10.0)),
-///         line: 1,
-///     }
-/// ];
-///
-/// let mut interpreter = Interpreter::new();
-/// let result = interpreter.run(&ast);
-/// assert!(result.is_ok());
-/// ```
-///
+//! Module for interpreting parsed LoGo-lang AST (Abstract Syntax Tree) nodes and executing them.
+//!
+//! This module provides an interpreter for LoGo-lang programs represented as AST nodes.
+//!
+//! # Examples
+//!
+//! ```
+//! use logolang_lib::interpreter::Interpreter;
+//! use logolang_lib::parser::AstNode;
+//! use unsvg::Image;
+//!
+//! let ast = vec![
+//!     AstNode::MakeStmnt {
+//!         var: String::from("x"),
+//!         expr: Box::new(AstNode::Num(10.0)),
+//!         line: 1,
+//!     }
+//! ];
+//!
+//! let mut image = Image::new(100, 100);
+//! let mut interpreter = Interpreter::new(&mut image);
+//! let result = interpreter.run(&ast);
+//! assert!(result.is_ok());
+//! ```
 
+use crate::analysis::node_kind;
+use crate::gcode::{self, Segment};
+use crate::glyphs::{self, GLYPH_ADVANCE, GLYPH_HEIGHT};
 use crate::logolang_errors::InterpreterError;
-use crate::parser::{ArithOp, AstNode, BoolOp, CompOp, Direction, NodeType, PenPos, QueryKind};
+use crate::parser::{
+    AngleUnit, ArithOp, AstNode, BoolOp, CompOp, Direction, NodeType, PenPos, QueryKind, RoundFn,
+    TrigFn,
+};
+use crate::raster::Raster;
+use crate::rng::Rng;
 use anyhow::{Context, Result};
 use core::panic;
-use std::borrow::BorrowMut;
 use std::collections::HashMap;
 use std::mem::discriminant;
 use std::rc::Rc;
-use unsvg::{get_end_coordinates, Image, COLORS};
+use unsvg::{get_end_coordinates, Color, Image, COLORS};
+
+/// Color SHOWPATH draws in (magenta)
+const PATH_COLOR: PenColor = PenColor::Indexed(5);
+
+/// Color AXES draws in (grey)
+const AXES_COLOR: PenColor = PenColor::Indexed(15);
+
+/// Distance between AXES tick marks, in turtle pixels
+const AXES_TICK_SPACING: f32 = 20.0;
+
+/// Length of each AXES tick mark, in turtle pixels
+const AXES_TICK_LENGTH: f32 = 6.0;
+
+/// Maximum length of each segment of a CURVETO curve or ARC, in turtle pixels
+const CURVE_SEGMENT_LENGTH: f32 = 5.0;
+
+/// Largest sweep ARC accepts, in degrees. Arcs are drawn as at most one segment per degree, so
+/// the sweep is bounded to bound the work done for each arc.
+const MAX_ARC_DEGREES: f32 = 3600.0;
+
+/// Widest pen SETPENWIDTH accepts, in turtle pixels. Wide lines are drawn as one 1px line per
+/// pixel of width, so the width is bounded to bound the work done for each line.
+const MAX_PEN_WIDTH: f32 = 1000.0;
+
+/// Most edges a single move may cross in the Wrap boundary mode before the turtle jumps
+/// straight to the end of the move, as the path has long since covered the whole image
+const MAX_WRAP_CROSSINGS: usize = 10_000;
+
+/// Size of one LABEL glyph grid unit, in turtle pixels
+const LABEL_GLYPH_SCALE: f32 = 2.0;
+
+/// Length of the STAMP triangle from its base to its tip, in turtle pixels
+const STAMP_LENGTH: f32 = 12.0;
+
+/// Width of the base of the STAMP triangle, in turtle pixels
+const STAMP_WIDTH: f32 = 8.0;
 
 /// Describes to turtles position
 #[derive(Debug)]
@@ -39,6 +84,165 @@ pub struct Position {
     direction: f32,
 }
 
+impl Position {
+    /// Constructor
+    pub fn new(x_coordinate: f32, y_coordinate: f32, direction: f32) -> Self {
+        Self {
+            x_coordinate,
+            y_coordinate,
+            direction,
+        }
+    }
+
+    /// Returns the x coordinate
+    pub fn x(&self) -> f32 {
+        self.x_coordinate
+    }
+
+    /// Returns the y coordinate
+    pub fn y(&self) -> f32 {
+        self.y_coordinate
+    }
+
+    /// Returns the heading in degrees, clockwise from straight up
+    pub fn heading(&self) -> f32 {
+        self.direction
+    }
+}
+
+/// Formats a position as `(x, y) @ heading°`
+///
+/// # Examples
+///
+/// ```
+/// use logolang_lib::interpreter::Position;
+///
+/// let position = Position::new(50.0, 25.5, 90.0);
+/// assert_eq!(position.to_string(), "(50, 25.5) @ 90°");
+/// ```
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "({}, {}) @ {}°",
+            self.x_coordinate, self.y_coordinate, self.direction
+        )
+    }
+}
+
+/// Color the pen draws in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PenColor {
+    /// Index into the unsvg palette (0..15), as set by PENCOLOR
+    Indexed(usize),
+    /// Explicit red, green and blue channels, as set by SETRGB
+    Rgb(u8, u8, u8),
+}
+
+impl PenColor {
+    /// Returns the palette index of the color, or None for an RGB color
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::PenColor;
+    ///
+    /// assert_eq!(PenColor::Indexed(3).palette_index(), Some(3));
+    /// assert_eq!(PenColor::Rgb(255, 128, 0).palette_index(), None);
+    /// ```
+    pub fn palette_index(self) -> Option<usize> {
+        match self {
+            PenColor::Indexed(index) => Some(index),
+            PenColor::Rgb(..) => None,
+        }
+    }
+
+    /// Returns the unsvg color the pen draws in
+    pub(crate) fn to_color(self) -> Color {
+        match self {
+            PenColor::Indexed(index) => COLORS[index],
+            PenColor::Rgb(red, green, blue) => Color::new_rgb(red, green, blue),
+        }
+    }
+
+    /// Returns the color drawn by a pen of this color and the given opacity over a pixel
+    /// already drawn in another color, or over the black of an undrawn pixel.
+    /// Opaque pens keep their own color, so palette colors can still be sensed.
+    pub(crate) fn blended_over(self, under: Option<PenColor>, opacity: f32) -> PenColor {
+        if opacity >= 1.0 {
+            return self;
+        }
+        let (over, under) = (self.to_color(), under.map_or(COLORS[0], PenColor::to_color));
+        let blend = |over: u8, under: u8| {
+            (over as f32 * opacity + under as f32 * (1.0 - opacity)).round() as u8
+        };
+        PenColor::Rgb(
+            blend(over.red, under.red),
+            blend(over.green, under.green),
+            blend(over.blue, under.blue),
+        )
+    }
+}
+
+/// Snapshot of the pen's drawing configuration, as stored by SAVEPEN and restored by LOADPEN
+#[derive(Debug, Clone)]
+pub struct PenState {
+    color: PenColor,
+    opacity: f32,
+    width: f32,
+    dash_pattern: Option<(f32, f32)>,
+}
+
+/// Determines how arithmetic results which are not finite (inf/NaN) are handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowMode {
+    /// Raise an error naming the operator and line (default)
+    Error,
+    /// Saturate infinite results to f32::MAX / f32::MIN. NaN results still raise an error,
+    /// as they have no sensible value to saturate to, as does division by zero.
+    Saturate,
+}
+
+/// Determines how SETPENCOLOR handles integer indices outside the palette (0..15).
+/// Non-integer colors are always a FractionalPenColor error.
+///
+/// ```
+/// use logolang_lib::interpreter::Interpreter;
+/// use logolang_lib::parser::AstNode;
+/// use unsvg::Image;
+///
+/// let set_color = |color| {
+///     let mut image = Image::new(100, 100);
+///     let mut interpreter = Interpreter::new(&mut image);
+///     let color = Box::new(AstNode::Num(color));
+///     let ast = vec![AstNode::PenColorUpdate { color, line: 1 }];
+///     interpreter.run(&ast).err().map(|error| error.to_string())
+/// };
+///
+/// assert_eq!(set_color(2.0), None);
+/// // Fractional colors and colors outside the palette are reported differently
+/// assert!(set_color(2.5).unwrap().contains("2.5 is not a whole number."));
+/// assert!(set_color(20.0).unwrap().contains("20 is out of range for a color."));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PenColorMode {
+    /// Raise an InvalidPenColor error (default)
+    Strict,
+    /// Clamp to the nearest palette index, so -1 becomes 0 and 16 becomes 15
+    Clamp,
+    /// Wrap around the palette, so -1 becomes 15 and 16 becomes 0
+    Modulo,
+}
+
+/// Determines what happens when the turtle moves past the edge of the image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoundaryMode {
+    /// Let the turtle leave the image, clipping lines at its edges (default)
+    Window,
+    /// Raise an OutOfBounds error instead of moving the turtle outside the image
+    Fence,
+    /// Bring the turtle back in at the opposite edge, continuing any line from there
+    Wrap,
+}
+
 /// The terminal values for which an expression can evaluate to
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Value {
@@ -47,12 +251,35 @@ pub enum Value {
     Word(String),
 }
 
-/// Implementation for addition assignment of type Value::Float
-impl std::ops::AddAssign for Value {
-    fn add_assign(&mut self, rhs: Self) {
-        match (self, rhs) {
-            (Value::Float(left), Value::Float(right)) => *left += right,
-            _ => panic!("Unsupported addition-assignment: Must be Value::Float()"),
+impl Value {
+    /// Parses a value supplied from outside a program, such as on the command line. Text
+    /// which reads as a finite number is a Float, and any other text is a Word.
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::{Interpreter, Value};
+    /// use logolang_lib::lexer::tokenize_str;
+    /// use logolang_lib::parser::Parser;
+    /// use std::collections::HashMap;
+    /// use unsvg::Image;
+    ///
+    /// assert_eq!(Value::parse("2.5"), Value::Float(2.5));
+    /// assert_eq!(Value::parse("square"), Value::Word(String::from("square")));
+    /// assert_eq!(Value::parse("inf"), Value::Word(String::from("inf")));
+    ///
+    /// // A program reads a preset value like any other variable
+    /// let tokens = tokenize_str("REPEAT :count [\n TURN \"10\n]\n", &HashMap::new(), "//");
+    /// let ast = Parser::new().parse(tokens.unwrap()).unwrap();
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.preset_variable("count", Value::parse("5"));
+    /// interpreter.run(&ast).unwrap();
+    /// assert_eq!(interpreter.heading(), 50.0);
+    /// ```
+    pub fn parse(text: &str) -> Value {
+        match text.parse::<f32>() {
+            Ok(num) if num.is_finite() => Value::Float(num),
+            _ => Value::Word(text.to_string()),
         }
     }
 }
@@ -85,6 +312,127 @@ impl std::fmt::Display for ArithOp {
             ArithOp::SUB => write!(f, "-"),
             ArithOp::MUL => write!(f, "*"),
             ArithOp::DIV => write!(f, "/"),
+            ArithOp::MOD => write!(f, "%"),
+            ArithOp::POW => write!(f, "POW"),
+            ArithOp::MIN => write!(f, "MIN"),
+            ArithOp::MAX => write!(f, "MAX"),
+        }
+    }
+}
+impl std::fmt::Display for TrigFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrigFn::SIN => write!(f, "SIN"),
+            TrigFn::COS => write!(f, "COS"),
+            TrigFn::TAN => write!(f, "TAN"),
+        }
+    }
+}
+impl AngleUnit {
+    /// Converts an angle given in this unit to degrees.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::parser::AngleUnit;
+    ///
+    /// assert_eq!(AngleUnit::Degrees.to_degrees(90.0), 90.0);
+    /// assert!((AngleUnit::Radians.to_degrees(std::f32::consts::PI) - 180.0).abs() < 1e-4);
+    /// ```
+    pub fn to_degrees(&self, angle: f32) -> f32 {
+        match self {
+            AngleUnit::Degrees => angle,
+            AngleUnit::Radians => angle.to_degrees(),
+        }
+    }
+
+    /// Converts an angle in degrees to this unit.
+    pub fn from_degrees(&self, degrees: f32) -> f32 {
+        match self {
+            AngleUnit::Degrees => degrees,
+            AngleUnit::Radians => degrees.to_radians(),
+        }
+    }
+}
+
+impl ArithOp {
+    /// Applies the operator to two numbers. Division by zero and fractional powers of negative
+    /// numbers give results which are not finite, as with the underlying f32 operations.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::parser::ArithOp;
+    ///
+    /// assert_eq!(ArithOp::SUB.apply(2.0, 5.0), -3.0);
+    /// assert_eq!(ArithOp::MOD.apply(-1.0, 4.0), 3.0);
+    /// assert!(!ArithOp::DIV.apply(1.0, 0.0).is_finite());
+    /// ```
+    pub fn apply(&self, left: f32, right: f32) -> f32 {
+        match self {
+            ArithOp::ADD => left + right,
+            ArithOp::SUB => left - right,
+            ArithOp::MUL => left * right,
+            ArithOp::DIV => left / right,
+            // The Euclidean remainder is never negative, so wrapped values stay in 0..|right|
+            ArithOp::MOD => left.rem_euclid(right),
+            ArithOp::POW => left.powf(right),
+            ArithOp::MIN => left.min(right),
+            ArithOp::MAX => left.max(right),
+        }
+    }
+}
+
+impl TrigFn {
+    /// Applies the function to an angle in degrees.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::parser::TrigFn;
+    ///
+    /// assert!((TrigFn::SIN.apply(90.0) - 1.0).abs() < 1e-6);
+    /// assert!((TrigFn::COS.apply(0.0) - 1.0).abs() < 1e-6);
+    /// assert!((TrigFn::TAN.apply(45.0) - 1.0).abs() < 1e-6);
+    /// ```
+    pub fn apply(&self, degrees: f32) -> f32 {
+        let radians = degrees.to_radians();
+        match self {
+            TrigFn::SIN => radians.sin(),
+            TrigFn::COS => radians.cos(),
+            TrigFn::TAN => radians.tan(),
+        }
+    }
+}
+impl std::fmt::Display for RoundFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoundFn::FLOOR => write!(f, "FLOOR"),
+            RoundFn::ROUND => write!(f, "ROUND"),
+            RoundFn::INT => write!(f, "INT"),
+        }
+    }
+}
+impl RoundFn {
+    /// Rounds a number to a whole number. FLOOR rounds down, ROUND rounds to the nearest whole
+    /// number (with halves rounded away from zero) and INT drops the fractional part.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::parser::RoundFn;
+    ///
+    /// assert_eq!(RoundFn::ROUND.apply(2.6), 3.0);
+    /// assert_eq!(RoundFn::ROUND.apply(-2.5), -3.0);
+    /// assert_eq!(RoundFn::FLOOR.apply(2.9), 2.0);
+    /// assert_eq!(RoundFn::FLOOR.apply(-2.1), -3.0);
+    /// assert_eq!(RoundFn::INT.apply(-2.7), -2.0);
+    /// ```
+    pub fn apply(&self, value: f32) -> f32 {
+        match self {
+            RoundFn::FLOOR => value.floor(),
+            RoundFn::ROUND => value.round(),
+            RoundFn::INT => value.trunc(),
         }
     }
 }
@@ -113,132 +461,844 @@ impl std::fmt::Display for BoolOp {
 pub struct Interpreter<'a> {
     /// Image to write
     image: &'a mut Image,
-    /// Variable environment
-    environment: HashMap<String, Value>,
+    /// Variable environment: a stack of scopes, with the globals at the bottom and a scope
+    /// for each procedure call in progress above them
+    environment: Vec<HashMap<String, Value>>,
     /// Function environment
     func_environment: HashMap<String, Rc<Vec<AstNode>>>, // Map each proc name to a list of its param names and a pointer to its executable body
     /// Turtle position
     current_position: Position,
     /// Pen color
-    current_color: usize,
+    current_color: PenColor,
+    /// Pen color restored by RESETALL
+    default_color: usize,
+    /// Pen opacity, from 0 (transparent) to 1 (opaque)
+    pen_opacity: f32,
+    /// Pen width in pixels, drawn as parallel 1px lines
+    current_pen_width: f32,
+    /// Lengths in pixels of each dash and the gap after it, or None for solid lines
+    dash_pattern: Option<(f32, f32)>,
     /// Drawing status
     currently_drawing: bool,
+    /// Whether the turtle is drawn at its final position once the program finishes
+    turtle_visible: bool,
+    /// Distance in pixels the turtle has moved, whether drawing or not
+    total_distance: f32,
+    /// Debug mode: draw each procedure's output in its own color
+    color_by_proc: bool,
+    /// Debug colors assigned to each procedure, in order of first invocation
+    proc_colors: HashMap<String, usize>,
+    /// Number of procedure calls currently being evaluated
+    proc_depth: usize,
+    /// Handling of non-finite arithmetic results
+    overflow_mode: OverflowMode,
+    /// Handling of out of range pen colors
+    pen_color_mode: PenColorMode,
+    /// Handling of moves past the edge of the image
+    boundary_mode: BoundaryMode,
+    /// Unit in which the program gives and receives angles; headings are kept in degrees
+    angle_unit: AngleUnit,
+    /// Named pen configurations
+    pen_presets: HashMap<String, PenState>,
+    /// Named turtle positions, in image coordinates, and headings
+    marks: HashMap<String, (f32, f32, f32)>,
+    /// Blank border added to every side of the drawing area
+    margin: f32,
+    /// Number of image pixels per turtle pixel
+    scale: f32,
+    /// Every position the turtle has occupied, in image coordinates, for SHOWPATH
+    path_history: Vec<(f32, f32)>,
+    /// Maximum number of pixels which may be drawn, if limited
+    max_drawn_pixels: Option<u64>,
+    /// Number of pixels drawn so far
+    drawn_pixels: u64,
+    /// Maximum number of loop iterations and procedure calls which may be evaluated, if limited
+    max_steps: Option<u64>,
+    /// Number of loop iterations and procedure calls evaluated so far
+    steps: u64,
+    /// Palette colors drawn at each pixel, as the image cannot be read back
+    raster: Raster,
+    /// Start and end points of every line drawn, in image coordinates and drawing order
+    segments: Vec<Segment>,
+    /// Set by STOP until the enclosing procedure (or program) has returned
+    stopping: bool,
+    /// Set by OUTPUT to the value the enclosing procedure returns
+    output: Option<Value>,
+    /// Palette color painted behind the drawing whenever the image is cleared, if set
+    background: Option<usize>,
+    /// Likely mistakes noticed while running the program
+    warnings: Vec<String>,
+    /// Whether each evaluated node is recorded in the trace
+    tracing: bool,
+    /// One entry per node evaluated while tracing, in evaluation order
+    trace: Vec<String>,
+    /// Source of values for RANDOM
+    rng: Rng,
 }
 
 impl<'a> Interpreter<'a> {
-    /// Constructor
+    /// Constructor, placing the turtle at the centre of the image facing up
     pub fn new(image: &'a mut Image) -> Self {
+        let (width, height) = image.get_dimensions();
+        Self::new_at(image, width as f32 / 2.0, height as f32 / 2.0, 0.0)
+    }
+
+    /// Constructor placing the turtle at the given image coordinates and heading in degrees,
+    /// for drawings which continue where another left off. RESETALL still returns the turtle
+    /// to the centre of the image.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::parser::{AstNode, Direction};
+    /// use unsvg::Image;
+    ///
+    /// let ast = vec![
+    ///     AstNode::PenStatusUpdate(true),
+    ///     AstNode::DrawInstruction {
+    ///         direction: Direction::FORWARD,
+    ///         num_pixels: Box::new(AstNode::Num(5.0)),
+    ///         line: 2,
+    ///     },
+    /// ];
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new_at(&mut image, 10.0, 20.0, 90.0);
+    /// interpreter.run(&ast).unwrap();
+    /// assert_eq!(interpreter.drawn_segments()[0], ((10.0, 20.0), (15.0, 20.0)));
+    /// ```
+    pub fn new_at(image: &'a mut Image, x: f32, y: f32, heading: f32) -> Self {
         let (width, height) = image.get_dimensions();
         Self {
             image,
-            environment: HashMap::new(),
+            environment: vec![HashMap::new()],
             func_environment: HashMap::new(),
             current_position: Position {
-                x_coordinate: width as f32 / 2.0,
-                y_coordinate: height as f32 / 2.0,
-                direction: 0.0,
+                x_coordinate: x,
+                y_coordinate: y,
+                direction: heading,
             },
-            current_color: 7,         // Starts default white
-            currently_drawing: false, // Starts default penup (not drawing)
+            current_color: PenColor::Indexed(7), // Starts default white
+            currently_drawing: false,            // Starts default penup (not drawing)
+            turtle_visible: false,
+            default_color: 7,
+            total_distance: 0.0,
+            pen_opacity: 1.0,
+            current_pen_width: 1.0,
+            dash_pattern: None,
+            color_by_proc: false,
+            proc_colors: HashMap::new(),
+            proc_depth: 0,
+            overflow_mode: OverflowMode::Error,
+            pen_color_mode: PenColorMode::Strict,
+            boundary_mode: BoundaryMode::Window,
+            angle_unit: AngleUnit::Degrees,
+            pen_presets: HashMap::new(),
+            marks: HashMap::new(),
+            margin: 0.0,
+            scale: 1.0,
+            path_history: vec![(x, y)],
+            max_drawn_pixels: None,
+            drawn_pixels: 0,
+            max_steps: None,
+            steps: 0,
+            raster: Raster::new(width, height),
+            segments: Vec::new(),
+            stopping: false,
+            output: None,
+            background: None,
+            warnings: Vec::new(),
+            tracing: false,
+            trace: Vec::new(),
+            rng: Rng::from_entropy(),
+        }
+    }
+
+    /// Constructor for an interpreter whose RANDOM values are determined by the seed, so
+    /// runs with the same seed draw the same image. Interpreters created with `new` are seeded
+    /// from the system.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::parser::{AstNode, Direction};
+    /// use unsvg::Image;
+    ///
+    /// // Draws five lines of random length below 10 pixels
+    /// let random_line = AstNode::DrawInstruction {
+    ///     direction: Direction::FORWARD,
+    ///     num_pixels: Box::new(AstNode::Random {
+    ///         max: Box::new(AstNode::Num(10.0)),
+    ///         line: 2,
+    ///     }),
+    ///     line: 2,
+    /// };
+    /// let mut ast = vec![AstNode::PenStatusUpdate(true)];
+    /// ast.extend(std::iter::repeat(random_line).take(5));
+    ///
+    /// let mut lengths = Vec::new();
+    /// for _ in 0..2 {
+    ///     let mut image = Image::new(200, 200);
+    ///     let mut interpreter = Interpreter::with_seed(&mut image, 42);
+    ///     interpreter.run(&ast).unwrap();
+    ///     let run_lengths: Vec<f32> = interpreter
+    ///         .drawn_segments()
+    ///         .iter()
+    ///         .map(|(start, end)| start.1 - end.1)
+    ///         .collect();
+    ///     lengths.push(run_lengths);
+    /// }
+    ///
+    /// // The same seed gives the same sequence, always in [0, 10)
+    /// assert_eq!(lengths[0], lengths[1]);
+    /// assert!(lengths[0].iter().all(|length| (0.0..10.0).contains(length)));
+    /// ```
+    pub fn with_seed(image: &'a mut Image, seed: u64) -> Self {
+        let mut interpreter = Self::new(image);
+        interpreter.rng = Rng::with_seed(seed);
+        interpreter
+    }
+
+    /// Sets the pen color the turtle starts with, in place of the default white (7).
+    /// Returns an InvalidPenColor error if the index is outside the palette (0..15).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use unsvg::Image;
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// assert!(Interpreter::new(&mut image).with_default_color(0).is_ok());
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// assert!(Interpreter::new(&mut image).with_default_color(16).is_err());
+    /// ```
+    pub fn with_default_color(mut self, index: usize) -> Result<Self, InterpreterError> {
+        if index >= COLORS.len() {
+            return Err(InterpreterError::InvalidPenColor(index.to_string()));
         }
+        self.current_color = PenColor::Indexed(index);
+        self.default_color = index;
+        Ok(self)
+    }
+
+    /// Limits the total number of pixels the program may draw, bounding the work done for
+    /// untrusted input. Once a drawing command would exceed the budget, evaluation stops with
+    /// an InkBudgetExceeded error. Pixels are counted in the image, so the budget also covers
+    /// supersampled output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::parser::{AstNode, Direction};
+    /// use unsvg::Image;
+    ///
+    /// let ast = vec![
+    ///     AstNode::PenStatusUpdate(true),
+    ///     AstNode::DrawInstruction {
+    ///         direction: Direction::FORWARD,
+    ///         num_pixels: Box::new(AstNode::Num(40.0)),
+    ///         line: 2,
+    ///     },
+    /// ];
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image).with_ink_budget(20);
+    /// assert!(interpreter.run(&ast).is_err());
+    /// ```
+    pub fn with_ink_budget(mut self, max_drawn_pixels: u64) -> Self {
+        self.max_drawn_pixels = Some(max_drawn_pixels);
+        self
+    }
+
+    /// Limits the total number of steps the program may take, so programs which never finish
+    /// (such as a WHILE loop whose condition is always true) fail instead of hanging. Each
+    /// iteration of a WHILE or REPEAT body and each procedure call is one step. Once the limit
+    /// is exceeded, evaluation stops with a StepLimitExceeded error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::parser::AstNode;
+    /// use unsvg::Image;
+    ///
+    /// let ast = vec![AstNode::WhileStmnt {
+    ///     condition: Box::new(AstNode::BoolLiteral(true)),
+    ///     body: Box::new(vec![]),
+    ///     line: 1,
+    /// }];
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image).with_step_limit(1000);
+    /// assert!(interpreter.run(&ast).is_err());
+    /// ```
+    pub fn with_step_limit(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Adds a blank margin of the given number of pixels around the drawing.
+    /// The image is replaced by a blank one expanded by the margin on each side, and the
+    /// turtle is shifted inward with it. Turtle coordinates (SETX/SETY/XCOR/YCOR) remain
+    /// relative to the original drawing area, so programs are unaffected by the margin.
+    /// Must be called before running a program, as the existing image is discarded.
+    pub fn set_margin(&mut self, margin: u32) {
+        let (width, height) = self.image.get_dimensions();
+        let scaled_margin = (margin as f32 * self.scale) as u32;
+        *self.image = Image::new(width + 2 * scaled_margin, height + 2 * scaled_margin);
+        self.raster = Raster::new(width + 2 * scaled_margin, height + 2 * scaled_margin);
+
+        let shift = (margin as f32 - self.margin) * self.scale;
+        self.current_position.x_coordinate += shift;
+        self.current_position.y_coordinate += shift;
+        self.margin = margin as f32;
+        self.path_history = vec![self.current_coordinates()];
+    }
+
+    /// Renders the drawing at `factor` times its size, for the caller to downsample into a
+    /// smoother image. The image is replaced by a blank one scaled by the factor, and every
+    /// distance the turtle moves is scaled with it. Turtle coordinates are unaffected, so
+    /// programs behave as if drawn at the original size. Lines keep their 1 pixel width in
+    /// the scaled image. Must be called before running a program, as the existing image is
+    /// discarded.
+    pub fn set_supersample(&mut self, factor: u32) {
+        let (width, height) = self.image.get_dimensions();
+        let ratio = factor as f32 / self.scale;
+        let (width, height) = (
+            (width as f32 * ratio) as u32,
+            (height as f32 * ratio) as u32,
+        );
+        *self.image = Image::new(width, height);
+        self.raster = Raster::new(width, height);
+
+        self.current_position.x_coordinate *= ratio;
+        self.current_position.y_coordinate *= ratio;
+        self.scale = factor as f32;
+        self.path_history = vec![self.current_coordinates()];
+    }
+
+    /// Binds a variable before the program runs, as if by a MAKE at the start of the program.
+    pub fn preset_variable(&mut self, name: &str, value: Value) {
+        self.bind(name.to_string(), value);
+    }
+
+    /// Sets how arithmetic results which overflow to inf or NaN are handled.
+    pub fn set_overflow_mode(&mut self, mode: OverflowMode) {
+        self.overflow_mode = mode;
+    }
+
+    /// Sets how pen colors outside the palette are handled by SETPENCOLOR.
+    pub fn set_pen_color_mode(&mut self, mode: PenColorMode) {
+        self.pen_color_mode = mode;
+    }
+
+    /// Sets how moves past the edge of the image are handled.
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::{BoundaryMode, Interpreter};
+    /// use logolang_lib::parser::{AstNode, Direction};
+    /// use unsvg::Image;
+    ///
+    /// // Move 70px right from the centre of a 100px image, 20px past its right edge
+    /// let ast = vec![
+    ///     AstNode::PenStatusUpdate(true),
+    ///     AstNode::DrawInstruction {
+    ///         direction: Direction::RIGHT,
+    ///         num_pixels: Box::new(AstNode::Num(70.0)),
+    ///         line: 2,
+    ///     },
+    /// ];
+    ///
+    /// // Window: the turtle leaves the image
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.run(&ast).unwrap();
+    /// assert_eq!(interpreter.position(), (120.0, 50.0));
+    ///
+    /// // Fence: the move is an error
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.set_boundary_mode(BoundaryMode::Fence);
+    /// let error = interpreter.run(&ast).err().unwrap();
+    /// assert!(error.to_string().contains("outside the image, to (120, 50)"));
+    /// assert_eq!(interpreter.position(), (50.0, 50.0));
+    ///
+    /// // Wrap: the line continues from the left edge
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.set_boundary_mode(BoundaryMode::Wrap);
+    /// interpreter.run(&ast).unwrap();
+    /// assert_eq!(interpreter.position(), (20.0, 50.0));
+    /// assert_eq!(
+    ///     interpreter.drawn_segments(),
+    ///     &[((50.0, 50.0), (100.0, 50.0)), ((0.0, 50.0), (20.0, 50.0))]
+    /// );
+    /// ```
+    pub fn set_boundary_mode(&mut self, mode: BoundaryMode) {
+        self.boundary_mode = mode;
+    }
+
+    /// Sets the unit in which the program gives and receives angles, as ANGLEUNITS does.
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::parser::AngleUnit;
+    /// use unsvg::Image;
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.set_angle_unit(AngleUnit::Radians);
+    /// ```
+    pub fn set_angle_unit(&mut self, unit: AngleUnit) {
+        self.angle_unit = unit;
+    }
+
+    /// Enables or disables the color-by-procedure debug mode.
+    /// When enabled, every procedure invocation draws in a color assigned to that procedure,
+    /// and SETPENCOLOR within procedure bodies is ignored. The caller's color is restored
+    /// once the procedure returns.
+    pub fn set_color_by_proc(&mut self, enabled: bool) {
+        self.color_by_proc = enabled;
+    }
+
+    /// Enables or disables tracing, for debugging the order a program runs in.
+    /// While enabled, every statement evaluated, including those within loop and procedure
+    /// bodies, is recorded in the trace as its line and kind (e.g. `[Line 3]: DrawInstruction`).
+    /// Expressions evaluated as arguments are not recorded separately.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::lexer::tokenize_str;
+    /// use logolang_lib::parser::Parser;
+    /// use std::collections::HashMap;
+    /// use unsvg::Image;
+    ///
+    /// let program = "PENDOWN\nREPEAT \"2 [\n    FORWARD \"10\n    TURN \"90\n]\n";
+    /// let tokens = tokenize_str(program, &HashMap::new(), "//").unwrap();
+    /// let ast = Parser::new().parse(tokens).unwrap();
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.set_trace(true);
+    /// interpreter.run(&ast).unwrap();
+    /// assert_eq!(
+    ///     interpreter.trace(),
+    ///     &vec![
+    ///         "PenStatusUpdate",
+    ///         "[Line 2]: RepeatStmnt",
+    ///         "[Line 3]: DrawInstruction",
+    ///         "[Line 4]: PenPosUpdate",
+    ///         "[Line 3]: DrawInstruction",
+    ///         "[Line 4]: PenPosUpdate",
+    ///     ]
+    /// );
+    ///
+    /// // Nothing is recorded by default
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.run(&ast).unwrap();
+    /// assert!(interpreter.trace().is_empty());
+    /// ```
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.tracing = enabled;
+    }
+
+    /// Returns the nodes evaluated while tracing was enabled, in evaluation order
+    pub fn trace(&self) -> &Vec<String> {
+        &self.trace
     }
 
     /// Runs the evaluator to traverse the AST.
     /// Returns the edited image on success, else returns an InterpreterError.
     pub fn run(&mut self, ast: &Vec<AstNode>) -> Result<&Image, InterpreterError> {
+        let drawn_before = self.drawn_pixels;
+        self.hoist_procedures(ast);
         self.evaluate(ast)
             .with_context(|| "Failed to evaluate program".to_string())?;
+        self.check_drawn(drawn_before);
+        self.show_turtle()?;
         // Return image on success
         Ok(self.image)
     }
 
+    /// Runs the evaluator without aborting on the first error.
+    /// An error raised by a top-level statement is recorded, and evaluation resumes at the
+    /// next top-level node. Errors inside loops/procedures still abort that whole construct.
+    /// Returns the edited image alongside every error encountered.
+    pub fn run_lenient(&mut self, ast: &Vec<AstNode>) -> (&Image, Vec<InterpreterError>) {
+        let mut errors = Vec::<InterpreterError>::new();
+        let drawn_before = self.drawn_pixels;
+        self.hoist_procedures(ast);
+
+        for node in ast {
+            if let Err(e) = self.evaluate_node(node) {
+                errors.push(e);
+            }
+            if self.stopping {
+                break;
+            }
+        }
+
+        self.check_drawn(drawn_before);
+        if let Err(e) = self.show_turtle() {
+            errors.push(e);
+        }
+        (self.image, errors)
+    }
+
+    /// Returns the warnings found while running the program, such as a program which drew
+    /// nothing. Warnings do not cause the program to fail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::parser::{AstNode, Direction};
+    /// use unsvg::Image;
+    ///
+    /// // Moves with the pen up, as PENDOWN was forgotten
+    /// let ast = vec![AstNode::DrawInstruction {
+    ///     direction: Direction::FORWARD,
+    ///     num_pixels: Box::new(AstNode::Num(10.0)),
+    ///     line: 1,
+    /// }];
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.run(&ast).unwrap();
+    /// assert_eq!(interpreter.warnings().len(), 1);
+    /// ```
+    pub fn warnings(&self) -> &Vec<String> {
+        &self.warnings
+    }
+
+    /// Helper fn: Warns if nothing has been drawn since the given amount of ink was spent,
+    /// usually because PENDOWN was forgotten
+    fn check_drawn(&mut self, drawn_before: u64) {
+        if self.drawn_pixels == drawn_before {
+            self.warnings.push(String::from(
+                "Program produced an empty image. Is PENDOWN missing?",
+            ));
+        }
+    }
+
+    /// Draws the turtle at its final position, if it was left visible by SHOWTURTLE
+    fn show_turtle(&mut self) -> Result<(), InterpreterError> {
+        if self.turtle_visible {
+            self.draw_turtle("SHOWTURTLE", 0)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the turtle's position in image coordinates, which may be passed to `new_at` to
+    /// continue drawing from the same point.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::{Interpreter, PenColor};
+    /// use logolang_lib::parser::{AstNode, Direction, PenPos};
+    /// use unsvg::Image;
+    ///
+    /// let ast = vec![
+    ///     AstNode::PenStatusUpdate(true),
+    ///     AstNode::DrawInstruction {
+    ///         direction: Direction::FORWARD,
+    ///         num_pixels: Box::new(AstNode::Num(10.0)),
+    ///         line: 2,
+    ///     },
+    ///     AstNode::DrawInstruction {
+    ///         direction: Direction::RIGHT,
+    ///         num_pixels: Box::new(AstNode::Num(5.0)),
+    ///         line: 3,
+    ///     },
+    ///     AstNode::PenPosUpdate {
+    ///         update_type: PenPos::TURN,
+    ///         value: Box::new(AstNode::Num(90.0)),
+    ///         line: 4,
+    ///     },
+    /// ];
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.run(&ast).unwrap();
+    /// assert_eq!(interpreter.position(), (55.0, 40.0));
+    /// assert_eq!(interpreter.heading(), 90.0);
+    /// assert_eq!(interpreter.pen_color(), PenColor::Indexed(7));
+    /// assert!(interpreter.is_pen_down());
+    /// ```
+    pub fn position(&self) -> (f32, f32) {
+        self.current_coordinates()
+    }
+
+    /// Returns the turtle's heading in degrees, clockwise from up
+    pub fn heading(&self) -> f32 {
+        self.current_position.direction
+    }
+
+    /// Returns the current pen color
+    pub fn pen_color(&self) -> PenColor {
+        self.current_color
+    }
+
+    /// Returns whether the pen is down, so moves draw lines
+    pub fn is_pen_down(&self) -> bool {
+        self.currently_drawing
+    }
+
+    /// Returns the start and end points of every line drawn so far, in image coordinates and
+    /// in the order they were drawn. Lines removed by RESETALL "CLEAR are not included.
+    pub fn drawn_segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Translates the lines drawn so far into a G-code program for a pen plotter, where each
+    /// pixel the turtle moves is `mm_per_pixel` millimetres on the plotter. Supersampling and
+    /// margins are accounted for, so the plot matches the saved image.
+    pub fn gcode(&self, mm_per_pixel: f32) -> String {
+        let (_, height) = self.image.get_dimensions();
+        gcode::to_gcode(&self.segments, height as f32, mm_per_pixel / self.scale)
+    }
+
+    /// Encodes the raster approximation of the image drawn so far as PNG bytes, without writing
+    /// a file. unsvg can only render an Image to a file, so the pixels are instead taken from
+    /// the record of drawn pixels which PIXELHERE reads: lines are 1 pixel wide without
+    /// anti-aliasing, and so differ slightly from the PNG the Image itself saves.
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use unsvg::Image;
+    ///
+    /// let mut image = Image::new(100, 50);
+    /// let interpreter = Interpreter::new(&mut image);
+    /// let bytes = interpreter.raster_png_bytes();
+    /// assert!(bytes.starts_with(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']));
+    /// ```
+    pub fn raster_png_bytes(&self) -> Vec<u8> {
+        let (width, height) = self.raster.dimensions();
+        let mut bytes = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.raster.rgb_bytes()))
+            .expect("Writing to a Vec cannot fail, and the raster has one pixel per image pixel");
+        bytes
+    }
+
     /// Traverses AST by matching on each parent node, and recursively stepping
     /// until leaf nodes are reached. The results are then propogated back up to
     /// the parent node.
     fn evaluate(&mut self, ast: &Vec<AstNode>) -> Result<(), InterpreterError> {
         for node in ast {
-            match node {
-                // Statement evaluation
-                AstNode::MakeStmnt { var, expr, line } => {
-                    self.make(String::from(var), expr, *line)?
-                }
-                AstNode::AddAssign {
-                    var_name,
-                    expr,
-                    line,
-                } => self.add_assign(var_name, expr, *line)?,
-                AstNode::DrawInstruction {
-                    direction,
-                    num_pixels,
-                    line,
-                } => self.draw_line(direction, num_pixels, *line)?,
-                AstNode::IfStmnt {
-                    condition,
-                    body,
-                    line,
-                } => self.if_statement(condition, body, *line)?,
-                AstNode::WhileStmnt {
-                    condition,
-                    body,
-                    line,
-                } => self.while_statement(condition, body, *line)?,
-                AstNode::PenStatusUpdate(new_drawing_status) => {
-                    self.set_drawing_status(*new_drawing_status);
-                }
-                AstNode::PenColorUpdate { color, line } => self.set_pen_color(color, *line)?,
-                AstNode::PenPosUpdate {
-                    update_type,
-                    value,
-                    line,
-                } => self.set_position(update_type, value, *line)?,
-                AstNode::Procedure { name, body } => {
-                    self.create_procedure(String::from(name), Rc::clone(body));
-                }
-                AstNode::ProcedureRef {
-                    name_ref,
-                    args,
-                    line,
-                } => self.eval_procedure(name_ref, args, *line)?,
-                // Expressions that are evaluated here are stand alone expressions; that is,
-                // their results are not used in any operations. We evaluate non-terminal
-                // expressions for correctness, and return nothing for terminal expressions.
-                AstNode::ArithExpr {
-                    operator,
-                    left,
-                    right,
-                    line,
-                } => {
-                    self.arith_expr(operator, left, right, *line)?;
-                }
-                AstNode::Query(_) => (),
-                AstNode::IdentRef(_) => (),
-                AstNode::Num { .. } => (),
-                AstNode::CompExpr {
-                    operator,
-                    left,
-                    right,
-                    line,
-                } => {
-                    self.comp_expr(operator, left, right, *line)?;
-                }
-                AstNode::BoolExpr {
-                    operator,
-                    left,
-                    right,
-                    line,
-                } => {
-                    self.bool_expr(operator, left, right, *line)?;
-                }
-                AstNode::Ident { .. } => (),
-                // If an ident it received here, it is not bound: treat it as an unbound word
-                AstNode::Word(word) => self.word(word),
+            self.evaluate_node(node)?;
+            // Unwind to the enclosing procedure once STOP is evaluated
+            if self.stopping {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates a single AST node.
+    fn evaluate_node(&mut self, node: &AstNode) -> Result<(), InterpreterError> {
+        if self.tracing {
+            self.trace.push(match node.line() {
+                Some(line) => format!("[Line {}]: {}", line, node_kind(node)),
+                None => node_kind(node).to_string(),
+            });
+        }
+
+        match node {
+            // Statement evaluation
+            AstNode::MakeStmnt { var, expr, line } => self.make(String::from(var), expr, *line)?,
+            AstNode::AddAssign {
+                var_name,
+                expr,
+                line,
+            } => self.add_assign(var_name, expr, *line)?,
+            AstNode::DrawInstruction {
+                direction,
+                num_pixels,
+                line,
+            } => self.draw_line(direction, num_pixels, *line)?,
+            AstNode::DrawAt {
+                angle,
+                length,
+                line,
+            } => self.draw_at(angle, length, *line)?,
+            AstNode::ForwardDots {
+                length,
+                spacing,
+                line,
+            } => self.forward_dots(length, spacing, *line)?,
+            AstNode::Star {
+                points,
+                length,
+                line,
+            } => self.star(points, length, *line)?,
+            AstNode::CurveTo { x, y, line } => self.curve_to(x, y, *line)?,
+            AstNode::Arc {
+                radius,
+                degrees,
+                line,
+            } => self.arc(radius, degrees, *line)?,
+            AstNode::SetPosition { x, y, line } => self.set_pos(x, y, *line)?,
+            AstNode::Label { text, line } => self.label(text, *line)?,
+            AstNode::Fill { line } => self.fill(*line)?,
+            AstNode::Stamp { line } => self.stamp(*line)?,
+            AstNode::IfStmnt {
+                condition,
+                body,
+                line,
+            } => self.if_statement(condition, body, *line)?,
+            AstNode::IfElseStmnt {
+                condition,
+                then_body,
+                else_body,
+                line,
+            } => self.if_else_statement(condition, then_body, else_body, *line)?,
+            AstNode::WhileStmnt {
+                condition,
+                body,
+                line,
+            } => self.while_statement(condition, body, *line)?,
+            AstNode::RepeatStmnt { count, body, line } => {
+                self.repeat_statement(count, body, *line)?
+            }
+            AstNode::ForStmnt {
+                var,
+                start,
+                end,
+                step,
+                body,
+                line,
+            } => self.for_statement(var, (start, end, step), body, *line)?,
+            AstNode::ForEachStmnt {
+                var,
+                values,
+                body,
+                line,
+            } => self.for_each_statement(var, values, body, *line)?,
+            AstNode::PenStatusUpdate(new_drawing_status) => {
+                self.set_drawing_status(*new_drawing_status);
+            }
+            AstNode::TurtleVisibility(visible) => self.turtle_visible = *visible,
+            AstNode::AngleUnits(unit) => self.angle_unit = *unit,
+            AstNode::PenColorUpdate { color, line } => self.set_pen_color(color, *line)?,
+            AstNode::BackgroundUpdate { color, line } => self.set_background(color, *line)?,
+            AstNode::PenRgbUpdate {
+                red,
+                green,
+                blue,
+                line,
+            } => self.set_pen_rgb([red, green, blue], *line)?,
+            AstNode::PenOpacityUpdate { opacity, line } => self.set_pen_opacity(opacity, *line)?,
+            AstNode::PenWidthUpdate { width, line } => self.set_pen_width(width, *line)?,
+            AstNode::PenDashUpdate { on, off, line } => self.set_pen_dash(on, off, *line)?,
+            AstNode::ResetAll { clear_image } => self.reset_all(*clear_image)?,
+            AstNode::Home { line } => self.home(*line)?,
+            AstNode::ClearScreen { .. } => self.clear_screen()?,
+            AstNode::ShowPath { line } => self.show_path(*line)?,
+            AstNode::Axes { line } => self.axes(*line)?,
+            AstNode::SavePen { name, .. } => self.save_pen(name),
+            AstNode::LoadPen { name, line } => self.load_pen(name, *line)?,
+            AstNode::Mark { name, .. } => self.mark(name),
+            AstNode::GoMark { name, line } => self.go_mark(name, *line)?,
+            AstNode::PenPosUpdate {
+                update_type,
+                value,
+                line,
+            } => self.set_position(update_type, value, *line)?,
+            AstNode::Stop { .. } => self.stopping = true,
+            AstNode::Output { value, line } => self.output(value, *line)?,
+            AstNode::Procedure { name, body, .. } => {
+                self.create_procedure(String::from(name), Rc::clone(body));
+            }
+            AstNode::ProcedureRef {
+                name_ref,
+                args,
+                line,
+            } => {
+                self.eval_procedure(name_ref, args, *line)?;
+            }
+            // Expressions that are evaluated here are stand alone expressions; that is,
+            // their results are not used in any operations. We evaluate non-terminal
+            // expressions for correctness, and return nothing for terminal expressions.
+            AstNode::ArithExpr {
+                operator,
+                left,
+                right,
+                line,
+            } => {
+                self.arith_expr(operator, left, right, *line)?;
             }
+            AstNode::TrigExpr { line, .. }
+            | AstNode::RoundExpr { line, .. }
+            | AstNode::Negate { line, .. }
+            | AstNode::Abs { line, .. }
+            | AstNode::Sqrt { line, .. }
+            | AstNode::Random { line, .. } => {
+                self.eval_numeric_expression(node, *line)?;
+            }
+            AstNode::Query(_) => (),
+            AstNode::IdentRef(_) => (),
+            AstNode::Num { .. } => (),
+            AstNode::BoolLiteral(_) => (),
+            AstNode::CompExpr {
+                operator,
+                left,
+                right,
+                line,
+            } => {
+                self.comp_expr(operator, left, right, *line)?;
+            }
+            AstNode::BoolExpr {
+                operator,
+                left,
+                right,
+                line,
+            } => {
+                self.bool_expr(operator, left, right, *line)?;
+            }
+            AstNode::IfVal { line, .. } => {
+                self.eval_value(node, *line)?;
+            }
+            AstNode::NotExpr { line, .. } => {
+                self.eval_logic_expression(node, *line)?;
+            }
+            AstNode::Ident { .. } => (),
+            // If an ident it received here, it is not bound: treat it as an unbound word
+            AstNode::Word(word) => self.word(word),
         }
         Ok(())
     }
 
     /// Evaluation of MAKE statment
     fn make(&mut self, var: String, expr: &AstNode, line: i32) -> Result<(), InterpreterError> {
+        let bound_val = self.make_value(&var, expr, line)?;
+
+        // Add binding to map
+        self.bind(var, bound_val);
+        Ok(())
+    }
+
+    /// Evaluates the expression of a MAKE statement to the value bound to its variable
+    fn make_value(
+        &mut self,
+        var: &str,
+        expr: &AstNode,
+        line: i32,
+    ) -> Result<Value, InterpreterError> {
         let bound_val = match expr {
             // Numeric expressions
             AstNode::ArithExpr {
@@ -248,11 +1308,16 @@ impl<'a> Interpreter<'a> {
                 line
             } => Value::Float(self.arith_expr(operator, left, right, *line)
                               .with_context(|| format!("[Line {}]: interp Invalid MAKE statement: Failed to evaluate expression passed to {}",line, var))?),
+            AstNode::TrigExpr { .. } | AstNode::RoundExpr { .. } | AstNode::Negate { .. } | AstNode::Abs { .. } | AstNode::Sqrt { .. } | AstNode::Random { .. } => Value::Float(self.eval_numeric_expression(expr, line)
+                              .with_context(|| format!("[Line {}]: Invalid MAKE statement: Failed to evaluate expression passed to {}", line, var))?),
             AstNode::Query(query_kind) => Value::Float(self.query(query_kind)),
             AstNode::IdentRef(var) => self.eval_ident_ref_as_val(var)
                     .with_context(|| format!("[Line {}]: Invalid MAKE statement: Failed to evaluate expression passed to {}",line, var))?,
+            AstNode::ProcedureRef { name_ref, args, line } => self.procedure_value(name_ref, args, *line)
+                    .with_context(|| format!("[Line {}]: Invalid MAKE statement: Failed to evaluate expression passed to {}", line, var))?,
             AstNode::Num(val) => Value::Float(*val),
             // Logic expressions
+            AstNode::BoolLiteral(value) => Value::Bool(*value),
             AstNode::CompExpr {
                 operator,
                 left,
@@ -267,14 +1332,17 @@ impl<'a> Interpreter<'a> {
                 line
             } => Value::Bool(self.bool_expr(operator, left, right, *line)
                            .with_context(|| format!("[Line {}]: Failed to evaluate expression provided to {}", line, operator))?),                            
+            AstNode::NotExpr { .. } => Value::Bool(self.eval_logic_expression(expr, line)
+                           .with_context(|| format!("[Line {}]: Failed to evaluate expression provided to NOT", line))?),
             // Word expressions
             AstNode::Word(word) => Value::Word(word.to_string()),
+            // Conditional expressions
+            AstNode::IfVal { .. } => self.eval_value(expr, line)
+                    .with_context(|| format!("[Line {}]: Invalid MAKE statement: Failed to evaluate expression passed to {}", line, var))?,
             _ => unreachable!("fn make_op in parser checks that expressions passed to MAKE implement is_boolean() or is_numeric()."),
         };
 
-        // Add binding to map
-        self.environment.insert(var, bound_val);
-        Ok(())
+        Ok(bound_val)
     }
 
 
@@ -296,18 +1364,83 @@ impl<'a> Interpreter<'a> {
                                                  ,line
                                                  ,operator))?
                     ),
+            AstNode::TrigExpr {
+                function,
+                operand,
+                line,
+            } => self.trig_expr(function, operand, *line),
+            AstNode::RoundExpr {
+                function,
+                operand,
+                line,
+            } => {
+                let value = self
+                    .eval_numeric_expression(operand, *line)
+                    .with_context(|| format!("[Line {}]: Invalid argument to '{}'", line, function))?;
+                Ok(function.apply(value))
+            }
+            AstNode::Negate { operand, line } => {
+                let value = self
+                    .eval_numeric_expression(operand, *line)
+                    .with_context(|| format!("[Line {}]: Invalid argument to 'NEG'", line))?;
+                Ok(-value)
+            }
+            AstNode::Abs { operand, line } => {
+                let value = self
+                    .eval_numeric_expression(operand, *line)
+                    .with_context(|| format!("[Line {}]: Invalid argument to 'ABS'", line))?;
+                Ok(value.abs())
+            }
+            AstNode::Sqrt { operand, line } => {
+                let value = self
+                    .eval_numeric_expression(operand, *line)
+                    .with_context(|| format!("[Line {}]: Invalid argument to 'SQRT'", line))?;
+                // f32::sqrt gives NaN for negative numbers, which would corrupt coordinates
+                if value < 0.0 {
+                    return Err(InterpreterError::NegativeSqrt(format!(
+                        "[Line {}]: Cannot take the square root of negative number {}.",
+                        line, value
+                    )));
+                }
+                Ok(value.sqrt())
+            }
+            AstNode::Random { max, line } => self.random(max, *line),
             AstNode::Query(query_kind) => Ok(self.query(query_kind)),
             AstNode::IdentRef(var) => {
                 let ident_value = self.eval_ident_ref(var)?;
                 match ident_value {
                     Value::Float(num) => Ok(*num),
-                    Value::Bool(val) => Err(InterpreterError::TypeError(format!("[Line {}]: variable '{}' is assigned to the boolean value {}, not a float.",
-                                                                                 line,String::from(var), val))),
-                    Value::Word(word) => Err(InterpreterError::TypeError(format!("[Line {}]: variable '{}' is assigned to the String value {}, not a float."
-                                                                             ,line,String::from(var), word))),
+                    Value::Bool(_) => Err(InterpreterError::VariableTypeError(format!(
+                        "[Line {}]: variable '{}' holds a boolean and cannot be used as a number here",
+                        line, var
+                    ))),
+                    Value::Word(_) => Err(InterpreterError::VariableTypeError(format!(
+                        "[Line {}]: variable '{}' holds a word and cannot be used as a number here",
+                        line, var
+                    ))),
                 }
             }
+            AstNode::ProcedureRef {
+                name_ref,
+                args,
+                line,
+            } => match self.procedure_value(name_ref, args, *line)? {
+                Value::Float(num) => Ok(num),
+                _ => Err(InterpreterError::TypeError(format!(
+                    "[Line {}]: procedure '{}' did not OUTPUT a number, and cannot be used as a number here",
+                    line, name_ref
+                ))),
+            },
             AstNode::Num(val) => Ok(*val),
+            AstNode::IfVal {
+                condition,
+                then_val,
+                else_val,
+                line,
+            } => {
+                let chosen = self.if_val(condition, then_val, else_val, *line)?;
+                self.eval_numeric_expression(chosen, *line)
+            }
             _ => unreachable!("This fn is only called by functions which expect numeric expressions, which has already been verified by the parser."),
         }
     }
@@ -347,6 +1480,13 @@ impl<'a> Interpreter<'a> {
                         line, operator
                     )
                 })?),
+            AstNode::BoolLiteral(value) => Ok(*value),
+            AstNode::NotExpr { operand, line } => {
+                let value = self
+                    .eval_logic_expression(operand, *line)
+                    .with_context(|| format!("[Line {}]: Invalid argument to 'NOT'", line))?;
+                Ok(!value)
+            }
             AstNode::IdentRef(var) => {
                 let ident_value = self
                     .eval_ident_ref(var)
@@ -367,6 +1507,26 @@ impl<'a> Interpreter<'a> {
                     ))),
                 }
             }
+            AstNode::ProcedureRef {
+                name_ref,
+                args,
+                line,
+            } => match self.procedure_value(name_ref, args, *line)? {
+                Value::Bool(value) => Ok(value),
+                _ => Err(InterpreterError::TypeError(format!(
+                    "[Line {}]: procedure '{}' did not OUTPUT a bool, and cannot be used as a bool here",
+                    line, name_ref
+                ))),
+            },
+            AstNode::IfVal {
+                condition,
+                then_val,
+                else_val,
+                line,
+            } => {
+                let chosen = self.if_val(condition, then_val, else_val, *line)?;
+                self.eval_logic_expression(chosen, *line)
+            }
             _ => panic!("All cases for which this function is called were expected to be handled"),
         }
     }
@@ -378,21 +1538,36 @@ impl<'a> Interpreter<'a> {
         expr: &AstNode,
         line: i32,
     ) -> Result<(), InterpreterError> {
-        let bound_value =
-            Value::Float(self.eval_numeric_expression(expr, line).with_context(|| {
-                format!(
-                    "Invalid ADDASSIGN: Failed to add number to '{}'\n",
-                    var_name
-                )
-            })?);
-        if let Some(var) = self.environment.get_mut(var_name) {
-            *var += bound_value;
-        } else {
-            return Err(InterpreterError::InvalidVariableRef(format!(
-                "Variable {} does not exist.",
+        let addend = self.eval_numeric_expression(expr, line).with_context(|| {
+            format!(
+                "Invalid ADDASSIGN: Failed to add number to '{}'\n",
                 var_name
-            )));
-        }
+            )
+        })?;
+        // Only numbers can be added to, as with variables used in arithmetic
+        let sum = match self.lookup(var_name) {
+            Some(Value::Float(value)) => value + addend,
+            Some(Value::Bool(_)) => {
+                return Err(InterpreterError::VariableTypeError(format!(
+                    "[Line {}]: variable '{}' holds a boolean and cannot be used as a number here",
+                    line, var_name
+                )))
+            }
+            Some(Value::Word(_)) => {
+                return Err(InterpreterError::VariableTypeError(format!(
+                    "[Line {}]: variable '{}' holds a word and cannot be used as a number here",
+                    line, var_name
+                )))
+            }
+            None => {
+                return Err(InterpreterError::InvalidVariableRef(format!(
+                    "Variable {} does not exist.",
+                    var_name
+                )))
+            }
+        };
+        // Outer scopes are read-only, so the sum is bound in the current scope
+        self.bind(var_name.to_string(), Value::Float(sum));
         Ok(())
     }
 
@@ -413,246 +1588,1629 @@ impl<'a> Interpreter<'a> {
         value: &AstNode,
         line: i32,
     ) -> Result<(), InterpreterError> {
-        let num_pixels = self.eval_numeric_expression(value, line).with_context(|| {
-            format!(
-                "[Line {}]: Invalid ADDASSIGN: Failed to add number to '{}'\n",
-                line, direction
-            )
-        })?;
+        let num_pixels = self
+            .eval_numeric_expression(value, line)
+            .with_context(|| format!("[Line {}]: Invalid argument to '{}'\n", line, direction))?;
 
         let adjusted_direction = self.get_relative_direction(direction);
 
-        if self.currently_drawing {
-            let res_pair = self.image.draw_simple_line(
-                self.current_position.x_coordinate,
-                self.current_position.y_coordinate,
-                adjusted_direction,
-                num_pixels,
-                COLORS[self.current_color],
-            );
-
-            match res_pair {
-                Ok(res_pair) => {
-                    (
-                        self.current_position.x_coordinate,
-                        self.current_position.y_coordinate,
-                    ) = res_pair;
-                }
-                Err(error) => {
-                    return Err(InterpreterError::DrawLineError(
-                        format!(
-                            "[Line {}]: Failed to draw line for direction {} due to UNSVG error:",
-                            line, direction
-                        ),
-                        error.to_string(),
-                    ))
-                }
-            };
-        } else {
-            // Update coordinates without drawing
-            let res_pair = get_end_coordinates(
-                self.current_position.x_coordinate,
-                self.current_position.y_coordinate,
-                adjusted_direction,
-                num_pixels,
-            );
+        self.move_turtle(adjusted_direction, num_pixels, &direction.to_string(), line)
+    }
 
-            (
-                self.current_position.x_coordinate,
-                self.current_position.y_coordinate,
-            ) = res_pair;
-        };
+    /// Draws a line of DRAWAT's given absolute heading, leaving the turtle's own heading intact
+    fn draw_at(
+        &mut self,
+        angle: &AstNode,
+        length: &AstNode,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let angle_val = self
+            .eval_numeric_expression(angle, line)
+            .with_context(|| format!("[Line {}]: Invalid angle provided to DRAWAT.\n", line))?;
+        let length_val = self
+            .eval_numeric_expression(length, line)
+            .with_context(|| format!("[Line {}]: Invalid length provided to DRAWAT.\n", line))?;
 
-        Ok(())
+        let degrees = self.angle_unit.to_degrees(angle_val);
+        self.move_turtle(degrees as i32, length_val, "DRAWAT", line)
     }
 
-    /// Evaluates If statement
-    fn if_statement(
+    /// Moves forward, placing a dot every `spacing` pixels regardless of the pen state
+    fn forward_dots(
         &mut self,
-        condition: &AstNode,
-        body: &Vec<AstNode>,
+        length: &AstNode,
+        spacing: &AstNode,
         line: i32,
     ) -> Result<(), InterpreterError> {
-        let condition_is_true = self
-            .eval_logic_expression(condition, line)
-            .with_context(|| format!("[Line {}]: Invalid IF statement condition.\n", line))?;
-        if condition_is_true {
-            self.evaluate(body)
-                .with_context(|| format!("[Line {}]: Invalid IF statement condition.\n", line))?;
+        let length_val = self
+            .eval_numeric_expression(length, line)
+            .with_context(|| {
+                format!("[Line {}]: Invalid length provided to FORWARDDOTS.\n", line)
+            })?;
+        let spacing_val = self
+            .eval_numeric_expression(spacing, line)
+            .with_context(|| {
+                format!(
+                    "[Line {}]: Invalid spacing provided to FORWARDDOTS.\n",
+                    line
+                )
+            })?;
+
+        if spacing_val.is_nan() || spacing_val <= 0.0 {
+            return Err(InterpreterError::InvalidArgument(format!(
+                "[Line {}]: FORWARDDOTS spacing must be positive, received {}.",
+                line, spacing_val
+            )));
+        }
+        // At most one dot per pixel, so a tiny spacing can't place an unbounded number of dots
+        if spacing_val * self.scale < 1.0 {
+            return Err(InterpreterError::InvalidArgument(format!(
+                "[Line {}]: FORWARDDOTS spacing must be at least one pixel, received {}.",
+                line, spacing_val
+            )));
+        }
+
+        let direction = self.current_position.direction as i32;
+        let (start_x, start_y) = (
+            self.current_position.x_coordinate,
+            self.current_position.y_coordinate,
+        );
+        let end = get_end_coordinates(start_x, start_y, direction, length_val * self.scale);
+        if self.boundary_mode == BoundaryMode::Fence {
+            self.check_in_bounds(end, "FORWARDDOTS", line)?;
+        }
+
+        // Dots are measured from the start of the movement so rounding errors don't accumulate
+        let num_dots = (length_val.abs() / spacing_val) as usize;
+        for i in 0..=num_dots {
+            let distance = i as f32 * spacing_val * length_val.signum() * self.scale;
+            let dot = get_end_coordinates(start_x, start_y, direction, distance);
+            let (dot_x, dot_y) = self.landing_point(dot);
+            self.draw_dot(dot_x, dot_y, direction, line)?;
         }
+
+        (
+            self.current_position.x_coordinate,
+            self.current_position.y_coordinate,
+        ) = self.landing_point(end);
+        self.total_distance += length_val.abs();
+        self.path_history.push(self.current_coordinates());
+
         Ok(())
     }
 
-    /// Evaluates while statement
-    fn while_statement(
+    /// Draws an n-pointed {n/k} star polygon from the current pose, connecting every k-th
+    /// vertex. The step k is the largest value below n/2 sharing no factor with n, so the star
+    /// is drawn in a single stroke. The turtle finishes back at its starting pose.
+    fn star(
         &mut self,
-        condition: &AstNode,
-        body: &Vec<AstNode>,
+        points: &AstNode,
+        length: &AstNode,
         line: i32,
     ) -> Result<(), InterpreterError> {
-        let condition_is_true = self
-            .eval_logic_expression(condition, line)
-            .with_context(|| format!("[Line {}]: Invalid WHILE statement condition.\n", line))?;
+        let points_val = self
+            .eval_numeric_expression(points, line)
+            .with_context(|| format!("[Line {}]: Invalid points provided to STAR.\n", line))?;
+        let length_val = self
+            .eval_numeric_expression(length, line)
+            .with_context(|| format!("[Line {}]: Invalid length provided to STAR.\n", line))?;
 
-        if condition_is_true {
-            self.evaluate(body).with_context(|| {
-                format!(
-                    "[Line {}]: Invalid expression in the body of the WHILE statement.\n",
-                    line
-                )
+        if points_val.fract() != 0.0 || points_val < 5.0 {
+            return Err(InterpreterError::InvalidArgument(format!(
+                "[Line {}]: STAR requires a whole number of at least 5 points, received {}.",
+                line, points_val
+            )));
+        }
+
+        let num_points = points_val as u32;
+        let step = (2..=(num_points - 1) / 2)
+            .rev()
+            .find(|k| gcd(num_points, *k) == 1)
+            .ok_or_else(|| {
+                InterpreterError::InvalidArgument(format!(
+                    "[Line {}]: A {}-pointed star cannot be drawn in a single stroke.",
+                    line, num_points
+                ))
             })?;
-            self.while_statement(condition, body, line)
-                  .with_context(|| format!("[Line {}]: Invalid expression in the body of the WHILE loop encountered while looping.\n",line))?;
+
+        let turn = 360.0 * step as f32 / num_points as f32;
+        let start_direction = self.current_position.direction;
+        for i in 0..num_points {
+            let direction = start_direction + turn * i as f32;
+            self.move_turtle(direction.round() as i32, length_val, "STAR", line)?;
         }
+
         Ok(())
     }
 
-    /// Sets drawing state
-    fn set_drawing_status(&mut self, new_drawing_status: bool) {
-        self.currently_drawing = new_drawing_status;
+    /// Draws a smooth quadratic curve from the current position to the given coordinates,
+    /// leaving in the direction of the current heading. The curve's control point lies
+    /// along the heading, half the distance to the target away. The curve is drawn as short
+    /// segments, and the turtle finishes at the target facing along the curve's end tangent.
+    fn curve_to(&mut self, x: &AstNode, y: &AstNode, line: i32) -> Result<(), InterpreterError> {
+        let x_val = self
+            .eval_numeric_expression(x, line)
+            .with_context(|| format!("[Line {}]: Invalid x provided to CURVETO.\n", line))?;
+        let y_val = self
+            .eval_numeric_expression(y, line)
+            .with_context(|| format!("[Line {}]: Invalid y provided to CURVETO.\n", line))?;
+
+        // Work in image coordinates, matching SETX / SETY
+        let start = self.current_coordinates();
+        let end = (
+            (x_val + self.margin) * self.scale,
+            (y_val + self.margin) * self.scale,
+        );
+        let distance = (end.0 - start.0).hypot(end.1 - start.1);
+        if distance == 0.0 {
+            return Ok(());
+        }
+
+        let heading = self.current_position.direction.to_radians();
+        let control = (
+            start.0 + heading.sin() * distance / 2.0,
+            start.1 - heading.cos() * distance / 2.0,
+        );
+
+        let num_segments = ((distance / self.scale / CURVE_SEGMENT_LENGTH).ceil() as usize).max(8);
+        let mut reached = start;
+        for i in 1..=num_segments {
+            let t = i as f32 / num_segments as f32;
+            let point = (
+                quadratic_bezier(start.0, control.0, end.0, t),
+                quadratic_bezier(start.1, control.1, end.1, t),
+            );
+
+            // Aim from where the last segment actually ended so rounding of directions doesn't
+            // accumulate. This is tracked separately from the turtle, which may have wrapped.
+            let (dx, dy) = (point.0 - reached.0, point.1 - reached.1);
+            let direction = dx.atan2(-dy).to_degrees().round() as i32;
+            let length = dx.hypot(dy);
+            self.move_turtle(direction, length / self.scale, "CURVETO", line)?;
+            reached = get_end_coordinates(reached.0, reached.1, direction, length);
+        }
+
+        // Land exactly on the target, facing along the end tangent
+        let landing = self.landing_point(end);
+        (
+            self.current_position.x_coordinate,
+            self.current_position.y_coordinate,
+        ) = landing;
+        if let Some(last) = self.path_history.last_mut() {
+            *last = landing;
+        }
+        let (dx, dy) = (end.0 - control.0, end.1 - control.1);
+        self.current_position.direction = dx.atan2(-dy).to_degrees().rem_euclid(360.0);
+
+        Ok(())
     }
 
-    /// Sets pen color
-    fn set_pen_color(&mut self, value: &AstNode, line: i32) -> Result<(), InterpreterError> {
-        let float_val = self
-            .eval_numeric_expression(value, line)
-            .with_context(|| format!("[Line {}]: Invalid argument to PENCOLOR.\n", line))?;
+    /// Moves the turtle along a circular arc of the given radius, turning through the given
+    /// number of degrees as it goes: clockwise (to the right) for positive degrees, as with
+    /// TURN, and anticlockwise for negative degrees. The arc is drawn as short segments if the
+    /// pen is down, and the turtle finishes at its end, facing along it.
+    fn arc(
+        &mut self,
+        radius: &AstNode,
+        degrees: &AstNode,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let radius_val = self
+            .eval_numeric_expression(radius, line)
+            .with_context(|| format!("[Line {}]: Invalid radius provided to ARC.\n", line))?;
+        let angle_val = self
+            .eval_numeric_expression(degrees, line)
+            .with_context(|| format!("[Line {}]: Invalid degrees provided to ARC.\n", line))?;
+        let degrees_val = self.angle_unit.to_degrees(angle_val);
 
-        // Check precision & bounds before casting to an int color
-        if float_val == (float_val as usize) as f32 && (0.0..=15.0).contains(&float_val) {
-            self.current_color = float_val as usize;
-        } else {
-            return Err(InterpreterError::InvalidPenColor(float_val.to_string()));
+        if radius_val <= 0.0 {
+            return Err(InterpreterError::InvalidArgument(format!(
+                "[Line {}]: ARC requires a positive radius, got {}.",
+                line, radius_val
+            )));
+        }
+        if degrees_val.is_nan() || degrees_val.abs() > MAX_ARC_DEGREES {
+            return Err(InterpreterError::InvalidArgument(format!(
+                "[Line {}]: ARC sweeps at most {} degrees either way, got {}.",
+                line, MAX_ARC_DEGREES, degrees_val
+            )));
+        }
+        if degrees_val == 0.0 {
+            return Ok(());
+        }
+
+        // The centre lies to the side the turtle turns towards, and each point on the arc is
+        // found by turning the radius from the centre to the turtle about it
+        let start = self.current_coordinates();
+        let heading = self.current_position.direction;
+        let side = degrees_val.signum();
+        let radius_px = radius_val * self.scale;
+        let right = |angle: f32| (angle.to_radians().cos(), angle.to_radians().sin());
+        let centre = (
+            start.0 + side * radius_px * right(heading).0,
+            start.1 + side * radius_px * right(heading).1,
+        );
+        let point_at = |swept: f32| {
+            (
+                centre.0 - side * radius_px * right(heading + swept).0,
+                centre.1 - side * radius_px * right(heading + swept).1,
+            )
         };
+
+        let arc_length = radius_val * degrees_val.abs().to_radians();
+        // At most one segment per degree, however large the radius
+        let max_segments = degrees_val.abs().ceil();
+        let num_segments = (arc_length / CURVE_SEGMENT_LENGTH)
+            .ceil()
+            .min(max_segments)
+            .max(8.0) as usize;
+        let mut reached = start;
+        for i in 1..=num_segments {
+            let point = point_at(degrees_val * i as f32 / num_segments as f32);
+
+            // Aim from where the last segment actually ended, as with CURVETO
+            let (dx, dy) = (point.0 - reached.0, point.1 - reached.1);
+            let direction = dx.atan2(-dy).to_degrees().round() as i32;
+            let length = dx.hypot(dy);
+            self.move_turtle(direction, length / self.scale, "ARC", line)?;
+            reached = get_end_coordinates(reached.0, reached.1, direction, length);
+        }
+
+        // Land exactly on the end of the arc, facing along it
+        let landing = self.landing_point(point_at(degrees_val));
+        (
+            self.current_position.x_coordinate,
+            self.current_position.y_coordinate,
+        ) = landing;
+        if let Some(last) = self.path_history.last_mut() {
+            *last = landing;
+        }
+        self.current_position.direction = (heading + degrees_val).rem_euclid(360.0);
+
         Ok(())
     }
 
-    /// Sets the position/orientation of the pen
-    fn set_position(
+    /// Helper fn: Draws a single pixel dot at the given coordinates in the current pen color
+    fn draw_dot(
         &mut self,
-        update_type: &PenPos,
-        value: &AstNode,
+        x: f32,
+        y: f32,
+        direction: i32,
         line: i32,
     ) -> Result<(), InterpreterError> {
-        let val = self
-            .eval_numeric_expression(value, line)
-            .with_context(|| format!("[Line {}]: Invalid argument to {}.\n", line, update_type))?;
-        match update_type {
-            PenPos::SETX => self.current_position.x_coordinate = val,
-            PenPos::SETY => self.current_position.y_coordinate = val,
-            PenPos::SETHEADING => self.current_position.direction = val,
-            PenPos::TURN => self.current_position.direction += val,
+        self.draw_segment(
+            (x, y),
+            direction,
+            self.scale,
+            (self.current_color, self.pen_opacity),
+            "FORWARDDOTS",
+            line,
+        )?;
+        Ok(())
+    }
+
+    /// Draws a word in the current pen color and opacity, reading left to right with the bottom
+    /// left corner of its first character at the turtle's position. Text is drawn whether or
+    /// not the pen is down, and the turtle and pen are left unchanged.
+    fn label(&mut self, text: &AstNode, line: i32) -> Result<(), InterpreterError> {
+        let text = match self
+            .eval_value(text, line)
+            .with_context(|| format!("[Line {}]: Invalid text provided to LABEL.\n", line))?
+        {
+            Value::Word(word) => word,
+            Value::Float(num) => {
+                return Err(InterpreterError::TypeError(format!(
+                    "[Line {}]: LABEL requires a word, received the float value {}.",
+                    line, num
+                )))
+            }
+            Value::Bool(value) => {
+                return Err(InterpreterError::TypeError(format!(
+                    "[Line {}]: LABEL requires a word, received the bool value {}.",
+                    line, value
+                )))
+            }
+        };
+
+        let unit = LABEL_GLYPH_SCALE * self.scale;
+        let (origin_x, origin_y) = self.current_coordinates();
+        for (i, character) in text.chars().enumerate() {
+            let strokes = glyphs::glyph(character).ok_or_else(|| {
+                InterpreterError::InvalidArgument(format!(
+                    "[Line {}]: LABEL can only draw letters and digits, received '{}'.",
+                    line, character
+                ))
+            })?;
+
+            // Glyph rows are counted down from the top of the character cell
+            let cell_x = origin_x + (i as f32 * GLYPH_ADVANCE as f32) * unit;
+            let to_image = |(column, row): (u8, u8)| {
+                (
+                    cell_x + column as f32 * unit,
+                    origin_y - (GLYPH_HEIGHT - row) as f32 * unit,
+                )
+            };
+            for stroke in strokes {
+                for points in stroke.windows(2) {
+                    self.draw_between(to_image(points[0]), to_image(points[1]), "LABEL", line)?;
+                }
+            }
         }
+        Ok(())
+    }
 
+    /// Draws a triangle in the current pen color and opacity, centred on the turtle with its
+    /// tip pointing along the turtle's heading. The triangle is drawn whether or not the pen
+    /// is down, and the turtle and pen are left unchanged.
+    fn stamp(&mut self, line: i32) -> Result<(), InterpreterError> {
+        self.draw_turtle("STAMP", line)
+    }
+
+    /// Helper fn: Draws the turtle as a triangle, for STAMP and a visible turtle
+    fn draw_turtle(&mut self, command: &str, line: i32) -> Result<(), InterpreterError> {
+        let (x, y) = self.current_coordinates();
+        let heading = self.current_position.direction.to_radians();
+        let (length, width) = (STAMP_LENGTH * self.scale, STAMP_WIDTH * self.scale);
+
+        // Image y coordinates increase downwards, and headings turn clockwise from up
+        let (forward_x, forward_y) = (heading.sin(), -heading.cos());
+        let (right_x, right_y) = (-forward_y, forward_x);
+
+        // The turtle sits at the centroid, a third of the way from the base to the tip
+        let tip = (
+            x + forward_x * length * 2.0 / 3.0,
+            y + forward_y * length * 2.0 / 3.0,
+        );
+        let base = (x - forward_x * length / 3.0, y - forward_y * length / 3.0);
+        let (half_x, half_y) = (right_x * width / 2.0, right_y * width / 2.0);
+        let left = (base.0 - half_x, base.1 - half_y);
+        let right = (base.0 + half_x, base.1 + half_y);
+
+        self.draw_between(tip, right, command, line)?;
+        self.draw_between(right, left, command, line)?;
+        self.draw_between(left, tip, command, line)?;
         Ok(())
     }
 
-    /// Creates a new procedure binding in the function map
-    fn create_procedure(&mut self, name: String, body: Rc<Vec<AstNode>>) {
-        // Add the procedure name and body to the func environment
-        self.func_environment.insert(name, body);
+    /// Helper fn: Draws a line between two points in image coordinates in the current pen
+    /// color and opacity, regardless of whether the pen is down. Returns the end point of
+    /// the line drawn, which may differ slightly as directions are whole degrees.
+    fn draw_between(
+        &mut self,
+        start: (f32, f32),
+        end: (f32, f32),
+        command: &str,
+        line: i32,
+    ) -> Result<(f32, f32), InterpreterError> {
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let direction = dx.atan2(-dy).to_degrees().round() as i32;
+        self.draw_segment(
+            start,
+            direction,
+            dx.hypot(dy),
+            (self.current_color, self.pen_opacity),
+            command,
+            line,
+        )
     }
 
-    /// Evaluates a procedure that has been referenced
-    // func_body has an exclusive borrow over the environment maps Vec<AstNode>. Below, we access
-    // self.evaluate(), which itself may mutate the map. As we assume procedures are never defined
-    // (but can be called) within another procedure, we can assure self.evaluate() will never
-    // mutate the map, and will at most read from it, in the case another procedure is referenced.
-    // As such, we take a Rc over the func_body to allow shared access to the map.
-    fn eval_procedure(
+    /// Floods the region of same-colored pixels containing the turtle with the current pen
+    /// color and opacity, bounded by pixels of any other color and the edges of the image.
+    /// The region is filled whether or not the pen is down, and the turtle is left unchanged.
+    /// If the turtle is outside the image, nothing is filled and a warning is recorded.
+    fn fill(&mut self, line: i32) -> Result<(), InterpreterError> {
+        // The region is all one color, so the pen blends over it in a single color
+        let (x, y) = self.current_coordinates();
+        let under = self.raster.get_pixel(x, y);
+        let color = self.current_color.blended_over(under, self.pen_opacity);
+        let Some(runs) = self.raster.flood_fill(x, y, color) else {
+            self.warnings.push(format!(
+                "[Line {}]: FILL ignored as the turtle is outside the image.",
+                line
+            ));
+            return Ok(());
+        };
+
+        // Pixels are centred on whole coordinates, so each run is widened by half a pixel
+        // at either end to cover its first and last pixels
+        for (row, first, last) in runs {
+            let start = (first as f32 - 0.5, row as f32);
+            let length = (last - first + 1) as f32;
+            self.draw_image_line(start, 90, length, color.to_color(), "FILL", line)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the pen color drawn at the given image coordinates, or None if nothing has
+    /// been drawn there. Points outside the image are treated as undrawn.
+    pub fn pixel_color(&self, x: f32, y: f32) -> Option<PenColor> {
+        self.raster.get_pixel(x, y)
+    }
+
+    /// Helper fn: Draws a line in image coordinates in the given pen color and opacity,
+    /// charging it against the ink budget and recording it in the raster.
+    /// Returns the end point of the line.
+    fn draw_segment(
         &mut self,
-        name_ref: &String,
-        args: &Vec<AstNode>,
+        start: (f32, f32),
+        direction: i32,
+        length: f32,
+        (color, opacity): (PenColor, f32),
+        command: &str,
+        line: i32,
+    ) -> Result<(f32, f32), InterpreterError> {
+        if opacity >= 1.0 {
+            let end =
+                self.draw_image_line(start, direction, length, color.to_color(), command, line)?;
+            self.raster.draw_line(start, end, color);
+            return Ok(end);
+        }
+
+        // A translucent line blends over whatever each pixel already shows, so it is painted
+        // in runs of pixels sharing a blended color, but still charged and recorded once
+        self.check_drawable(start, length, command, line)?;
+        self.spend_ink(length.abs(), line)?;
+        let end = get_end_coordinates(start.0, start.1, direction, length);
+        for (run_start, run_end, run_color) in self.raster.blend_line(start, end, color, opacity) {
+            let run_length = (run_end.0 - run_start.0).hypot(run_end.1 - run_start.1);
+            self.paint_line(
+                run_start,
+                direction,
+                run_length.copysign(length),
+                run_color.to_color(),
+                command,
+                line,
+            )?;
+        }
+        self.segments.push((start, end));
+        Ok(end)
+    }
+
+    /// Helper fn: Draws a line in image coordinates, charging it against the ink budget but
+    /// leaving the raster unchanged. Returns the end point of the line.
+    fn draw_image_line(
+        &mut self,
+        start: (f32, f32),
+        direction: i32,
+        length: f32,
+        color: Color,
+        command: &str,
+        line: i32,
+    ) -> Result<(f32, f32), InterpreterError> {
+        self.check_drawable(start, length, command, line)?;
+        self.spend_ink(length.abs(), line)?;
+        let end = self.paint_line(start, direction, length, color, command, line)?;
+        self.segments.push((start, end));
+        Ok(end)
+    }
+
+    /// Helper fn: Checks that a line starting at the given image coordinates can be drawn.
+    /// unsvg reports only that it could not draw lines with non-finite coordinates, so they
+    /// are caught first to explain why.
+    fn check_drawable(
+        &self,
+        start: (f32, f32),
+        length: f32,
+        command: &str,
         line: i32,
     ) -> Result<(), InterpreterError> {
-        // Eval the args to bind the values
-        self.evaluate(args).with_context(|| {
-            format!(
-                "[Line {}]: Failed to bind provided arguments to {}'s parameters.\n",
-                line, name_ref
-            )
-        })?;
+        let values = [start.0, start.1, length];
+        let reason = if values.iter().any(|value| value.is_nan()) {
+            Some("its coordinates are not a number (NaN). Check for NaN values in the program.")
+        } else if values.iter().any(|value| value.is_infinite()) {
+            Some("its coordinates are infinite. Check for infinite values in the program.")
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => Err(InterpreterError::DrawLineError(
+                format!("[Line {}]: Failed to draw line for {}:", line, command),
+                String::from(reason),
+            )),
+            None => Ok(()),
+        }
+    }
 
-        // Evaluate body of procedure
-        if let Some(func_body) = self.func_environment.get_mut(name_ref) {
-            let mut func_body_rc = Rc::clone(func_body);
-            self.evaluate(func_body_rc.borrow_mut()).with_context(|| {
-                format!(
-                    "[Line {}]: Failed to evaluate body of procedure {}.\n",
-                    line, name_ref
+    /// Helper fn: Draws a line on the image alone, without charging or recording it.
+    /// Returns the end point of the line.
+    fn paint_line(
+        &mut self,
+        start: (f32, f32),
+        direction: i32,
+        length: f32,
+        color: Color,
+        command: &str,
+        line: i32,
+    ) -> Result<(f32, f32), InterpreterError> {
+        self.image
+            .draw_simple_line(start.0, start.1, direction, length, color)
+            .map_err(|error| {
+                InterpreterError::DrawLineError(
+                    format!(
+                        "[Line {}]: Failed to draw line for {} due to UNSVG error:",
+                        line, command
+                    ),
+                    error.to_string(),
                 )
-            })?;
-        } else {
-            return Err(InterpreterError::InvalidProcedureRef(format!(
-                "[Line {}]: Referenced Procedure {} does not exist.",
-                line, name_ref
-            )));
+            })
+    }
+
+    /// Helper fn: Charges a line of the given length in image pixels against the ink budget
+    fn spend_ink(&mut self, length: f32, line: i32) -> Result<(), InterpreterError> {
+        self.drawn_pixels = self.drawn_pixels.saturating_add(length.ceil() as u64);
+        match self.max_drawn_pixels {
+            Some(max) if self.drawn_pixels > max => {
+                Err(InterpreterError::InkBudgetExceeded(format!(
+                    "[Line {}]: Drawing budget of {} pixels exceeded.",
+                    line, max
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Helper fn: Counts a loop iteration or procedure call against the step limit
+    fn take_step(&mut self, line: i32) -> Result<(), InterpreterError> {
+        self.steps += 1;
+        match self.max_steps {
+            Some(max) if self.steps > max => Err(InterpreterError::StepLimitExceeded(format!(
+                "[Line {}]: Step limit of {} loop iterations and procedure calls exceeded.",
+                line, max
+            ))),
+            _ => Ok(()),
         }
+    }
+
+    /// Helper fn: Returns the turtle's current coordinates in the image
+    fn current_coordinates(&self) -> (f32, f32) {
+        (
+            self.current_position.x_coordinate,
+            self.current_position.y_coordinate,
+        )
+    }
 
+    /// Draws a polyline through every position the turtle has occupied, including moves made
+    /// with the pen up, in the path debug color. The turtle and pen are left unchanged.
+    fn show_path(&mut self, line: i32) -> Result<(), InterpreterError> {
+        for i in 1..self.path_history.len() {
+            let (start_x, start_y) = self.path_history[i - 1];
+            let (end_x, end_y) = self.path_history[i];
+            let (dx, dy) = (end_x - start_x, end_y - start_y);
+            let length = dx.hypot(dy);
+            if length == 0.0 {
+                continue;
+            }
+
+            // Headings are measured clockwise from straight up, where y decreases
+            let direction = dx.atan2(-dy).to_degrees().round() as i32;
+            self.draw_segment(
+                (start_x, start_y),
+                direction,
+                length,
+                (PATH_COLOR, 1.0),
+                "SHOWPATH",
+                line,
+            )?;
+        }
         Ok(())
     }
 
-    /// Evaluates an arithmetic expression
-    fn arith_expr(
-        &mut self,
-        operator: &ArithOp,
-        left: &AstNode,
-        right: &AstNode,
-        line: i32,
-    ) -> Result<f32, InterpreterError> {
-        let left_val = self.eval_numeric_expression(left, line).with_context(|| {
-            format!(
-                "[Line {}]: Failed to evaluate first argument to operator '{}",
-                line, operator
-            )
-        })?;
+    /// Draws the x and y axes through the centre of the image in the axes debug color, with
+    /// tick marks every AXES_TICK_SPACING turtle pixels. The turtle and pen are left unchanged.
+    pub fn draw_axes(&mut self) -> Result<(), InterpreterError> {
+        self.axes(0)
+    }
 
-        let right_val = self.eval_numeric_expression(right, line).with_context(|| {
-            format!(
-                "[Line {}]: Failed to evaluate second argument to operator '{}",
-                line, operator
-            )
-        })?;
+    /// Draws the coordinate axes overlay for the AXES command
+    fn axes(&mut self, line: i32) -> Result<(), InterpreterError> {
+        let (width, height) = self.image.get_dimensions();
+        let (width, height) = (width as f32, height as f32);
+        let (center_x, center_y) = (width / 2.0, height / 2.0);
+        let stroke = (AXES_COLOR, 1.0);
 
-        match operator {
-            ArithOp::ADD => Ok(left_val + right_val),
-            ArithOp::SUB => Ok(left_val - right_val),
-            ArithOp::MUL => Ok(left_val * right_val),
-            ArithOp::DIV => Ok(left_val / right_val),
+        self.draw_segment((0.0, center_y), 90, width, stroke, "AXES", line)?;
+        self.draw_segment((center_x, 0.0), 180, height, stroke, "AXES", line)?;
+
+        // Ticks are placed outward from the centre, so one always marks the origin
+        let spacing = AXES_TICK_SPACING * self.scale;
+        let half_tick = AXES_TICK_LENGTH * self.scale / 2.0;
+        let num_ticks = (width.max(height) / 2.0 / spacing) as i32;
+        for i in -num_ticks..=num_ticks {
+            let offset = i as f32 * spacing;
+            let (tick_x, tick_y) = (center_x + offset, center_y + offset);
+            if (0.0..=width).contains(&tick_x) {
+                let start = (tick_x, center_y - half_tick);
+                self.draw_segment(start, 180, 2.0 * half_tick, stroke, "AXES", line)?;
+            }
+            if (0.0..=height).contains(&tick_y) {
+                let start = (center_x - half_tick, tick_y);
+                self.draw_segment(start, 90, 2.0 * half_tick, stroke, "AXES", line)?;
+            }
         }
+        Ok(())
     }
 
-    /// Evaluates a comparison expression
-    fn comp_expr(
+    /// Helper fn: Moves the turtle along an absolute direction, drawing a line if the pen is down.
+    /// Moves past the edge of the image are handled according to the boundary mode.
+    fn move_turtle(
         &mut self,
-        operator: &CompOp,
-        left: &AstNode,
-        right: &AstNode,
+        direction: i32,
+        num_pixels: f32,
+        command: &str,
         line: i32,
-    ) -> Result<bool, InterpreterError> {
-        // If we're dealing with LT or GT, check arguments are numeric
-        match operator {
-            CompOp::LT => {
-                if !left.is_numeric() || !right.is_numeric() {
-                    return Err(InterpreterError::TypeError(format!(
-                        "[Line {}]: Arguments to LT operator must evaluate to numbers.\n",
-                        line
-                    )));
-                }
+    ) -> Result<(), InterpreterError> {
+        match self.boundary_mode {
+            BoundaryMode::Window => self.move_straight(direction, num_pixels, command, line)?,
+            BoundaryMode::Fence => {
+                let (x, y) = self.current_coordinates();
+                let end = get_end_coordinates(x, y, direction, num_pixels * self.scale);
+                self.check_in_bounds(end, command, line)?;
+                self.move_straight(direction, num_pixels, command, line)?;
             }
-            CompOp::GT => {
-                if !left.is_numeric() || !right.is_numeric() {
-                    return Err(InterpreterError::TypeError(format!(
-                        "[Line {}]: Arguments to LT operator must evaluate to numbers.\n",
-                        line
-                    )));
+            BoundaryMode::Wrap => self.move_wrapping(direction, num_pixels, command, line)?,
+        }
+        self.total_distance += num_pixels.abs();
+        self.path_history.push(self.current_coordinates());
+
+        Ok(())
+    }
+
+    /// Helper fn: Moves the turtle along an absolute direction, drawing a line if the pen is down.
+    /// Lines wider than a pixel are drawn as parallel 1px lines centred on the turtle's path.
+    fn move_straight(
+        &mut self,
+        direction: i32,
+        num_pixels: f32,
+        command: &str,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let start = self.current_coordinates();
+        let length = num_pixels * self.scale;
+        if self.currently_drawing {
+            let num_lines = (self.current_pen_width * self.scale).round().max(1.0) as usize;
+            let dashes = self.dashes(length);
+
+            for i in 0..num_lines {
+                let offset = i as f32 - (num_lines - 1) as f32 / 2.0;
+                let line_start = if offset == 0.0 {
+                    start
+                } else {
+                    get_end_coordinates(start.0, start.1, direction + 90, offset)
+                };
+                for &(distance, dash_length) in &dashes {
+                    self.draw_segment(
+                        point_along(line_start, direction, distance),
+                        direction,
+                        dash_length,
+                        (self.current_color, self.pen_opacity),
+                        command,
+                        line,
+                    )?;
                 }
             }
-            _ => {},
-        };
+        }
+
+        (
+            self.current_position.x_coordinate,
+            self.current_position.y_coordinate,
+        ) = get_end_coordinates(start.0, start.1, direction, length);
+        Ok(())
+    }
+
+    /// Helper fn: Splits a line of the given length in image pixels into the dashes drawn by
+    /// the pen, as (distance from the start of the line, length) pairs. Negative lengths give
+    /// negative distances, for lines drawn backwards. Solid pens draw a single dash.
+    fn dashes(&self, length: f32) -> Vec<(f32, f32)> {
+        let Some((on, off)) = self.dash_pattern else {
+            return vec![(0.0, length)];
+        };
+        let (on, off) = (on * self.scale, off * self.scale);
+        let (total, sign) = (length.abs(), length.signum());
+
+        let mut dashes = Vec::new();
+        let mut distance = 0.0;
+        loop {
+            dashes.push((distance * sign, on.min(total - distance) * sign));
+            distance += on + off;
+            if distance >= total {
+                break;
+            }
+        }
+        dashes
+    }
+
+    /// Helper fn: Moves the turtle along an absolute direction as if the image were a torus. On
+    /// reaching an edge, the turtle reappears at the opposite edge and carries on for the rest
+    /// of the distance, drawing each part of the path if the pen is down. Once the path has
+    /// crossed MAX_WRAP_CROSSINGS edges, the rest of it is skipped and the turtle placed at its
+    /// end, so very long moves finish.
+    fn move_wrapping(
+        &mut self,
+        direction: i32,
+        num_pixels: f32,
+        command: &str,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        // Backward moves are made as forward moves in the opposite direction
+        let direction = if num_pixels < 0.0 {
+            direction + 180
+        } else {
+            direction
+        };
+        let (width, height) = self.image.get_dimensions();
+        let (width, height) = (width as f32, height as f32);
+        // Unit step along the direction, measured as unsvg does from straight up, clockwise.
+        // get_end_coordinates rounds its result, which is too coarse for a unit step.
+        let radians = (direction as f32 - 90.0).to_radians();
+        let (step_x, step_y) = (radians.cos(), radians.sin());
+
+        (
+            self.current_position.x_coordinate,
+            self.current_position.y_coordinate,
+        ) = self.wrapped(self.current_coordinates());
+
+        let mut remaining = num_pixels.abs() * self.scale;
+        for _ in 0..MAX_WRAP_CROSSINGS {
+            if remaining <= 0.0 {
+                return Ok(());
+            }
+
+            // Distance along the path to the edges it is heading towards
+            let (x, y) = self.current_coordinates();
+            let to_edge = |position: f32, step: f32, size: f32| {
+                if step > 0.0 {
+                    (size - position) / step
+                } else if step < 0.0 {
+                    -position / step
+                } else {
+                    f32::INFINITY
+                }
+            };
+            let to_x_edge = to_edge(x, step_x, width);
+            let to_y_edge = to_edge(y, step_y, height);
+            let distance = remaining.min(to_x_edge).min(to_y_edge);
+
+            if distance > 0.0 {
+                self.move_straight(direction, distance / self.scale, command, line)?;
+            }
+            remaining -= distance;
+
+            // Reappear at the opposite side of any edge reached
+            if remaining > 0.0 {
+                let (mut x, mut y) = self.current_coordinates();
+                if distance == to_x_edge {
+                    x = if step_x > 0.0 { 0.0 } else { width };
+                }
+                if distance == to_y_edge {
+                    y = if step_y > 0.0 { 0.0 } else { height };
+                }
+                (
+                    self.current_position.x_coordinate,
+                    self.current_position.y_coordinate,
+                ) = (x, y);
+            }
+        }
+
+        // The rest of the path is left undrawn, and the turtle lands where it would have ended
+        let end = point_along(self.current_coordinates(), direction, remaining);
+        (
+            self.current_position.x_coordinate,
+            self.current_position.y_coordinate,
+        ) = self.wrapped(end);
+        Ok(())
+    }
+
+    /// Helper fn: Raises an OutOfBounds error if a point in image coordinates lies outside the
+    /// image. Points on its edges are in bounds.
+    fn check_in_bounds(
+        &self,
+        (x, y): (f32, f32),
+        command: &str,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let (width, height) = self.image.get_dimensions();
+        if (0.0..=width as f32).contains(&x) && (0.0..=height as f32).contains(&y) {
+            return Ok(());
+        }
+        Err(InterpreterError::OutOfBounds(format!(
+            "[Line {}]: {} would move the turtle outside the image, to ({}, {}).",
+            line,
+            command,
+            x / self.scale - self.margin,
+            y / self.scale - self.margin
+        )))
+    }
+
+    /// Helper fn: Returns a point in image coordinates wrapped around into the image
+    fn wrapped(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (width, height) = self.image.get_dimensions();
+        (x.rem_euclid(width as f32), y.rem_euclid(height as f32))
+    }
+
+    /// Helper fn: Returns where the turtle lands when moved exactly to a point in image
+    /// coordinates, which is wrapped around into the image in the Wrap boundary mode
+    fn landing_point(&self, point: (f32, f32)) -> (f32, f32) {
+        match self.boundary_mode {
+            BoundaryMode::Wrap => self.wrapped(point),
+            BoundaryMode::Window | BoundaryMode::Fence => point,
+        }
+    }
+
+    /// Helper fn: Moves the turtle in a straight line to a point in image coordinates, drawing
+    /// a line if the pen is down, without changing its heading
+    fn move_turtle_to(
+        &mut self,
+        target: (f32, f32),
+        command: &str,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let (x, y) = self.current_coordinates();
+        let (dx, dy) = (target.0 - x, target.1 - y);
+        if dx != 0.0 || dy != 0.0 {
+            let direction = dx.atan2(-dy).to_degrees().round() as i32;
+            self.move_turtle(direction, dx.hypot(dy) / self.scale, command, line)?;
+        }
+
+        // Land exactly on the target, as the line's direction is rounded to whole degrees
+        let target = self.landing_point(target);
+        (
+            self.current_position.x_coordinate,
+            self.current_position.y_coordinate,
+        ) = target;
+        if let Some(last) = self.path_history.last_mut() {
+            *last = target;
+        }
+        Ok(())
+    }
+
+    /// Evaluates If statement
+    fn if_statement(
+        &mut self,
+        condition: &AstNode,
+        body: &Vec<AstNode>,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let condition_is_true = self
+            .eval_logic_expression(condition, line)
+            .with_context(|| format!("[Line {}]: Invalid IF statement condition.\n", line))?;
+        if condition_is_true {
+            self.evaluate(body)
+                .with_context(|| format!("[Line {}]: Invalid IF statement condition.\n", line))?;
+        }
+        Ok(())
+    }
+
+    /// Evaluates If-else statement
+    fn if_else_statement(
+        &mut self,
+        condition: &AstNode,
+        then_body: &Vec<AstNode>,
+        else_body: &Vec<AstNode>,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let condition_is_true = self
+            .eval_logic_expression(condition, line)
+            .with_context(|| format!("[Line {}]: Invalid IFELSE statement condition.\n", line))?;
+        let body = if condition_is_true {
+            then_body
+        } else {
+            else_body
+        };
+        self.evaluate(body).with_context(|| {
+            format!(
+                "[Line {}]: Invalid expression in the body of the IFELSE statement.\n",
+                line
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Evaluates while statement
+    fn while_statement(
+        &mut self,
+        condition: &AstNode,
+        body: &Vec<AstNode>,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        while self
+            .eval_logic_expression(condition, line)
+            .with_context(|| format!("[Line {}]: Invalid WHILE statement condition.\n", line))?
+        {
+            self.take_step(line)?;
+            self.evaluate(body).with_context(|| {
+                format!(
+                    "[Line {}]: Invalid expression in the body of the WHILE statement.\n",
+                    line
+                )
+            })?;
+            if self.stopping {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates repeat statement
+    fn repeat_statement(
+        &mut self,
+        count: &AstNode,
+        body: &Vec<AstNode>,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let count = self
+            .eval_numeric_expression(count, line)
+            .with_context(|| format!("[Line {}]: Invalid REPEAT statement count.\n", line))?;
+
+        if count < 0.0 || count.fract() != 0.0 {
+            return Err(InterpreterError::TypeError(format!(
+                "[Line {}]: REPEAT count must be a non-negative whole number, got {}.\n",
+                line, count
+            )));
+        }
+
+        for _ in 0..count as u64 {
+            self.take_step(line)?;
+            self.evaluate(body).with_context(|| {
+                format!(
+                    "[Line {}]: Invalid expression in the body of the REPEAT statement.\n",
+                    line
+                )
+            })?;
+            if self.stopping {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates for statement
+    fn for_statement(
+        &mut self,
+        var: &str,
+        (start, end, step): (&AstNode, &AstNode, &AstNode),
+        body: &Vec<AstNode>,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let start = self
+            .eval_numeric_expression(start, line)
+            .with_context(|| format!("[Line {}]: Invalid FOR statement start.\n", line))?;
+        let end = self
+            .eval_numeric_expression(end, line)
+            .with_context(|| format!("[Line {}]: Invalid FOR statement end.\n", line))?;
+        let step = self
+            .eval_numeric_expression(step, line)
+            .with_context(|| format!("[Line {}]: Invalid FOR statement step.\n", line))?;
+
+        if step == 0.0 {
+            return Err(InterpreterError::InvalidArgument(format!(
+                "[Line {}]: FOR step must not be zero.",
+                line
+            )));
+        }
+
+        // Each value is computed from the start, so repeated additions do not accumulate error
+        for i in 0_u64.. {
+            let value = start + i as f32 * step;
+            if (step > 0.0 && value > end) || (step < 0.0 && value < end) {
+                break;
+            }
+            self.take_step(line)?;
+            self.bind(var.to_string(), Value::Float(value));
+            self.evaluate(body).with_context(|| {
+                format!(
+                    "[Line {}]: Invalid expression in the body of the FOR statement.\n",
+                    line
+                )
+            })?;
+            if self.stopping {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates for-each statement
+    fn for_each_statement(
+        &mut self,
+        var: &str,
+        values: &[AstNode],
+        body: &Vec<AstNode>,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let values = values
+            .iter()
+            .map(|value| {
+                self.eval_numeric_expression(value, line)
+                    .with_context(|| format!("[Line {}]: Invalid FOREACH value.\n", line))
+            })
+            .collect::<Result<Vec<f32>, _>>()?;
+
+        for value in values {
+            self.take_step(line)?;
+            self.bind(var.to_string(), Value::Float(value));
+            self.evaluate(body).with_context(|| {
+                format!(
+                    "[Line {}]: Invalid expression in the body of the FOREACH statement.\n",
+                    line
+                )
+            })?;
+            if self.stopping {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets drawing state
+    fn set_drawing_status(&mut self, new_drawing_status: bool) {
+        self.currently_drawing = new_drawing_status;
+    }
+
+    /// Sets pen color
+    fn set_pen_color(&mut self, value: &AstNode, line: i32) -> Result<(), InterpreterError> {
+        let color = self.eval_palette_index(value, "PENCOLOR", line)?;
+
+        // Procedure colors take precedence while debugging by procedure
+        if !(self.color_by_proc && self.proc_depth > 0) {
+            self.current_color = PenColor::Indexed(color);
+        }
+        Ok(())
+    }
+
+    /// Sets the background color and repaints the image in it, covering everything drawn so
+    /// far. The turtle and pen are left unchanged.
+    fn set_background(&mut self, value: &AstNode, line: i32) -> Result<(), InterpreterError> {
+        self.background = Some(self.eval_palette_index(value, "SETBACKGROUND", line)?);
+        self.clear_image()
+    }
+
+    /// Helper fn: Evaluates a palette color index, handling indices outside the palette
+    /// according to the pen color mode
+    fn eval_palette_index(
+        &mut self,
+        value: &AstNode,
+        command: &str,
+        line: i32,
+    ) -> Result<usize, InterpreterError> {
+        let float_val = self
+            .eval_numeric_expression(value, line)
+            .with_context(|| format!("[Line {}]: Invalid argument to {}.\n", line, command))?;
+
+        // Colors must be whole numbers, whichever the pen color mode
+        if float_val.is_nan() || float_val.fract() != 0.0 && float_val.is_finite() {
+            return Err(InterpreterError::FractionalPenColor(float_val.to_string()));
+        }
+
+        // Check bounds before casting to an int color
+        let num_colors = COLORS.len() as f32;
+        let color = match self.pen_color_mode {
+            _ if float_val.is_infinite() => None,
+            PenColorMode::Strict => Some(float_val).filter(|c| (0.0..num_colors).contains(c)),
+            PenColorMode::Clamp => Some(float_val.clamp(0.0, num_colors - 1.0)),
+            PenColorMode::Modulo => Some(float_val.rem_euclid(num_colors)),
+        };
+
+        color
+            .map(|color| color as usize)
+            .ok_or_else(|| InterpreterError::InvalidPenColor(float_val.to_string()))
+    }
+
+    /// Sets pen color from red, green and blue channels
+    fn set_pen_rgb(&mut self, channels: [&AstNode; 3], line: i32) -> Result<(), InterpreterError> {
+        let mut rgb = [0; 3];
+        for (channel, value) in rgb.iter_mut().zip(channels) {
+            let float_val = self
+                .eval_numeric_expression(value, line)
+                .with_context(|| format!("[Line {}]: Invalid argument to SETRGB.\n", line))?;
+
+            if !(0.0..=255.0).contains(&float_val) || float_val.fract() != 0.0 {
+                return Err(InterpreterError::InvalidArgument(format!(
+                    "[Line {}]: {} is not a valid RGB channel. Enter an integer between 0 and 255.",
+                    line, float_val
+                )));
+            }
+            *channel = float_val as u8;
+        }
+
+        // Procedure colors take precedence while debugging by procedure
+        if !(self.color_by_proc && self.proc_depth > 0) {
+            self.current_color = PenColor::Rgb(rgb[0], rgb[1], rgb[2]);
+        }
+        Ok(())
+    }
+
+    /// Resets the interpreter to the state of a newly constructed one, for programs which
+    /// draw several independent scenes. RESETALL clears:
+    /// - all variables
+    /// - all procedure definitions, so only procedures defined after RESETALL can be called;
+    ///   calls to earlier procedures fail at runtime with InvalidProcedureRef
+    /// - all pen presets, marks and color-by-procedure assignments
+    /// - the turtle, which returns to the centre facing up, pen up and hidden, in the default
+    ///   color at full opacity, with a solid 1px pen
+    /// - the distance travelled and path history, as reported by TOTALDIST and SHOWPATH
+    /// - the background color, so later clears leave the image black
+    /// - the image, only if `clear_image` is set
+    ///
+    /// Settings supplied by the embedder (overflow mode, margin, supersampling,
+    /// color-by-procedure, tracing) and the angle units are kept, as are the ink spent against
+    /// any ink budget, the steps taken against any step limit and the trace recorded so far.
+    fn reset_all(&mut self, clear_image: bool) -> Result<(), InterpreterError> {
+        self.background = None;
+        if clear_image {
+            self.clear_image()?;
+        }
+
+        self.environment.iter_mut().for_each(HashMap::clear);
+        self.func_environment.clear();
+        self.pen_presets.clear();
+        self.marks.clear();
+        self.proc_colors.clear();
+        self.return_home();
+        self.current_color = PenColor::Indexed(self.default_color);
+        self.pen_opacity = 1.0;
+        self.current_pen_width = 1.0;
+        self.dash_pattern = None;
+        self.currently_drawing = false;
+        self.turtle_visible = false;
+        self.total_distance = 0.0;
+        self.path_history = vec![self.current_coordinates()];
+        Ok(())
+    }
+
+    /// Moves the turtle back to the centre of the image and faces it up. A line is drawn back
+    /// to the centre if the pen is down, and the drawing is otherwise left intact.
+    fn home(&mut self, line: i32) -> Result<(), InterpreterError> {
+        let (width, height) = self.image.get_dimensions();
+        let center = (width as f32 / 2.0, height as f32 / 2.0);
+        self.move_turtle_to(center, "HOME", line)?;
+        self.current_position.direction = 0.0;
+        Ok(())
+    }
+
+    /// Blanks the image and returns the turtle to the centre facing up, starting a new drawing
+    /// without forgetting variables, procedures or the pen. The path history is restarted,
+    /// while the distance travelled and ink spent are kept. Any background color is repainted.
+    fn clear_screen(&mut self) -> Result<(), InterpreterError> {
+        self.clear_image()?;
+        self.return_home();
+        self.path_history = vec![self.current_coordinates()];
+        Ok(())
+    }
+
+    /// Helper fn: Replaces the image with a blank one of the same size, as unsvg images cannot
+    /// be cleared in place, and forgets the lines drawn on it
+    fn clear_image(&mut self) -> Result<(), InterpreterError> {
+        let (width, height) = self.image.get_dimensions();
+        *self.image = Image::new(width, height);
+        self.raster = Raster::new(width, height);
+        self.segments.clear();
+        self.paint_background()
+    }
+
+    /// Helper fn: Paints the whole image in the background color, if one has been set, as one
+    /// line through the middle of each row of pixels. The background is recorded in the raster
+    /// but, unlike drawn lines, is not charged against the ink budget or exported as G-code.
+    fn paint_background(&mut self) -> Result<(), InterpreterError> {
+        let Some(index) = self.background else {
+            return Ok(());
+        };
+        let color = PenColor::Indexed(index);
+        let (width, height) = self.image.get_dimensions();
+        for row in 0..height {
+            self.image
+                .draw_simple_line(0.0, row as f32 + 0.5, 90, width as f32, color.to_color())
+                .map_err(|error| {
+                    InterpreterError::DrawLineError(
+                        String::from("Failed to paint background due to UNSVG error:"),
+                        error.to_string(),
+                    )
+                })?;
+        }
+        self.raster.fill(color);
+        Ok(())
+    }
+
+    /// Helper fn: Moves the turtle to the centre of the image facing up, without drawing
+    fn return_home(&mut self) {
+        let (width, height) = self.image.get_dimensions();
+        self.current_position = Position {
+            x_coordinate: width as f32 / 2.0,
+            y_coordinate: height as f32 / 2.0,
+            direction: 0.0,
+        };
+    }
+
+    /// Sets pen opacity
+    fn set_pen_opacity(&mut self, value: &AstNode, line: i32) -> Result<(), InterpreterError> {
+        let opacity = self
+            .eval_numeric_expression(value, line)
+            .with_context(|| format!("[Line {}]: Invalid argument to SETOPACITY.\n", line))?;
+
+        if !(0.0..=1.0).contains(&opacity) {
+            return Err(InterpreterError::InvalidArgument(format!(
+                "[Line {}]: {} is not a valid opacity. Enter a number between 0 and 1.",
+                line, opacity
+            )));
+        }
+        self.pen_opacity = opacity;
+        Ok(())
+    }
+
+    /// Sets pen width
+    fn set_pen_width(&mut self, value: &AstNode, line: i32) -> Result<(), InterpreterError> {
+        let width = self
+            .eval_numeric_expression(value, line)
+            .with_context(|| format!("[Line {}]: Invalid argument to SETPENWIDTH.\n", line))?;
+
+        if !(width > 0.0 && width <= MAX_PEN_WIDTH) {
+            return Err(InterpreterError::InvalidArgument(format!(
+                "[Line {}]: {} is not a valid pen width. Enter a positive number up to {}.",
+                line, width, MAX_PEN_WIDTH
+            )));
+        }
+        self.current_pen_width = width;
+        Ok(())
+    }
+
+    /// Sets pen dash pattern
+    fn set_pen_dash(
+        &mut self,
+        on: &AstNode,
+        off: &AstNode,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let on = self
+            .eval_numeric_expression(on, line)
+            .with_context(|| format!("[Line {}]: Invalid dash length to SETDASH.\n", line))?;
+        let off = self
+            .eval_numeric_expression(off, line)
+            .with_context(|| format!("[Line {}]: Invalid gap length to SETDASH.\n", line))?;
+
+        if on <= 0.0 || off < 0.0 {
+            return Err(InterpreterError::InvalidArgument(format!(
+                "[Line {}]: SETDASH requires a positive dash length and a gap of at least 0, got {} and {}.",
+                line, on, off
+            )));
+        }
+        self.dash_pattern = if off == 0.0 { None } else { Some((on, off)) };
+        Ok(())
+    }
+
+    /// Stores the current pen configuration under the given name
+    fn save_pen(&mut self, name: &str) {
+        let state = PenState {
+            color: self.current_color,
+            opacity: self.pen_opacity,
+            width: self.current_pen_width,
+            dash_pattern: self.dash_pattern,
+        };
+        self.pen_presets.insert(name.to_string(), state);
+    }
+
+    /// Restores the pen configuration stored under the given name
+    fn load_pen(&mut self, name: &str, line: i32) -> Result<(), InterpreterError> {
+        match self.pen_presets.get(name) {
+            Some(state) => {
+                self.current_color = state.color;
+                self.pen_opacity = state.opacity;
+                self.current_pen_width = state.width;
+                self.dash_pattern = state.dash_pattern;
+                Ok(())
+            }
+            None => Err(InterpreterError::InvalidPenPreset(format!(
+                "[Line {}]: Pen preset '{}' has not been saved.",
+                line, name
+            ))),
+        }
+    }
+
+    /// Stores the turtle's current position and heading under the given name
+    fn mark(&mut self, name: &str) {
+        let (x, y) = self.current_coordinates();
+        let heading = self.current_position.direction;
+        self.marks.insert(name.to_string(), (x, y, heading));
+    }
+
+    /// Moves the turtle back to the position stored under the given name, drawing a line if
+    /// the pen is down, and restores the heading stored with it
+    fn go_mark(&mut self, name: &str, line: i32) -> Result<(), InterpreterError> {
+        let Some(&(x, y, heading)) = self.marks.get(name) else {
+            return Err(InterpreterError::InvalidMark(format!(
+                "[Line {}]: Mark '{}' has not been set.",
+                line, name
+            )));
+        };
+        self.move_turtle_to((x, y), "GOMARK", line)?;
+        self.current_position.direction = heading;
+        Ok(())
+    }
+
+    /// Sets the position/orientation of the pen. SETX and SETY move the turtle in a straight
+    /// line, drawing it if the pen is down.
+    fn set_position(
+        &mut self,
+        update_type: &PenPos,
+        value: &AstNode,
+        line: i32,
+    ) -> Result<(), InterpreterError> {
+        let val = self
+            .eval_numeric_expression(value, line)
+            .with_context(|| format!("[Line {}]: Invalid argument to {}.\n", line, update_type))?;
+        let coordinate = (val + self.margin) * self.scale;
+        match update_type {
+            PenPos::SETX => {
+                let target = (coordinate, self.current_position.y_coordinate);
+                self.move_turtle_to(target, "SETX", line)?;
+            }
+            PenPos::SETY => {
+                let target = (self.current_position.x_coordinate, coordinate);
+                self.move_turtle_to(target, "SETY", line)?;
+            }
+            PenPos::SETHEADING => self.current_position.direction = self.angle_unit.to_degrees(val),
+            PenPos::TURN => self.current_position.direction += self.angle_unit.to_degrees(val),
+        }
+
+        Ok(())
+    }
+
+    /// Moves the turtle in a straight line to the given coordinates, drawing it if the pen is
+    /// down. The turtle's heading is left unchanged.
+    fn set_pos(&mut self, x: &AstNode, y: &AstNode, line: i32) -> Result<(), InterpreterError> {
+        let x_val = self
+            .eval_numeric_expression(x, line)
+            .with_context(|| format!("[Line {}]: Invalid x provided to SETPOS.\n", line))?;
+        let y_val = self
+            .eval_numeric_expression(y, line)
+            .with_context(|| format!("[Line {}]: Invalid y provided to SETPOS.\n", line))?;
+
+        let target = (
+            (x_val + self.margin) * self.scale,
+            (y_val + self.margin) * self.scale,
+        );
+        self.move_turtle_to(target, "SETPOS", line)
+    }
+
+    /// Creates a binding for each procedure defined at the top level of the program before it
+    /// runs, so procedures may be called before their definitions
+    fn hoist_procedures(&mut self, ast: &[AstNode]) {
+        for node in ast {
+            if let AstNode::Procedure { name, body, .. } = node {
+                self.create_procedure(String::from(name), Rc::clone(body));
+            }
+        }
+    }
+
+    /// Creates a new procedure binding in the function map
+    fn create_procedure(&mut self, name: String, body: Rc<Vec<AstNode>>) {
+        // Add the procedure name and body to the func environment
+        self.func_environment.insert(name, body);
+    }
+
+    /// Evaluates a procedure that has been referenced, returning the value it OUTPUTs, if any
+    // The body is looked up once per call, and evaluated through its own Rc rather than a
+    // borrow of the map, as self.evaluate() needs mutable access to the interpreter. Cloning
+    // the Rc only increments a reference count, so the body AST itself is never copied.
+    fn eval_procedure(
+        &mut self,
+        name_ref: &String,
+        args: &[AstNode],
+        line: i32,
+    ) -> Result<Option<Value>, InterpreterError> {
+        // Eval the args in the caller's scope to bind the values
+        let scope = self.eval_arguments(args).with_context(|| {
+            format!(
+                "[Line {}]: Failed to bind provided arguments to {}'s parameters.\n",
+                line, name_ref
+            )
+        })?;
+
+        // Evaluate body of procedure
+        if let Some(func_body) = self.func_environment.get(name_ref).map(Rc::clone) {
+            self.take_step(line)?;
+
+            let saved_color = self.current_color;
+            if self.color_by_proc {
+                self.current_color = PenColor::Indexed(self.procedure_color(name_ref));
+            }
+
+            self.proc_depth += 1;
+            self.environment.push(scope);
+            let result = self.evaluate(&func_body);
+            self.environment.pop();
+            self.proc_depth -= 1;
+            self.stopping = false;
+            let output = self.output.take();
+
+            if self.color_by_proc {
+                self.current_color = saved_color;
+            }
+
+            result.with_context(|| {
+                format!(
+                    "[Line {}]: Failed to evaluate body of procedure {}.\n",
+                    line, name_ref
+                )
+            })?;
+            Ok(output)
+        } else {
+            Err(InterpreterError::InvalidProcedureRef(format!(
+                "[Line {}]: Referenced Procedure {} does not exist.",
+                line, name_ref
+            )))
+        }
+    }
+
+    /// Helper fn: Evaluates a procedure referenced as an expression, returning the value
+    /// it OUTPUTs
+    fn procedure_value(
+        &mut self,
+        name_ref: &String,
+        args: &[AstNode],
+        line: i32,
+    ) -> Result<Value, InterpreterError> {
+        self.eval_procedure(name_ref, args, line)?.ok_or_else(|| {
+            InterpreterError::TypeError(format!(
+                "[Line {}]: Procedure {} was used as a value, but did not OUTPUT one.",
+                line, name_ref
+            ))
+        })
+    }
+
+    /// Sets the value returned by the enclosing procedure, and returns from it as with STOP
+    fn output(&mut self, value: &AstNode, line: i32) -> Result<(), InterpreterError> {
+        let value = self
+            .eval_value(value, line)
+            .with_context(|| format!("[Line {}]: Invalid argument to OUTPUT.\n", line))?;
+        self.output = Some(value);
+        self.stopping = true;
+        Ok(())
+    }
+
+    /// Helper fn: Evaluates the MAKE statements binding a procedure's arguments to its
+    /// parameters, returning the bindings as the scope the procedure body runs in
+    fn eval_arguments(
+        &mut self,
+        args: &[AstNode],
+    ) -> Result<HashMap<String, Value>, InterpreterError> {
+        let mut scope = HashMap::with_capacity(args.len());
+        for arg in args {
+            let AstNode::MakeStmnt { var, expr, line } = arg else {
+                unreachable!("Parser binds procedure arguments with MAKE statements");
+            };
+            let value = self.make_value(var, expr, *line)?;
+            scope.insert(var.to_string(), value);
+        }
+        Ok(scope)
+    }
+
+    /// Helper fn: Returns the debug color of a procedure, assigning the next palette color
+    /// on its first invocation. Black is skipped, as it matches the default background.
+    fn procedure_color(&mut self, name: &str) -> usize {
+        let next_color = 1 + self.proc_colors.len() % (COLORS.len() - 1);
+        *self
+            .proc_colors
+            .entry(name.to_string())
+            .or_insert(next_color)
+    }
+
+    /// Evaluates an arithmetic expression
+    fn arith_expr(
+        &mut self,
+        operator: &ArithOp,
+        left: &AstNode,
+        right: &AstNode,
+        line: i32,
+    ) -> Result<f32, InterpreterError> {
+        let left_val = self.eval_numeric_expression(left, line).with_context(|| {
+            format!(
+                "[Line {}]: Failed to evaluate first argument to operator '{}",
+                line, operator
+            )
+        })?;
+
+        let right_val = self.eval_numeric_expression(right, line).with_context(|| {
+            format!(
+                "[Line {}]: Failed to evaluate second argument to operator '{}",
+                line, operator
+            )
+        })?;
+
+        let result = match operator {
+            ArithOp::DIV | ArithOp::MOD if right_val == 0.0 => {
+                return Err(InterpreterError::DivideByZero(format!(
+                    "[Line {}]: Cannot divide {} by zero with operator '{}'.",
+                    line, left_val, operator
+                )));
+            }
+            // Negative numbers have no real fractional powers, which powf reports as NaN
+            ArithOp::POW if left_val < 0.0 && right_val.fract() != 0.0 => {
+                return Err(InterpreterError::InvalidArgument(format!(
+                    "[Line {}]: Cannot raise negative number {} to fractional power {}.",
+                    line, left_val, right_val
+                )));
+            }
+            _ => operator.apply(left_val, right_val),
+        };
+
+        // Non-finite results would otherwise silently corrupt coordinates downstream
+        if result.is_finite() {
+            Ok(result)
+        } else if self.overflow_mode == OverflowMode::Saturate && result.is_infinite() {
+            Ok(if result > 0.0 { f32::MAX } else { f32::MIN })
+        } else {
+            Err(InterpreterError::ArithmeticOverflow(format!(
+                "[Line {}]: Result of operator '{}' on {} and {} is not a finite number ({}).",
+                line, operator, left_val, right_val, result
+            )))
+        }
+    }
+
+    /// Evaluates a trigonometric expression, with its operand in degrees
+    fn trig_expr(
+        &mut self,
+        function: &TrigFn,
+        operand: &AstNode,
+        line: i32,
+    ) -> Result<f32, InterpreterError> {
+        let angle = self
+            .eval_numeric_expression(operand, line)
+            .with_context(|| format!("[Line {}]: Invalid argument to '{}'", line, function))?;
+        Ok(function.apply(self.angle_unit.to_degrees(angle)))
+    }
+
+    /// Evaluates RANDOM, returning a random number from 0 up to (but excluding) its argument
+    fn random(&mut self, max: &AstNode, line: i32) -> Result<f32, InterpreterError> {
+        let max = self
+            .eval_numeric_expression(max, line)
+            .with_context(|| format!("[Line {}]: Invalid argument to 'RANDOM'", line))?;
+
+        if max <= 0.0 {
+            return Err(InterpreterError::InvalidArgument(format!(
+                "[Line {}]: RANDOM requires a positive upper bound, got {}.",
+                line, max
+            )));
+        }
+
+        // Rounding the product can reach max itself, which is excluded from the range
+        let value = self.rng.next_f32() * max;
+        Ok(if value < max { value } else { max.next_down() })
+    }
+
+    /// Evaluates a comparison expression
+    fn comp_expr(
+        &mut self,
+        operator: &CompOp,
+        left: &AstNode,
+        right: &AstNode,
+        line: i32,
+    ) -> Result<bool, InterpreterError> {
+        // If we're dealing with LT or GT, check arguments are numeric
+        match operator {
+            CompOp::LT if !left.is_numeric() || !right.is_numeric() => {
+                return Err(InterpreterError::TypeError(format!(
+                    "[Line {}]: Arguments to LT operator must evaluate to numbers.\n",
+                    line
+                )));
+            }
+            CompOp::GT if !left.is_numeric() || !right.is_numeric() => {
+                return Err(InterpreterError::TypeError(format!(
+                    "[Line {}]: Arguments to LT operator must evaluate to numbers.\n",
+                    line
+                )));
+            }
+            _ => {},
+        };
 
         // Choose evaluation path based on trait implementation
         let left_val = match left {
+            AstNode::IfVal { .. } | AstNode::ProcedureRef { .. } => {
+                self.eval_value(left, line).with_context(|| {
+                    format!(
+                        "[Line {}]: Failed to evaluate first argument to {}",
+                        line, operator
+                    )
+                })?
+            }
             _ if left.is_word() => match left {
                 AstNode::Word(word) => Value::Word(word.to_string()),
                 AstNode::IdentRef(word) => self.eval_ident_ref_as_val(word).with_context(|| {
@@ -688,6 +3246,14 @@ impl<'a> Interpreter<'a> {
         };
 
         let right_val = match right {
+            AstNode::IfVal { .. } | AstNode::ProcedureRef { .. } => {
+                self.eval_value(right, line).with_context(|| {
+                    format!(
+                        "[Line {}]: Failed to evaluate second argument to {}",
+                        line, operator
+                    )
+                })?
+            }
             _ if right.is_word() => match right {
                 AstNode::Word(word) => Value::Word(word.to_string()),
                 AstNode::IdentRef(word) => self.eval_ident_ref_as_val(word).with_context(|| {
@@ -722,19 +3288,70 @@ impl<'a> Interpreter<'a> {
             }
         };
 
-        // Check that both types match
+        // Values of different types are never equal, but cannot be ordered
         if discriminant(&left_val) != discriminant(&right_val) {
-            return Err(InterpreterError::TypeError(format!(
-                "[Line {}]: Arguments to {} do not have matching types",
-                line, operator
-            )));
+            return match operator {
+                CompOp::EQ => Ok(false),
+                CompOp::NE => Ok(true),
+                CompOp::LT | CompOp::GT => Err(InterpreterError::TypeError(format!(
+                    "[Line {}]: Arguments to {} do not have matching types",
+                    line, operator
+                ))),
+            };
         }
 
-        match operator {
-            CompOp::EQ => Ok(left_val == right_val),
-            CompOp::NE => Ok(left_val != right_val),
-            CompOp::LT => Ok(left_val < right_val),
-            CompOp::GT => Ok(left_val > right_val),
+        match operator {
+            CompOp::EQ => Ok(left_val == right_val),
+            CompOp::NE => Ok(left_val != right_val),
+            CompOp::LT => Ok(left_val < right_val),
+            CompOp::GT => Ok(left_val > right_val),
+        }
+    }
+
+    /// Evaluates the condition of an IFVAL expression, returning the branch it selects
+    fn if_val<'n>(
+        &mut self,
+        condition: &AstNode,
+        then_val: &'n AstNode,
+        else_val: &'n AstNode,
+        line: i32,
+    ) -> Result<&'n AstNode, InterpreterError> {
+        let condition_is_true = self
+            .eval_logic_expression(condition, line)
+            .with_context(|| format!("[Line {}]: Invalid IFVAL condition.\n", line))?;
+
+        Ok(if condition_is_true {
+            then_val
+        } else {
+            else_val
+        })
+    }
+
+    /// Evaluates any expression to its terminal value, choosing the evaluation path by node type
+    fn eval_value(&mut self, node: &AstNode, line: i32) -> Result<Value, InterpreterError> {
+        match node {
+            AstNode::Word(word) => Ok(Value::Word(word.to_string())),
+            AstNode::IdentRef(var) => self.eval_ident_ref_as_val(var),
+            AstNode::ProcedureRef {
+                name_ref,
+                args,
+                line,
+            } => self.procedure_value(name_ref, args, *line),
+            AstNode::IfVal {
+                condition,
+                then_val,
+                else_val,
+                line,
+            } => {
+                let chosen = self.if_val(condition, then_val, else_val, *line)?;
+                self.eval_value(chosen, *line)
+            }
+            _ if node.is_numeric() => Ok(Value::Float(self.eval_numeric_expression(node, line)?)),
+            _ if node.is_boolean() => Ok(Value::Bool(self.eval_logic_expression(node, line)?)),
+            _ => Err(InterpreterError::TypeError(format!(
+                "[Line {}]: Expected an expression which returns a value, received a statement.",
+                line
+            ))),
         }
     }
 
@@ -767,10 +3384,26 @@ impl<'a> Interpreter<'a> {
 
     fn query(&mut self, query_kind: &QueryKind) -> f32 {
         match query_kind {
-            QueryKind::XCOR => self.current_position.x_coordinate,
-            QueryKind::YCOR => self.current_position.y_coordinate,
-            QueryKind::HEADING => self.current_position.direction,
-            QueryKind::COLOR => self.current_color as f32,
+            QueryKind::XCOR => self.current_position.x_coordinate / self.scale - self.margin,
+            QueryKind::YCOR => self.current_position.y_coordinate / self.scale - self.margin,
+            QueryKind::HEADING => self
+                .angle_unit
+                .from_degrees(self.current_position.direction),
+            // RGB colors have no palette index, so are reported as -1
+            QueryKind::COLOR => self
+                .current_color
+                .palette_index()
+                .map_or(-1.0, |index| index as f32),
+            QueryKind::NUMCOLORS => COLORS.len() as f32,
+            QueryKind::TOTALDIST => self.total_distance,
+            QueryKind::PENWIDTH => self.current_pen_width,
+            QueryKind::PIXELHERE => {
+                let (x, y) = self.current_coordinates();
+                self.raster
+                    .get_pixel(x, y)
+                    .and_then(PenColor::palette_index)
+                    .map_or(-1.0, |index| index as f32)
+            }
         }
     }
 
@@ -779,13 +3412,28 @@ impl<'a> Interpreter<'a> {
         // A clone is necessary here as we access to the same value,
         // and a smart pointer is likely excessive
         let ident_clone = String::from(var);
+        self.bind(var.to_string(), Value::Word(ident_clone));
+    }
+
+    /// Helper fn: Binds a variable in the current scope
+    fn bind(&mut self, var: String, value: Value) {
         self.environment
-            .insert(var.to_string(), Value::Word(ident_clone));
+            .last_mut()
+            .expect("The global scope is never popped")
+            .insert(var, value);
+    }
+
+    /// Helper fn: Looks up a variable, from the current scope outward to the globals
+    fn lookup(&self, var: &str) -> Option<&Value> {
+        self.environment
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(var))
     }
 
     /// Returns a reference to an identifiers value
     fn eval_ident_ref(&mut self, var: &String) -> Result<&Value, InterpreterError> {
-        match self.environment.get(var) {
+        match self.lookup(var) {
             Some(value) => Ok(value),
             _ => Err(InterpreterError::InvalidVariableRef(var.to_string())),
         }
@@ -793,9 +3441,1196 @@ impl<'a> Interpreter<'a> {
 
     /// Returns a copy of the identifiers value
     fn eval_ident_ref_as_val(&mut self, var: &String) -> Result<Value, InterpreterError> {
-        match self.environment.get(var) {
+        match self.lookup(var) {
             Some(value) => Ok(value.clone()),
             _ => Err(InterpreterError::InvalidVariableRef(var.to_string())),
         }
     }
 }
+
+/// Returns the point a distance along an absolute direction from a start point, measured as
+/// unsvg does from straight up, clockwise. Unlike get_end_coordinates, the result is not
+/// rounded, so short distances stay accurate.
+fn point_along((x, y): (f32, f32), direction: i32, distance: f32) -> (f32, f32) {
+    if distance == 0.0 {
+        return (x, y);
+    }
+    let radians = (direction as f32 - 90.0).to_radians();
+    (x + radians.cos() * distance, y + radians.sin() * distance)
+}
+
+/// Returns the point at t (0..1) along a one-dimensional quadratic bezier curve
+fn quadratic_bezier(start: f32, control: f32, end: f32, t: f32) -> f32 {
+    (1.0 - t).powi(2) * start + 2.0 * (1.0 - t) * t * control + t.powi(2) * end
+}
+
+/// Returns the greatest common divisor of two numbers
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize_str;
+    use crate::parser::Parser;
+
+    /// Lexes and parses a program as rslogo does by default
+    fn parse(program: &str) -> Vec<AstNode> {
+        let tokens = tokenize_str(program, &HashMap::new(), "//").unwrap();
+        Parser::new().parse(tokens).unwrap()
+    }
+
+    /// Runs a program on the given image, returning the interpreter so its state can be read
+    fn run<'a>(image: &'a mut Image, program: &str) -> Interpreter<'a> {
+        let mut interpreter = Interpreter::new(image);
+        interpreter.run(&parse(program)).unwrap();
+        interpreter
+    }
+
+    /// Returns the value of a query once a program has run on a 100x100 image
+    fn query_after(program: &str, query_kind: QueryKind) -> f32 {
+        let mut image = Image::new(100, 100);
+        run(&mut image, program).query(&query_kind)
+    }
+
+    /// Returns the error a program fails with on a 100x100 image
+    fn run_err(program: &str) -> InterpreterError {
+        let mut image = Image::new(100, 100);
+        Interpreter::new(&mut image)
+            .run(&parse(program))
+            .err()
+            .unwrap()
+    }
+
+    #[test]
+    fn color_query_gives_palette_index_or_minus_one_for_rgb() {
+        assert_eq!(query_after("SETPENCOLOR \"3\n", QueryKind::COLOR), 3.0);
+        assert_eq!(
+            query_after("SETRGB \"255 \"128 \"0\n", QueryKind::COLOR),
+            -1.0
+        );
+    }
+
+    #[test]
+    fn penwidth_query_gives_width_set_by_setpenwidth() {
+        assert_eq!(query_after("", QueryKind::PENWIDTH), 1.0);
+        assert_eq!(query_after("SETPENWIDTH \"4\n", QueryKind::PENWIDTH), 4.0);
+    }
+
+    #[test]
+    fn lenient_run_reports_failing_statement_and_continues() {
+        let ast = parse("MAKE \"first \"1\nPENDOWN\nFORWARD :missing\nMAKE \"third \"3\n");
+        let mut image = Image::new(100, 100);
+        let mut interpreter = Interpreter::new(&mut image);
+        let (_, errors) = interpreter.run_lenient(&ast);
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("missing"));
+        assert_eq!(interpreter.lookup("first"), Some(&Value::Float(1.0)));
+        assert_eq!(interpreter.lookup("third"), Some(&Value::Float(3.0)));
+    }
+
+    #[test]
+    fn resetall_removes_variables_and_procedures() {
+        let program = "MAKE \"x \"1\nTO Step\nFORWARD \"1\nEND\nRESETALL\n";
+        let mut image = Image::new(100, 100);
+        let mut interpreter = run(&mut image, program);
+        assert_eq!(interpreter.lookup("x"), None);
+        assert!(interpreter.func_environment.is_empty());
+
+        let error = interpreter.run(&parse("FORWARD :x\n")).err().unwrap();
+        assert!(error.to_string().contains("Variable x does not exist."));
+    }
+
+    #[test]
+    fn axes_cross_at_the_centre_pixel() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "AXES\n");
+
+        let axis = Some(AXES_COLOR);
+        assert_eq!(interpreter.pixel_color(50.0, 50.0), axis);
+        // Along each axis on either side of the centre
+        assert_eq!(interpreter.pixel_color(5.0, 50.0), axis);
+        assert_eq!(interpreter.pixel_color(95.0, 50.0), axis);
+        assert_eq!(interpreter.pixel_color(50.0, 5.0), axis);
+        assert_eq!(interpreter.pixel_color(50.0, 95.0), axis);
+        assert_eq!(interpreter.pixel_color(40.0, 40.0), None);
+        assert_eq!(interpreter.position(), (50.0, 50.0));
+        assert!(!interpreter.is_pen_down());
+    }
+
+    #[test]
+    fn recursive_countdown_terminates_after_n_calls() {
+        // Each call moves by its own :n after the calls it makes have returned
+        let program = "TO Countdown \"n\nIF GT :n \"0 [\nFORWARD \"2\nCountdown - :n \"1\n\
+                       FORWARD :n\n]\nEND\nPENDOWN\nCountdown \"5\n";
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, program);
+
+        let lengths = interpreter
+            .drawn_segments()
+            .iter()
+            .map(|(start, end)| start.1 - end.1)
+            .collect::<Vec<_>>();
+        assert_eq!(lengths, [2.0, 2.0, 2.0, 2.0, 2.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(interpreter.position(), (50.0, 25.0));
+    }
+
+    #[test]
+    fn while_loop_runs_100000_iterations() {
+        let program = "MAKE \"count \"0\nWHILE LT :count \"100000 [\nADDASSIGN \"count \"1\n]\n";
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, program);
+        assert_eq!(interpreter.lookup("count"), Some(&Value::Float(100000.0)));
+    }
+
+    #[test]
+    fn drawat_keeps_heading_and_pen() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "PENDOWN\nSETHEADING \"45\nDRAWAT \"90 \"20\n");
+
+        assert_eq!(interpreter.heading(), 45.0);
+        assert!(interpreter.is_pen_down());
+        assert_eq!(
+            interpreter.drawn_segments(),
+            &[((50.0, 50.0), (70.0, 50.0))]
+        );
+        assert_eq!(interpreter.position(), (70.0, 50.0));
+    }
+
+    #[test]
+    fn color_by_proc_draws_each_procedure_in_its_own_color() {
+        let program = "TO Up\n    FORWARD \"10\nEND\nTO Across\n    RIGHT \"10\nEND\n\
+                       PENDOWN\nUp\nAcross\nBACK \"10\n";
+        let mut image = Image::new(100, 100);
+        let mut interpreter = Interpreter::new(&mut image);
+        interpreter.set_color_by_proc(true);
+        interpreter.run(&parse(program)).unwrap();
+
+        let up_color = interpreter.pixel_color(50.0, 45.0).unwrap();
+        let across_color = interpreter.pixel_color(55.0, 40.0).unwrap();
+        assert_ne!(up_color, across_color);
+        // The program's own color is used outside procedures
+        assert_eq!(
+            interpreter.pixel_color(60.0, 45.0),
+            Some(PenColor::Indexed(7))
+        );
+    }
+
+    #[test]
+    fn forwarddots_places_a_dot_every_spacing() {
+        let mut image = Image::new(200, 200);
+        let interpreter = run(&mut image, "FORWARDDOTS \"100 \"20\n");
+
+        // Dots are placed at the start, every 20 pixels and at the end, with the pen up
+        let dots = interpreter.drawn_segments();
+        assert_eq!(dots.len(), 6);
+        let dot_ys = dots.iter().map(|(start, _)| start.1).collect::<Vec<_>>();
+        assert_eq!(dot_ys, [100.0, 80.0, 60.0, 40.0, 20.0, 0.0]);
+        assert_eq!(interpreter.position(), (100.0, 0.0));
+    }
+
+    #[test]
+    fn forwarddots_rejects_spacing_below_a_pixel() {
+        for spacing in ["0.0000001", "0.5"] {
+            let ast = parse(&format!("FORWARDDOTS \"90 \"{}\n", spacing));
+            let mut image = Image::new(100, 100);
+            let error = Interpreter::new(&mut image).run(&ast).err().unwrap();
+            assert!(error
+                .to_string()
+                .contains("spacing must be at least one pixel"));
+        }
+    }
+
+    #[test]
+    fn arc_draws_at_most_one_segment_per_degree() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            "SETPENCOLOR \"1\nPENDOWN\nARC \"1000000000 \"90\n",
+        );
+        assert_eq!(interpreter.drawn_segments().len(), 90);
+
+        let ast = parse("ARC \"360 \"1000000000\n");
+        let mut image = Image::new(100, 100);
+        let error = Interpreter::new(&mut image).run(&ast).err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("ARC sweeps at most 3600 degrees"));
+    }
+
+    #[test]
+    fn overflowing_arithmetic_errors_unless_saturating() {
+        let ast = parse("MAKE \"x * \"1e30 \"1e30\n");
+
+        let mut image = Image::new(100, 100);
+        let error = Interpreter::new(&mut image).run(&ast).err().unwrap();
+        assert!(error.to_string().contains("is not a finite number (inf)"));
+
+        let mut image = Image::new(100, 100);
+        let mut interpreter = Interpreter::new(&mut image);
+        interpreter.set_overflow_mode(OverflowMode::Saturate);
+        interpreter.run(&ast).unwrap();
+        assert_eq!(interpreter.lookup("x"), Some(&Value::Float(f32::MAX)));
+    }
+
+    #[test]
+    fn loadpen_restores_every_saved_pen_setting() {
+        let saved = "SETRGB \"10 \"20 \"30\nSETOPACITY \"0.5\nSETPENWIDTH \"3\n\
+                     SETDASH \"4 \"2\n";
+        let changed = "SETPENCOLOR \"2\nSETOPACITY \"1\nSETPENWIDTH \"1\n\
+                       SETDASH \"4 \"0\n";
+        let program = format!("{}SAVEPEN \"style\n{}LOADPEN \"style\n", saved, changed);
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, &program);
+
+        assert_eq!(interpreter.current_color, PenColor::Rgb(10, 20, 30));
+        assert_eq!(interpreter.pen_opacity, 0.5);
+        assert_eq!(interpreter.current_pen_width, 3.0);
+        assert_eq!(interpreter.dash_pattern, Some((4.0, 2.0)));
+    }
+
+    #[test]
+    fn fill_colors_only_the_inside_of_a_closed_shape() {
+        let program = "PENDOWN\nREPEAT \"4 [\nFORWARD \"20\nTURN \"90\n]\n\
+                       PENUP\nFORWARD \"10\nRIGHT \"10\nSETPENCOLOR \"2\nFILL\n";
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, program);
+
+        let filled = Some(PenColor::Indexed(2));
+        assert_eq!(interpreter.pixel_color(60.0, 40.0), filled);
+        assert_eq!(interpreter.pixel_color(51.0, 31.0), filled);
+        // The outline and everything outside it are untouched
+        assert_eq!(
+            interpreter.pixel_color(60.0, 30.0),
+            Some(PenColor::Indexed(7))
+        );
+        assert_eq!(interpreter.pixel_color(80.0, 40.0), None);
+        assert_eq!(interpreter.pixel_color(60.0, 60.0), None);
+    }
+
+    #[test]
+    fn fill_over_a_large_area_exceeds_a_small_ink_budget() {
+        let ast = parse("FILL\n");
+        let mut image = Image::new(100, 100);
+        let mut interpreter = Interpreter::new(&mut image).with_ink_budget(500);
+
+        let error = interpreter.run(&ast).err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("Drawing budget of 500 pixels exceeded."));
+    }
+
+    #[test]
+    fn square_program_gives_four_gcode_draw_moves() {
+        let program = "PENDOWN\nREPEAT \"4 [\nFORWARD \"20\nTURN \"90\n]\n";
+        let mut image = Image::new(100, 100);
+        let gcode = run(&mut image, program).gcode(1.0);
+
+        let draws = gcode
+            .lines()
+            .filter(|line| line.starts_with("G1 X"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            draws,
+            [
+                "G1 X50.000 Y70.000",
+                "G1 X70.000 Y70.000",
+                "G1 X70.000 Y50.000",
+                "G1 X50.000 Y50.000"
+            ]
+        );
+        // The square is one continuous line, so the pen is lowered once
+        assert_eq!(gcode.matches("G1 Z").count(), 1);
+    }
+
+    #[test]
+    fn recursive_procedure_renders_as_the_equivalent_loop() {
+        let recursive = "TO Spiral \"len\nIF GT :len \"0 [\nFORWARD :len\nTURN \"90\n\
+                         Spiral - :len \"4\n]\nEND\nPENDOWN\nSpiral \"40\n";
+        let looped = "MAKE \"len \"40\nPENDOWN\nWHILE GT :len \"0 [\nFORWARD :len\n\
+                      TURN \"90\nMAKE \"len - :len \"4\n]\n";
+        let render = |program: &str| {
+            let mut image = Image::new(100, 100);
+            let interpreter = run(&mut image, program);
+            (
+                interpreter.raster_png_bytes(),
+                interpreter.drawn_segments().to_vec(),
+            )
+        };
+
+        let (bytes, segments) = render(looped);
+        assert_eq!(segments.len(), 10);
+        assert_eq!(render(recursive), (bytes, segments));
+    }
+
+    #[test]
+    fn overlapping_translucent_lines_blend_where_they_cross() {
+        // A red line right from the centre, then a blue line down through its middle
+        let program = "SETOPACITY \"0.5\nPENDOWN\nSETRGB \"255 \"0 \"0\nRIGHT \"20\n\
+                       PENUP\nLEFT \"10\nFORWARD \"10\nPENDOWN\nSETRGB \"0 \"0 \"255\nBACK \"20\n";
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, program);
+
+        assert_eq!(
+            interpreter.pixel_color(55.0, 50.0),
+            Some(PenColor::Rgb(128, 0, 0))
+        );
+        assert_eq!(
+            interpreter.pixel_color(60.0, 45.0),
+            Some(PenColor::Rgb(0, 0, 128))
+        );
+        assert_eq!(
+            interpreter.pixel_color(60.0, 50.0),
+            Some(PenColor::Rgb(64, 0, 128))
+        );
+    }
+
+    #[test]
+    fn loadpen_of_a_solid_pen_clears_the_dash_pattern() {
+        let program = "SAVEPEN \"solid\nSETDASH \"5 \"5\nLOADPEN \"solid\nPENDOWN\nFORWARD \"20\n";
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, program);
+
+        assert_eq!(interpreter.dash_pattern, None);
+        assert_eq!(interpreter.drawn_segments().len(), 1);
+    }
+
+    #[test]
+    fn margin_insets_a_drawing_touching_the_edge() {
+        let mut image = Image::new(100, 100);
+        let mut interpreter = Interpreter::new(&mut image);
+        interpreter.set_margin(10);
+        interpreter.run(&parse("PENDOWN\nSETY \"0\n")).unwrap();
+
+        // The line to the top edge of the drawing ends 10px inside the expanded image
+        assert_eq!(interpreter.raster.dimensions(), (120, 120));
+        assert_eq!(
+            interpreter.drawn_segments(),
+            &[((60.0, 60.0), (60.0, 10.0))]
+        );
+        assert!(interpreter.pixel_color(60.0, 10.0).is_some());
+        assert!(interpreter.pixel_color(60.0, 5.0).is_none());
+        assert_eq!(interpreter.query(&QueryKind::YCOR), 0.0);
+    }
+
+    #[test]
+    fn addassign_to_a_non_numeric_variable_is_a_type_error() {
+        let error_for = |preset: Value| {
+            let mut image = Image::new(100, 100);
+            let mut interpreter = Interpreter::new(&mut image);
+            interpreter.preset_variable("x", preset);
+            let error = interpreter
+                .run(&parse("\nADDASSIGN \"x \"1\n"))
+                .err()
+                .unwrap();
+            assert_eq!(error.code(), "E0302");
+            error.to_string()
+        };
+
+        assert_eq!(
+            error_for(Value::Bool(true)),
+            "[Line 2]: variable 'x' holds a boolean and cannot be used as a number here"
+        );
+        assert_eq!(
+            error_for(Value::parse("hello")),
+            "[Line 2]: variable 'x' holds a word and cannot be used as a number here"
+        );
+    }
+
+    #[test]
+    fn addassign_to_a_comparison_result_is_a_type_error() {
+        let ast = parse("MAKE \"b GT \"1 \"2\nADDASSIGN \"b \"1\n");
+        let mut image = Image::new(100, 100);
+        let error = Interpreter::new(&mut image).run(&ast).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "[Line 2]: variable 'b' holds a boolean and cannot be used as a number here"
+        );
+    }
+
+    #[test]
+    fn far_off_canvas_lines_are_drawn_up_to_the_edge() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "PENDOWN\nFORWARD \"1e9\nHOME\nSETX \"1e30\n");
+
+        assert!(interpreter.pixel_color(50.0, 0.0).is_some());
+        assert!(interpreter.pixel_color(99.0, 50.0).is_some());
+    }
+
+    #[test]
+    fn huge_wrapped_moves_finish_inside_the_image() {
+        for program in ["SETX \"1e30\n", "PENDOWN\nTURN \"30\nFORWARD \"1e9\n"] {
+            let mut image = Image::new(100, 100);
+            let mut interpreter = Interpreter::new(&mut image);
+            interpreter.set_boundary_mode(BoundaryMode::Wrap);
+            interpreter.run(&parse(program)).unwrap();
+
+            let (x, y) = interpreter.position();
+            assert!((0.0..100.0).contains(&x) && (0.0..100.0).contains(&y));
+        }
+    }
+
+    #[test]
+    fn pen_width_is_limited() {
+        assert_eq!(
+            query_after("SETPENWIDTH \"1000\n", QueryKind::PENWIDTH),
+            1000.0
+        );
+
+        for width in ["0", "1000.5", "1e9"] {
+            let ast = parse(&format!("SETPENWIDTH \"{}\nPENDOWN\nFORWARD \"10\n", width));
+            let mut image = Image::new(100, 100);
+            let error = Interpreter::new(&mut image).run(&ast).err().unwrap();
+            assert_eq!(error.code(), "E0308");
+            assert!(error
+                .to_string()
+                .contains("Enter a positive number up to 1000."));
+        }
+    }
+
+    #[test]
+    fn numcolors_example_draws_in_every_palette_color() {
+        assert_eq!(query_after("", QueryKind::NUMCOLORS), 16.0);
+
+        let mut image = Image::new(200, 200);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_06_numcolors.lg"),
+        );
+        assert_eq!(interpreter.lookup("c"), Some(&Value::Float(16.0)));
+        assert_eq!(interpreter.drawn_segments().len(), 16);
+        assert_eq!(interpreter.pen_color(), PenColor::Indexed(15));
+    }
+
+    #[test]
+    fn ifval_example_picks_the_branch_matching_the_condition() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_07_ifval.lg"),
+        );
+
+        // Forward 20 while x is positive, then back 40 once it is negative
+        assert_eq!(interpreter.lookup("m"), Some(&Value::Float(-1.0)));
+        assert_eq!(interpreter.position(), (50.0, 70.0));
+    }
+
+    #[test]
+    fn star_example_draws_closed_stars_of_equal_edges() {
+        let mut image = Image::new(400, 400);
+        let interpreter = run(&mut image, include_str!("../../logo_examples/6_09_star.lg"));
+
+        let segments = interpreter.drawn_segments();
+        assert_eq!(segments.len(), 5 + 7);
+        let distance = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).hypot(a.1 - b.1);
+        for (star, length) in [(&segments[..5], 80.0), (&segments[5..], 60.0)] {
+            for &(start, end) in star {
+                assert!((distance(start, end) - length).abs() < 1.0);
+            }
+            // Each star is one stroke finishing where it started
+            assert!(distance(star[0].0, star[star.len() - 1].1) < 2.0);
+        }
+
+        // A pentagram turns through 144 degrees at each point, where a pentagon turns 72
+        let heading = |(start, end): Segment| (end.1 - start.1).atan2(end.0 - start.0).to_degrees();
+        let turn = (heading(segments[1]) - heading(segments[0])).rem_euclid(360.0);
+        assert!((turn - 144.0).abs() < 1.0, "turned {}", turn);
+    }
+
+    #[test]
+    fn totaldist_example_draws_a_line_as_long_as_every_earlier_move() {
+        let mut image = Image::new(200, 200);
+        let mut interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_16_totaldist.lg"),
+        );
+
+        assert_eq!(
+            interpreter.drawn_segments(),
+            [((100.0, 30.0), (170.0, 30.0))]
+        );
+        assert_eq!(interpreter.query(&QueryKind::TOTALDIST), 30.0 + 40.0 + 70.0);
+    }
+
+    #[test]
+    fn showpath_example_reveals_pen_up_travel() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_18_showpath.lg"),
+        );
+
+        // The square's pen-up sides, its drawn side and the final SETX all show in the path color
+        for (x, y) in [
+            (50.0, 30.0),
+            (70.0, 10.0),
+            (90.0, 30.0),
+            (70.0, 50.0),
+            (35.0, 50.0),
+        ] {
+            assert_eq!(
+                interpreter.pixel_color(x, y),
+                Some(PATH_COLOR),
+                "({}, {})",
+                x,
+                y
+            );
+        }
+        assert_eq!(interpreter.pixel_color(70.0, 30.0), None);
+        assert_eq!(interpreter.drawn_segments().len(), 1 + 5);
+    }
+
+    #[test]
+    fn pixelhere_example_senses_the_line_under_the_turtle() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_20_pixelhere.lg"),
+        );
+
+        assert_eq!(
+            interpreter.drawn_segments(),
+            [
+                ((50.0, 50.0), (50.0, 20.0)),
+                ((50.0, 30.0), (64.0, 30.0)),
+                ((74.0, 30.0), (74.0, 35.0)),
+            ]
+        );
+        assert_eq!(interpreter.lookup("below"), Some(&Value::Float(-1.0)));
+    }
+
+    #[test]
+    fn macros_example_splices_each_macro_in_place() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_21_macros.lg"),
+        );
+
+        assert_eq!(
+            interpreter.drawn_segments(),
+            [((50.0, 50.0), (50.0, 25.0)), ((70.0, 25.0), (70.0, 50.0))]
+        );
+        assert_eq!(interpreter.pen_color(), PenColor::Indexed(4));
+    }
+
+    #[test]
+    fn stop_example_returns_early_and_warns_of_unreachable_statements() {
+        let program = include_str!("../../logo_examples/6_24_stop.lg");
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, program);
+
+        // SIDE never goes back, and CAPPED only moves right for n = 1, 2 and 3
+        let segments = interpreter.drawn_segments();
+        assert_eq!(segments.len(), 1 + 3);
+        assert_eq!(segments[0], ((50.0, 50.0), (50.0, 30.0)));
+        assert_eq!(interpreter.position(), (65.0, 30.0));
+
+        let mut parser = Parser::new();
+        parser
+            .parse(tokenize_str(program, &HashMap::new(), "//").unwrap())
+            .unwrap();
+        assert_eq!(
+            parser.warnings(),
+            &["[Line 5]: 1 statement(s) after STOP will never be evaluated."]
+        );
+    }
+
+    #[test]
+    fn curveto_example_bends_towards_the_heading_and_leaves_along_its_tangent() {
+        let mut image = Image::new(200, 200);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_25_curveto.lg"),
+        );
+
+        let (line, curve) = interpreter.drawn_segments().split_last().unwrap();
+        assert_eq!(curve[0].0, (100.0, 100.0));
+        let near = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).hypot(a.1 - b.1) < 1.0;
+        assert!(near(curve[curve.len() - 1].1, (150.0, 100.0)));
+        // The control point lies 25 pixels up the starting heading, so the curve bulges up
+        assert!(curve
+            .iter()
+            .all(|&(_, (x, y))| (99.0..=151.0).contains(&x) && (75.0..=100.5).contains(&y)));
+        assert!(curve[curve.len() / 2].1 .1 < 90.0);
+
+        // The line leaves along the tangent from the control point to the end
+        let ((start_x, start_y), (end_x, end_y)) = *line;
+        assert!(near((start_x, start_y), (150.0, 100.0)));
+        let heading = (end_x - start_x).atan2(start_y - end_y).to_degrees();
+        assert!((heading - 50.0_f32.atan2(-25.0).to_degrees()).abs() < 1.0);
+        assert!(((end_x - start_x).hypot(end_y - start_y) - 20.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn pencolor_example_wraps_or_clamps_by_mode_and_errors_by_default() {
+        let ast = parse(include_str!("../../logo_examples/6_26_modulo_pencolor.lg"));
+        let colors_in_mode = |mode| {
+            let mut image = Image::new(100, 100);
+            let mut interpreter = Interpreter::new(&mut image);
+            interpreter.set_pen_color_mode(mode);
+            interpreter.run(&ast).unwrap();
+            [(50.0, 40.0), (50.0, 20.0)].map(|(x, y)| interpreter.pixel_color(x, y).unwrap())
+        };
+
+        assert_eq!(
+            colors_in_mode(PenColorMode::Modulo),
+            [PenColor::Indexed(15), PenColor::Indexed(0)]
+        );
+        assert_eq!(
+            colors_in_mode(PenColorMode::Clamp),
+            [PenColor::Indexed(0), PenColor::Indexed(15)]
+        );
+
+        let mut image = Image::new(100, 100);
+        let error = Interpreter::new(&mut image).run(&ast).err().unwrap();
+        assert_eq!(error.code(), "E0305");
+    }
+
+    #[test]
+    fn repeat_examples_draw_a_square_and_reject_fractional_counts() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_31_repeat.lg"),
+        );
+        assert_eq!(
+            interpreter.drawn_segments(),
+            [
+                ((50.0, 50.0), (50.0, 20.0)),
+                ((50.0, 20.0), (80.0, 20.0)),
+                ((80.0, 20.0), (80.0, 50.0)),
+                ((80.0, 50.0), (50.0, 50.0)),
+            ]
+        );
+
+        let ast = parse(include_str!(
+            "../../logo_examples/6_32_repeat_fraction_err.lg"
+        ));
+        let mut image = Image::new(100, 100);
+        let mut interpreter = Interpreter::new(&mut image);
+        let error = interpreter.run(&ast).err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("REPEAT count must be a non-negative whole number, got 2.5"));
+        assert!(interpreter.drawn_segments().is_empty());
+    }
+
+    #[test]
+    fn modulo_examples_give_non_negative_remainders_and_reject_zero() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_35_modulo.lg"),
+        );
+        assert_eq!(
+            interpreter.drawn_segments(),
+            [
+                ((50.0, 50.0), (50.0, 40.0)),
+                ((50.0, 40.0), (60.0, 40.0)),
+                ((60.0, 40.0), (60.0, 20.0)),
+                ((60.0, 20.0), (59.0, 20.0)),
+            ]
+        );
+
+        let ast = parse(include_str!("../../logo_examples/6_36_modulo_zero_err.lg"));
+        let mut image = Image::new(100, 100);
+        let error = Interpreter::new(&mut image).run(&ast).err().unwrap();
+        assert!(error.to_string().contains("by zero"), "{}", error);
+    }
+
+    #[test]
+    fn divide_by_zero_example_errors_after_the_valid_division() {
+        let ast = parse(include_str!(
+            "../../logo_examples/6_37_divide_by_zero_err.lg"
+        ));
+        let mut image = Image::new(100, 100);
+        let mut interpreter = Interpreter::new(&mut image);
+        let error = interpreter.run(&ast).err().unwrap();
+
+        assert_eq!(error.code(), "E0310");
+        assert!(error
+            .to_string()
+            .contains("[Line 5]: Cannot divide 5 by zero with operator '/'"));
+        assert_eq!(interpreter.drawn_segments(), [((50.0, 50.0), (50.0, 45.0))]);
+    }
+
+    #[test]
+    fn trig_example_draws_unit_lines_then_a_sine_wave() {
+        let mut image = Image::new(200, 200);
+        let interpreter = run(&mut image, include_str!("../../logo_examples/6_38_trig.lg"));
+
+        let (lines, wave) = interpreter.drawn_segments().split_at(2);
+        assert_eq!(
+            lines,
+            [
+                ((100.0, 100.0), (100.0, 90.0)),
+                ((100.0, 90.0), (110.0, 90.0))
+            ]
+        );
+        assert_eq!(wave.len(), 24);
+        for (i, &((start_x, start_y), (end_x, end_y))) in wave.iter().enumerate() {
+            let heading = (end_x - start_x).atan2(start_y - end_y).to_degrees();
+            let expected = 90.0 + 60.0 * (30.0 * i as f32).to_radians().sin();
+            assert!((heading - expected).abs() < 1.0, "{}: {}", i, heading);
+        }
+    }
+
+    #[test]
+    fn pow_examples_raise_to_whole_powers_and_reject_fractional_powers_of_negatives() {
+        let mut image = Image::new(300, 300);
+        let interpreter = run(&mut image, include_str!("../../logo_examples/6_39_pow.lg"));
+        // Up 1024 / 10, then back -8
+        let ends = interpreter
+            .drawn_segments()
+            .iter()
+            .map(|&(_, (x, y))| (x, y))
+            .collect::<Vec<_>>();
+        assert_eq!(ends.len(), 2);
+        for ((x, y), expected_y) in ends.into_iter().zip([150.0 - 102.4, 150.0 - 110.4]) {
+            assert_eq!(x, 150.0);
+            assert!((y - expected_y).abs() < 0.01, "{}", y);
+        }
+
+        let ast = parse(include_str!("../../logo_examples/6_40_pow_fraction_err.lg"));
+        let mut image = Image::new(100, 100);
+        let error = Interpreter::new(&mut image).run(&ast).err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("Cannot raise negative number -8 to fractional power 0.5"));
+    }
+
+    #[test]
+    fn negate_examples_negate_numbers_and_reject_booleans() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_41_negate.lg"),
+        );
+        assert_eq!(
+            interpreter.drawn_segments(),
+            [
+                ((50.0, 50.0), (50.0, 40.0)),
+                ((50.0, 40.0), (50.0, 45.0)),
+                ((50.0, 45.0), (65.0, 45.0)),
+            ]
+        );
+
+        let program = include_str!("../../logo_examples/6_42_negate_bool_err.lg");
+        let tokens = tokenize_str(program, &HashMap::new(), "//").unwrap();
+        let error = Parser::new().parse(tokens).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Arguments to 'NEG' will not return a float"));
+    }
+
+    #[test]
+    fn not_examples_flip_booleans_and_reject_numbers() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, include_str!("../../logo_examples/6_43_not.lg"));
+        // The loop stops once :done is set, after three steps rather than five
+        assert_eq!(interpreter.lookup("steps"), Some(&Value::Float(3.0)));
+        assert_eq!(interpreter.lookup("done"), Some(&Value::Bool(true)));
+        assert_eq!(interpreter.drawn_segments().len(), 1 + 3);
+        assert_eq!(interpreter.position(), (62.0, 40.0));
+
+        let program = include_str!("../../logo_examples/6_44_not_number_err.lg");
+        let tokens = tokenize_str(program, &HashMap::new(), "//").unwrap();
+        let error = Parser::new().parse(tokens).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Arguments to 'NOT' will not return a boolean"));
+    }
+
+    #[test]
+    fn bool_literals_example_takes_true_branches_and_skips_false_loops() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_45_bool_literals.lg"),
+        );
+
+        assert_eq!(interpreter.lookup("flag"), Some(&Value::Bool(true)));
+        assert_eq!(
+            interpreter.drawn_segments(),
+            [((50.0, 50.0), (50.0, 40.0)), ((50.0, 40.0), (60.0, 40.0))]
+        );
+
+        // Quoted, TRUE is only a word
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "MAKE \"flag \"TRUE\n");
+        assert_eq!(
+            interpreter.lookup("flag"),
+            Some(&Value::Word("TRUE".to_string()))
+        );
+    }
+
+    #[test]
+    fn procedure_scope_example_leaves_globals_unchanged() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_53_procedure_scope.lg"),
+        );
+
+        assert_eq!(
+            interpreter.drawn_segments(),
+            [
+                ((50.0, 50.0), (50.0, 30.0)),
+                ((50.0, 30.0), (70.0, 30.0)),
+                ((70.0, 30.0), (70.0, 60.0)),
+                ((70.0, 60.0), (70.0, 50.0)),
+                ((70.0, 50.0), (65.0, 50.0)),
+            ]
+        );
+        assert_eq!(interpreter.lookup("len"), Some(&Value::Float(10.0)));
+        assert_eq!(interpreter.lookup("gap"), Some(&Value::Float(5.0)));
+    }
+
+    #[test]
+    fn forward_reference_example_calls_procedures_defined_later() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            include_str!("../../logo_examples/6_61_forward_reference.lg"),
+        );
+
+        // A square back to the start, then the zigzag of Zig and Zag calling each other
+        assert_eq!(
+            interpreter.drawn_segments(),
+            [
+                ((50.0, 50.0), (50.0, 30.0)),
+                ((50.0, 30.0), (70.0, 30.0)),
+                ((70.0, 30.0), (70.0, 50.0)),
+                ((70.0, 50.0), (50.0, 50.0)),
+                ((50.0, 50.0), (50.0, 30.0)),
+                ((50.0, 30.0), (35.0, 30.0)),
+                ((35.0, 30.0), (35.0, 20.0)),
+                ((35.0, 20.0), (30.0, 20.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn values_of_different_types_are_never_equal() {
+        let turn_if = |comparison: &str| {
+            let program = format!("IF {} \"5 \"hello [\n TURN \"90\n]\n", comparison);
+            query_after(&program, QueryKind::HEADING)
+        };
+        assert_eq!(turn_if("EQ"), 0.0);
+        assert_eq!(turn_if("NE"), 90.0);
+        assert_eq!(
+            query_after("IF EQ \"5 \"5 [\n TURN \"90\n]\n", QueryKind::HEADING),
+            90.0
+        );
+
+        // LT and GT still require two numbers
+        let error = run_err("MAKE \"w \"hello\nIF LT \"5 :w [\n TURN \"90\n]\n");
+        assert_eq!(error.code(), "E0301");
+    }
+
+    #[test]
+    fn ifelse_evaluates_the_then_body_only_if_its_condition_holds() {
+        let turn_if_else = |condition: &str| {
+            let program = format!("IFELSE {} [\n TURN \"90\n] [\n TURN \"180\n]\n", condition);
+            query_after(&program, QueryKind::HEADING)
+        };
+        assert_eq!(turn_if_else("TRUE"), 90.0);
+        assert_eq!(turn_if_else("FALSE"), 180.0);
+    }
+
+    #[test]
+    fn ifelse_chain_evaluates_the_first_body_whose_condition_holds() {
+        let turn_for = |x: i32| {
+            let program = format!(
+                "MAKE \"x \"{}\n\
+                 IFELSE LT :x \"0 [\n TURN \"90\n]\n\
+                 IFELSE LT :x \"10 [\n TURN \"180\n]\n\
+                 [\n TURN \"270\n]\n",
+                x
+            );
+            query_after(&program, QueryKind::HEADING)
+        };
+        assert_eq!(turn_for(-5), 90.0);
+        assert_eq!(turn_for(5), 180.0);
+        assert_eq!(turn_for(15), 270.0);
+    }
+
+    #[test]
+    fn abs_max_and_min_may_be_used_within_arithmetic() {
+        let program = "TURN ABS \"-4\nTURN MAX \"3 \"7\nTURN + MIN \"2 \"5 \"10\n";
+        assert_eq!(query_after(program, QueryKind::HEADING), 4.0 + 7.0 + 12.0);
+    }
+
+    #[test]
+    fn sqrt_rejects_negative_numbers() {
+        assert_eq!(query_after("TURN SQRT \"16\n", QueryKind::HEADING), 4.0);
+
+        // Negative numbers have no real square root
+        let error = run_err("TURN SQRT \"-4\n");
+        assert!(error
+            .to_string()
+            .contains("Cannot take the square root of negative number -4."));
+    }
+
+    #[test]
+    fn for_binds_each_value_from_start_to_end_by_step() {
+        let heading = |program: &str| query_after(program, QueryKind::HEADING);
+
+        // The body sees each value of :i in turn
+        assert_eq!(heading("FOR \"i \"10 \"40 \"10 [\n TURN :i\n]\n"), 100.0);
+        // A negative step counts down, ending on the last value reached
+        assert_eq!(heading("FOR \"i \"3 \"1 \"-1 [\n SETHEADING :i\n]\n"), 1.0);
+        assert_eq!(
+            heading("FOR \"i \"3 \"1 \"-1 [\n TURN * :i \"10\n]\n"),
+            60.0
+        );
+        // A step moving away from the end leaves the body unevaluated
+        assert_eq!(heading("FOR \"i \"1 \"3 \"-1 [\n TURN \"90\n]\n"), 0.0);
+
+        // A zero step would never reach the end
+        let error = run_err("FOR \"i \"1 \"3 \"0 [\n TURN \"90\n]\n");
+        assert!(error.to_string().contains("FOR step must not be zero."));
+    }
+
+    #[test]
+    fn arc_turns_about_a_centre_to_the_turtles_side() {
+        // A full circle returns the turtle to where it started, facing the same way
+        let mut image = Image::new(200, 200);
+        let interpreter = run(&mut image, "PENDOWN\nARC \"50 \"360\n");
+        let (x, y) = interpreter.position();
+        assert!((x - 100.0).abs() < 1e-3 && (y - 100.0).abs() < 1e-3);
+        assert_eq!(interpreter.heading(), 0.0);
+        assert!(interpreter.drawn_segments().len() > 8);
+
+        // A quarter turn to the right ends beside the centre of the circle, which lies to the
+        // turtle's right. With the pen up, nothing is drawn.
+        let mut image = Image::new(200, 200);
+        let interpreter = run(&mut image, "ARC \"50 \"90\n");
+        let (x, y) = interpreter.position();
+        assert!((x - 150.0).abs() < 1e-3 && (y - 50.0).abs() < 1e-3);
+        assert_eq!(interpreter.heading(), 90.0);
+        assert!(interpreter.drawn_segments().is_empty());
+
+        let error = run_err("ARC \"0 \"90\n");
+        assert!(error
+            .to_string()
+            .contains("ARC requires a positive radius, got 0."));
+    }
+
+    #[test]
+    fn clearscreen_blanks_the_image_and_returns_home() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "PENDOWN\nLEFT \"30\nCLEARSCREEN\n");
+        assert_eq!(interpreter.position(), (50.0, 50.0));
+        assert!(interpreter.drawn_segments().is_empty());
+        assert_eq!(interpreter.pixel_color(30.0, 50.0), None);
+    }
+
+    #[test]
+    fn home_returns_to_the_centre_drawing_only_with_the_pen_down() {
+        // With the pen up, the turtle teleports home
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "TURN \"45\nRIGHT \"30\nHOME\n");
+        assert_eq!(interpreter.position(), (50.0, 50.0));
+        assert_eq!(interpreter.heading(), 0.0);
+        assert!(interpreter.drawn_segments().is_empty());
+
+        // With the pen down, a line is drawn back to the centre
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "PENDOWN\nRIGHT \"30\nHOME\n");
+        assert_eq!(
+            interpreter.drawn_segments()[1],
+            ((80.0, 50.0), (50.0, 50.0))
+        );
+    }
+
+    #[test]
+    fn wide_pens_draw_parallel_lines_centred_on_the_path() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "PENDOWN\nSETPENWIDTH \"3\nFORWARD \"10\n");
+        assert_eq!(interpreter.drawn_segments().len(), 3);
+        assert_eq!(
+            interpreter.drawn_segments()[1],
+            ((50.0, 50.0), (50.0, 40.0))
+        );
+        assert_eq!(interpreter.position(), (50.0, 40.0));
+    }
+
+    #[test]
+    fn setrgb_sets_a_color_outside_the_palette() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "SETRGB \"255 \"128 \"0\n");
+        assert_eq!(interpreter.pen_color(), PenColor::Rgb(255, 128, 0));
+
+        // Channels outside 0..=255 are rejected
+        assert!(run_err("SETRGB \"256 \"128 \"0\n")
+            .to_string()
+            .contains("256 is not a valid RGB channel"));
+    }
+
+    #[test]
+    fn label_draws_each_character_as_segments_without_moving() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "LABEL \"HI\n");
+        assert_eq!(interpreter.drawn_segments().len(), 6);
+        assert_eq!(interpreter.position(), (50.0, 50.0));
+    }
+
+    #[test]
+    fn background_is_repainted_on_clearing_without_using_ink() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "SETBACKGROUND \"1\nCLEARSCREEN\n");
+        assert_eq!(
+            interpreter.pixel_color(0.0, 0.0),
+            Some(PenColor::Indexed(1))
+        );
+        assert_eq!(
+            interpreter.pixel_color(99.0, 99.0),
+            Some(PenColor::Indexed(1))
+        );
+        assert!(interpreter.drawn_segments().is_empty());
+    }
+
+    #[test]
+    fn setx_draws_a_line_only_with_the_pen_down() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "SETX \"80\n");
+        assert_eq!(interpreter.position(), (80.0, 50.0));
+        assert!(interpreter.drawn_segments().is_empty());
+
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "PENDOWN\nSETX \"80\n");
+        assert_eq!(interpreter.position(), (80.0, 50.0));
+        assert_eq!(interpreter.drawn_segments(), [((50.0, 50.0), (80.0, 50.0))]);
+    }
+
+    #[test]
+    fn setpos_ends_exactly_on_the_target_keeping_the_heading() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "SETPOS \"10 \"20\n");
+        assert_eq!(interpreter.position(), (10.0, 20.0));
+        assert!(interpreter.drawn_segments().is_empty());
+
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, "PENDOWN\nSETPOS \"10 \"20\n");
+        assert_eq!(interpreter.position(), (10.0, 20.0));
+        assert_eq!(interpreter.heading(), 0.0);
+        assert_eq!(interpreter.drawn_segments().len(), 1);
+        assert_eq!(interpreter.drawn_segments()[0].0, (50.0, 50.0));
+    }
+
+    #[test]
+    fn angle_units_apply_to_angles_given_and_reported() {
+        let heading = |program: &str| {
+            let mut image = Image::new(100, 100);
+            run(&mut image, program).heading()
+        };
+
+        // In radians, a turn of PI is the same half turn as 180 degrees
+        assert_eq!(heading("TURN \"180\n"), 180.0);
+        assert!((heading("ANGLEUNITS RADIANS\nTURN PI\n") - 180.0).abs() < 1e-4);
+
+        // HEADING reports radians, so turning by it in degrees moves PI degrees further
+        let program =
+            "ANGLEUNITS RADIANS\nTURN PI\nMAKE \"h HEADING\nANGLEUNITS DEGREES\nTURN :h\n";
+        assert!((heading(program) - 180.0 - std::f32::consts::PI).abs() < 1e-4);
+
+        // Setting the unit up front acts as ANGLEUNITS does
+        let mut image = Image::new(100, 100);
+        let mut interpreter = Interpreter::new(&mut image);
+        interpreter.set_angle_unit(AngleUnit::Radians);
+        interpreter
+            .run(&parse("TURN \"1.5707964\nSETHEADING HEADING\n"))
+            .unwrap();
+        assert!((interpreter.heading() - 90.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn foreach_binds_each_value_of_its_list_in_turn() {
+        let mut image = Image::new(100, 100);
+        let interpreter = run(
+            &mut image,
+            "PENDOWN\nFOREACH \"v [ \"10 \"20 + \"10 \"20 ] [\n FORWARD :v\n]\n",
+        );
+
+        // One segment per value, each as long as its value
+        let lengths = interpreter
+            .drawn_segments()
+            .iter()
+            .map(|((_, start_y), (_, end_y))| start_y - end_y)
+            .collect::<Vec<_>>();
+        assert_eq!(lengths, [10.0, 20.0, 30.0]);
+    }
+
+    #[test]
+    fn gomark_returns_to_the_marked_position_and_heading() {
+        let program =
+            "PENDOWN\nFORWARD \"10\nMARK \"corner\nTURN \"90\nFORWARD \"20\nGOMARK \"corner\n";
+        let mut image = Image::new(100, 100);
+        let interpreter = run(&mut image, program);
+
+        // Drawing back, as the pen is down
+        assert_eq!(interpreter.position(), (50.0, 40.0));
+        assert_eq!(interpreter.heading(), 0.0);
+        assert_eq!(
+            interpreter.drawn_segments()[2],
+            ((70.0, 40.0), (50.0, 40.0))
+        );
+
+        // Only marked names can be returned to
+        let error = run_err("GOMARK \"nowhere\n");
+        assert!(error
+            .to_string()
+            .contains("Mark 'nowhere' has not been set."));
+    }
+
+    #[test]
+    fn dashed_lines_leave_gaps_but_move_the_whole_distance() {
+        // Counts the pixels drawn along the line up from the centre
+        let drawn_pixels = |program: &str| {
+            let mut image = Image::new(100, 100);
+            let interpreter = run(&mut image, program);
+            assert_eq!(interpreter.position(), (50.0, 10.0));
+            (10..=50)
+                .filter(|&y| interpreter.pixel_color(50.0, y as f32).is_some())
+                .count()
+        };
+
+        let solid = drawn_pixels("PENDOWN\nFORWARD \"40\n");
+        assert!(drawn_pixels("PENDOWN\nSETDASH \"5 \"5\nFORWARD \"40\n") < solid);
+        // A gap of 0 draws solid lines again
+        assert_eq!(
+            drawn_pixels("PENDOWN\nSETDASH \"5 \"0\nFORWARD \"40\n"),
+            solid
+        );
+    }
+
+    #[test]
+    fn raster_png_bytes_decode_to_the_drawn_pixels() {
+        let mut image = Image::new(100, 50);
+        let bytes = run(&mut image, "PENDOWN\nFORWARD \"20\n").raster_png_bytes();
+
+        // The line is drawn in white from the centre of the image, on a black background
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (100, 50));
+        let pixel = |x: usize, y: usize| &pixels[(y * 100 + x) * 3..][..3];
+        assert_eq!(pixel(50, 15), [255, 255, 255]);
+        assert_eq!(pixel(10, 10), [0, 0, 0]);
+    }
+
+    #[test]
+    fn macro_errors_point_to_the_use() {
+        let error = run_err("DEFINE Zero [ \"0 ]\n\nMAKE \"x / \"1 Zero\n");
+        assert_eq!(error.code(), "E0310");
+        assert!(error.to_string().contains("[Line 3]"), "{}", error);
+    }
+}
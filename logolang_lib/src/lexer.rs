@@ -7,266 +7,892 @@
 
 use crate::logolang_errors::LexerError;
 use anyhow::Result;
-use std::collections::VecDeque;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::collections::{HashMap, VecDeque};
 
 /// Represents the set of valid tokens in RSLOGO.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     MAKEOP,
     ARITHOP,
+    TRIGFN,
+    ROUNDFN,
+    NEGATE,
+    ABS,
+    SQRT,
+    NOTOP,
+    BOOL,
     COMPOP,
     BOOLOP,
     DIRECTION,
+    DRAWAT,
+    FORWARDDOTS,
+    STAR,
+    CURVETO,
+    ARC,
+    LABEL,
+    FILL,
+    STAMP,
     IDENT,
     IDENTREF,
     ADDASSIGN,
     NUM,
     IFSTMNT,
+    IFELSE,
+    IFVAL,
     WHILESTMNT,
+    REPEAT,
+    FOR,
+    FOREACH,
     LPAREN,
     RPAREN,
+    LGROUP,
+    RGROUP,
     PENSTATUS,
+    TURTLEVISIBILITY,
+    ANGLEUNITS,
+    ANGLEUNIT,
     PENCOLOR,
+    PENRGB,
+    PENOPACITY,
+    PENWIDTH,
+    PENDASH,
     PENPOS,
+    SETPOS,
+    PENPRESET,
+    MARK,
+    BACKGROUND,
     QUERY,
+    RANDOM,
+    RESETALL,
+    CLEARSCREEN,
+    HOME,
+    SHOWPATH,
+    AXES,
     PROCSTART,
     PROCEND,
     PROCNAME,
+    STOP,
+    OUTPUT,
+    DEFINE,
 }
 
 /// Representation of a single tokens kind and value.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token {
     pub kind: TokenKind,
     pub value: String,
     pub line: i32,
+    /// Line used to tell statements apart. This is `line`, except for tokens from the later
+    /// lines of a multi-line macro body, which stay separate statements from the use site.
+    pub statement_line: i32,
+    /// Column the token starts at, in characters counting from 1
+    pub col: usize,
 }
 
 /// Converts an input string to a token.
 /// # Arguments
 ///
 /// * `input` - The input string to convert to a token.
+/// * `line_no` - The line of the input file the string was read from.
+/// * `col` - The column of the line the string starts at.
 ///
 /// # Returns
 ///
 /// A `Result` containing the converted token, or a `LexerError` if the input
 /// is not a valid token.
-fn to_token(input: &str, line_no: i32) -> Result<Token, LexerError> {
+fn to_token(input: &str, line_no: i32, col: usize) -> Result<Token, LexerError> {
+    let invalid = || LexerError::InvalidTokenError(String::from(input), line_no, col);
+
     match input {
         // Variable Binding
         "MAKE" => Ok(Token {
             kind: TokenKind::MAKEOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // Arith Binary Operations
         "+" => Ok(Token {
             kind: TokenKind::ARITHOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "-" => Ok(Token {
             kind: TokenKind::ARITHOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "*" => Ok(Token {
             kind: TokenKind::ARITHOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "/" => Ok(Token {
             kind: TokenKind::ARITHOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "%" | "MOD" => Ok(Token {
+            kind: TokenKind::ARITHOP,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "POW" => Ok(Token {
+            kind: TokenKind::ARITHOP,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "MIN" | "MAX" => Ok(Token {
+            kind: TokenKind::ARITHOP,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // Comparitive Operators
         "EQ" => Ok(Token {
             kind: TokenKind::COMPOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "NE" => Ok(Token {
             kind: TokenKind::COMPOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "GT" => Ok(Token {
             kind: TokenKind::COMPOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "LT" => Ok(Token {
             kind: TokenKind::COMPOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // Boolean Operators
         "AND" => Ok(Token {
             kind: TokenKind::BOOLOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "OR" => Ok(Token {
             kind: TokenKind::BOOLOP,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // Addition Assignment
         "ADDASSIGN" => Ok(Token {
             kind: TokenKind::ADDASSIGN,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // Directional Movement
         "FORWARD" => Ok(Token {
             kind: TokenKind::DIRECTION,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "BACK" => Ok(Token {
             kind: TokenKind::DIRECTION,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "RIGHT" => Ok(Token {
             kind: TokenKind::DIRECTION,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "LEFT" => Ok(Token {
             kind: TokenKind::DIRECTION,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "DRAWAT" => Ok(Token {
+            kind: TokenKind::DRAWAT,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "FORWARDDOTS" => Ok(Token {
+            kind: TokenKind::FORWARDDOTS,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "STAR" => Ok(Token {
+            kind: TokenKind::STAR,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "CURVETO" => Ok(Token {
+            kind: TokenKind::CURVETO,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "ARC" => Ok(Token {
+            kind: TokenKind::ARC,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "LABEL" => Ok(Token {
+            kind: TokenKind::LABEL,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "FILL" => Ok(Token {
+            kind: TokenKind::FILL,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "STAMP" => Ok(Token {
+            kind: TokenKind::STAMP,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // Pen Status
         "PENUP" => Ok(Token {
             kind: TokenKind::PENSTATUS,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "PENDOWN" => Ok(Token {
             kind: TokenKind::PENSTATUS,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Turtle visibility
+        "SHOWTURTLE" => Ok(Token {
+            kind: TokenKind::TURTLEVISIBILITY,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "HIDETURTLE" => Ok(Token {
+            kind: TokenKind::TURTLEVISIBILITY,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Angle units
+        "ANGLEUNITS" => Ok(Token {
+            kind: TokenKind::ANGLEUNITS,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "DEGREES" | "RADIANS" => Ok(Token {
+            kind: TokenKind::ANGLEUNIT,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "SETPENCOLOR" => Ok(Token {
             kind: TokenKind::PENCOLOR,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "SETBACKGROUND" => Ok(Token {
+            kind: TokenKind::BACKGROUND,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "SETRGB" => Ok(Token {
+            kind: TokenKind::PENRGB,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "SETOPACITY" => Ok(Token {
+            kind: TokenKind::PENOPACITY,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "SETPENWIDTH" => Ok(Token {
+            kind: TokenKind::PENWIDTH,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "SETDASH" => Ok(Token {
+            kind: TokenKind::PENDASH,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Pen Presets
+        "SAVEPEN" => Ok(Token {
+            kind: TokenKind::PENPRESET,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "LOADPEN" => Ok(Token {
+            kind: TokenKind::PENPRESET,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Marked positions
+        "MARK" => Ok(Token {
+            kind: TokenKind::MARK,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "GOMARK" => Ok(Token {
+            kind: TokenKind::MARK,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // Pen Position / Orientation
         "SETX" => Ok(Token {
             kind: TokenKind::PENPOS,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "SETY" => Ok(Token {
             kind: TokenKind::PENPOS,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "TURN" => Ok(Token {
             kind: TokenKind::PENPOS,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "SETHEADING" => Ok(Token {
             kind: TokenKind::PENPOS,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "SETPOS" => Ok(Token {
+            kind: TokenKind::SETPOS,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Trigonometric functions
+        "SIN" | "COS" | "TAN" => Ok(Token {
+            kind: TokenKind::TRIGFN,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Rounding functions
+        "FLOOR" | "ROUND" | "INT" => Ok(Token {
+            kind: TokenKind::ROUNDFN,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Unary negation
+        "NEG" => Ok(Token {
+            kind: TokenKind::NEGATE,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Absolute value
+        "ABS" => Ok(Token {
+            kind: TokenKind::ABS,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Square root
+        "SQRT" => Ok(Token {
+            kind: TokenKind::SQRT,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Boolean literals
+        "TRUE" | "FALSE" => Ok(Token {
+            kind: TokenKind::BOOL,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Logical negation
+        "NOT" => Ok(Token {
+            kind: TokenKind::NOTOP,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Random numbers
+        "RANDOM" => Ok(Token {
+            kind: TokenKind::RANDOM,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // Queries
         "XCOR" => Ok(Token {
             kind: TokenKind::QUERY,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "YCOR" => Ok(Token {
             kind: TokenKind::QUERY,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "HEADING" => Ok(Token {
             kind: TokenKind::QUERY,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "COLOR" => Ok(Token {
             kind: TokenKind::QUERY,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "NUMCOLORS" => Ok(Token {
+            kind: TokenKind::QUERY,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "TOTALDIST" => Ok(Token {
+            kind: TokenKind::QUERY,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "PENWIDTH" => Ok(Token {
+            kind: TokenKind::QUERY,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "PIXELHERE" => Ok(Token {
+            kind: TokenKind::QUERY,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Interpreter State
+        "HOME" => Ok(Token {
+            kind: TokenKind::HOME,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "CLEARSCREEN" | "CS" => Ok(Token {
+            kind: TokenKind::CLEARSCREEN,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "RESETALL" => Ok(Token {
+            kind: TokenKind::RESETALL,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "SHOWPATH" => Ok(Token {
+            kind: TokenKind::SHOWPATH,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "AXES" => Ok(Token {
+            kind: TokenKind::AXES,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // If Statements
         "IF" => Ok(Token {
             kind: TokenKind::IFSTMNT,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // If-else statements
+        "IFELSE" => Ok(Token {
+            kind: TokenKind::IFELSE,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Conditional expressions
+        "IFVAL" => Ok(Token {
+            kind: TokenKind::IFVAL,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // While statements
         "WHILE" => Ok(Token {
             kind: TokenKind::WHILESTMNT,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Repeat statements
+        "REPEAT" => Ok(Token {
+            kind: TokenKind::REPEAT,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // For loops
+        "FOR" => Ok(Token {
+            kind: TokenKind::FOR,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "FOREACH" => Ok(Token {
+            kind: TokenKind::FOREACH,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
         }),
-        // Brackets (For If / While statement blocks)
+        // Brackets (For If / While / Repeat statement blocks)
         "[" => Ok(Token {
             kind: TokenKind::LPAREN,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "]" => Ok(Token {
             kind: TokenKind::RPAREN,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Parentheses (For optionally grouping expressions)
+        "(" => Ok(Token {
+            kind: TokenKind::LGROUP,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        ")" => Ok(Token {
+            kind: TokenKind::RGROUP,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Compass Headings
+        // Headings start at 0 degrees pointing straight up and increase clockwise,
+        // so NORTH is up (0), EAST is right (90), SOUTH is down (180) and WEST is left (270).
+        "NORTH" => Ok(Token {
+            kind: TokenKind::NUM,
+            value: String::from("0"),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "EAST" => Ok(Token {
+            kind: TokenKind::NUM,
+            value: String::from("90"),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "SOUTH" => Ok(Token {
+            kind: TokenKind::NUM,
+            value: String::from("180"),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "WEST" => Ok(Token {
+            kind: TokenKind::NUM,
+            value: String::from("270"),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        // Half a turn in radians, for use with ANGLEUNITS RADIANS
+        "PI" => Ok(Token {
+            kind: TokenKind::NUM,
+            value: std::f32::consts::PI.to_string(),
+            line: line_no,
+            statement_line: line_no,
+            col,
         }),
         // Variables and Numbers
+        // Prefixes are stripped by char rather than by byte index, so a multibyte character
+        // directly after the prefix can never split a UTF-8 sequence.
         s if s.starts_with('"') => {
-            if s[1..].parse::<f32>().is_ok() {
+            let value = s.strip_prefix('"').unwrap_or_default();
+            if let Some(radix_literal) = parse_radix_literal(value) {
+                match radix_literal {
+                    Some(num) => Ok(Token {
+                        kind: TokenKind::NUM,
+                        value: num.to_string(),
+                        line: line_no,
+            statement_line: line_no,
+                        col,
+                    }),
+                    None => Err(invalid()),
+                }
+            } else if let Some(separated_number) = parse_separated_number(value) {
+                match separated_number {
+                    Some(num) => Ok(Token {
+                        kind: TokenKind::NUM,
+                        value: num,
+                        line: line_no,
+            statement_line: line_no,
+                        col,
+                    }),
+                    None => Err(invalid()),
+                }
+            } else if value.parse::<f32>().is_ok() {
                 Ok(Token {
                     kind: TokenKind::NUM,
-                    value: s[1..].to_string(),
+                    value: value.to_string(),
                     line: line_no,
+            statement_line: line_no,
+                    col,
                 })
-            } else if s[1..].chars().all(|c| c.is_alphanumeric() || c == '_') {
+            } else if value.chars().all(|c| c.is_alphanumeric() || c == '_') {
                 Ok(Token {
                     kind: TokenKind::IDENT,
-                    value: s[1..].to_string(),
+                    value: value.to_string(),
                     line: line_no,
+            statement_line: line_no,
+                    col,
                 })
             } else {
-                Err(LexerError::InvalidTokenError(String::from(input)))
+                Err(invalid())
             }
         }
         // Variable Reference
-        s if s.starts_with(':') && s[1..].chars().all(|c| c.is_alphanumeric() || c == '_') => {
-            Ok(Token {
-                kind: TokenKind::IDENTREF,
-                value: s[1..].to_string(),
-                line: line_no,
-            })
+        s if s.starts_with(':') => {
+            let value = s.strip_prefix(':').unwrap_or_default();
+            if value.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                Ok(Token {
+                    kind: TokenKind::IDENTREF,
+                    value: value.to_string(),
+                    line: line_no,
+            statement_line: line_no,
+                    col,
+                })
+            } else {
+                Err(invalid())
+            }
         }
+        // Macros
+        "DEFINE" => Ok(Token {
+            kind: TokenKind::DEFINE,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
         // Procedures
         "TO" => Ok(Token {
             kind: TokenKind::PROCSTART,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
         "END" => Ok(Token {
             kind: TokenKind::PROCEND,
             value: String::from(input),
             line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "STOP" => Ok(Token {
+            kind: TokenKind::STOP,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
+        }),
+        "OUTPUT" => Ok(Token {
+            kind: TokenKind::OUTPUT,
+            value: String::from(input),
+            line: line_no,
+            statement_line: line_no,
+            col,
         }),
         s if s.chars().all(|c| c.is_alphabetic()) => Ok(Token {
             kind: TokenKind::PROCNAME,
             value: s.to_string(),
             line: line_no,
+            statement_line: line_no,
+            col,
         }),
 
-        _ => Err(LexerError::InvalidTokenError(String::from(input))),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses hexadecimal ("0x") and binary ("0b") integer literals.
+/// Returns None if the value has neither prefix, or Some(None) if the digits following
+/// the prefix are not valid in that base. Signs are not digits, so "0x+FF" is invalid.
+fn parse_radix_literal(value: &str) -> Option<Option<f32>> {
+    let (digits, radix) = if let Some(digits) = value.strip_prefix("0x") {
+        (digits, 16)
+    } else if let Some(digits) = value.strip_prefix("0b") {
+        (digits, 2)
+    } else {
+        return None;
+    };
+
+    // from_str_radix accepts a leading sign, so the digits are checked first
+    if !digits.chars().all(|c| c.is_digit(radix)) {
+        return Some(None);
+    }
+    Some(
+        u32::from_str_radix(digits, radix)
+            .ok()
+            .map(|num| num as f32),
+    )
+}
+
+/// Parses decimal literals containing underscores as digit separators (e.g. "1_000.5").
+/// Returns None if the value is not a number once its underscores are removed, or Some(None)
+/// if an underscore is not between two digits, as in "1__0", "_1" or "1_".
+fn parse_separated_number(value: &str) -> Option<Option<String>> {
+    let digits = value.replace('_', "");
+    // Words such as "in_f" are not numbers, though f32 parses "inf"
+    let is_decimal = digits
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+    if !value.contains('_') || !is_decimal || digits.parse::<f32>().is_err() {
+        return None;
     }
+
+    let chars = value.chars().collect::<Vec<_>>();
+    let separates_digits = |i: usize| {
+        i > 0 && chars[i - 1].is_ascii_digit() && chars.get(i + 1).is_some_and(char::is_ascii_digit)
+    };
+    let valid = (0..chars.len()).all(|i| chars[i] != '_' || separates_digits(i));
+
+    Some(valid.then_some(digits))
+}
+
+/// Returns true if the given word is a reserved keyword or query name in RSLOGO.
+/// Any bare word which does not lex to a procedure name is considered reserved.
+pub fn is_reserved_word(word: &str) -> bool {
+    matches!(to_token(word, 0, 0), Ok(token) if token.kind != TokenKind::PROCNAME)
 }
 
 /// Tokenizes the input from the provided file.
@@ -274,31 +900,376 @@ fn to_token(input: &str, line_no: i32) -> Result<Token, LexerError> {
 /// # Arguments
 ///
 /// * `file_path` - The path to the input file.
+/// * `aliases` - Map of alternative words to the keywords they stand for (e.g. "fd" to "FORWARD").
+///   Aliases are substituted before a word is converted to a token.
+/// * `comment_prefix` - Lines starting with this prefix (e.g. "//") are ignored.
 ///
 /// # Returns
 ///
 /// A [`anyhow::Result`] containing a [`VecDeque`] of tokens if successful, or a `LexerError`
 /// if an error occurs during tokenization.
-pub fn tokenize(file_path: std::path::PathBuf) -> Result<VecDeque<Token>, LexerError> {
-    let file = BufReader::new(File::open(file_path)?);
+///
+/// ```
+/// use logolang_lib::lexer::tokenize;
+/// use std::collections::HashMap;
+///
+/// // Tokens record the column they start at, counting leading whitespace
+/// let path = std::env::temp_dir().join("rslogo_token_columns.lg");
+/// std::fs::write(&path, "  FORWARD \t\"10\n").unwrap();
+/// let tokens = tokenize(path, &HashMap::new(), "//").unwrap();
+/// assert_eq!((tokens[0].line, tokens[0].col), (1, 3));
+/// assert_eq!((tokens[1].line, tokens[1].col), (1, 12));
+///
+/// // Invalid tokens are reported with the line and column they appear at
+/// let path = std::env::temp_dir().join("rslogo_invalid_token.lg");
+/// std::fs::write(&path, "PENDOWN\nFORWARD \"10\n\n// A comment\nFORWARD \"1$0\n").unwrap();
+/// let error = tokenize(path, &HashMap::new(), "//").unwrap_err();
+/// assert_eq!(
+///     error.to_string(),
+///     "[Line 5, Col 9]: Failed to lex input file: '\"1$0' is not a valid token"
+/// );
+/// ```
+pub fn tokenize(
+    file_path: std::path::PathBuf,
+    aliases: &HashMap<String, String>,
+    comment_prefix: &str,
+) -> Result<VecDeque<Token>, LexerError> {
+    let input = std::fs::read_to_string(file_path)?;
+    tokenize_str(&input, aliases, comment_prefix)
+}
 
+/// Tokenizes a program given as a string, as `tokenize` does for the contents of a file.
+///
+/// ```
+/// use logolang_lib::lexer::{tokenize_str, TokenKind};
+/// use std::collections::HashMap;
+///
+/// let tokens = tokenize_str("PENDOWN\n// A comment\nFORWARD \"10\n", &HashMap::new(), "//").unwrap();
+/// assert_eq!(tokens.len(), 3);
+/// assert_eq!((tokens[2].kind.clone(), tokens[2].line), (TokenKind::NUM, 3));
+///
+/// // Underscores may separate the digits of numbers, and are removed
+/// let tokens = tokenize_str("\"1_000 \"1_000.5 \"2_5e1_0", &HashMap::new(), "//").unwrap();
+/// let values = tokens.iter().map(|token| token.value.as_str()).collect::<Vec<_>>();
+/// assert_eq!(values, ["1000", "1000.5", "25e10"]);
+/// assert!(tokens.iter().all(|token| token.kind == TokenKind::NUM));
+///
+/// // Each underscore must lie between two digits
+/// for literal in ["\"1__0", "\"_1", "\"1_", "\"1_.5", "\"1_e3"] {
+///     assert!(tokenize_str(literal, &HashMap::new(), "//").is_err());
+/// }
+///
+/// // Words containing underscores are unaffected
+/// let tokens = tokenize_str("\"my_var \"in_f", &HashMap::new(), "//").unwrap();
+/// assert!(tokens.iter().all(|token| token.kind == TokenKind::IDENT));
+/// ```
+pub fn tokenize_str(
+    input: &str,
+    aliases: &HashMap<String, String>,
+    comment_prefix: &str,
+) -> Result<VecDeque<Token>, LexerError> {
     let mut tokens = VecDeque::<Token>::new();
-    for (line_no, buf_line) in (1_i32..).zip(file.lines()) {
-        let line = buf_line?;
-
-        // Ignore comments
-        if line.trim_start().starts_with("//") {
-            continue;
-        }
+    for (line_no, line) in (1_i32..).zip(input.lines()) {
+        // Ignore comments, which run to the end of the line
+        let line = strip_comment(line, comment_prefix);
 
         // Tokenize stream
-        let mut tokenized_lines = line
-            .split_whitespace()
-            .map(|word| to_token(word, line_no))
+        let mut tokenized_lines = words_with_columns(line)
+            .map(|(col, word)| (col, aliases.get(word).map_or(word, String::as_str)))
+            .map(|(col, word)| to_token(word, line_no, col))
             .collect::<Result<VecDeque<_>, _>>()?;
 
         tokens.append(&mut tokenized_lines);
     }
 
-    Ok(tokens)
+    expand_macros(tokens)
+}
+
+/// Splits a line into its whitespace separated words, each paired with the column it starts
+/// at, in characters counting from 1
+fn words_with_columns(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    // Splitting at every whitespace character leaves empty words between consecutive ones,
+    // so each piece is one character further along than the end of the last
+    let mut col = 1;
+    line.split(char::is_whitespace).filter_map(move |word| {
+        let word_col = col;
+        col += word.chars().count() + 1;
+        (!word.is_empty()).then_some((word_col, word))
+    })
+}
+
+/// Returns the part of a line before its comment, if any. A comment starts at the first
+/// occurrence of the comment prefix, whether it begins a word or follows a command
+/// (`FORWARD "50// up`), except where the prefix opens a quoted word (`"//foo`), which is kept
+/// as a value.
+fn strip_comment<'a>(line: &'a str, comment_prefix: &str) -> &'a str {
+    let opens_quoted_word = |index: usize| {
+        line[..index]
+            .strip_suffix('"')
+            .is_some_and(|rest| rest.chars().next_back().is_none_or(char::is_whitespace))
+    };
+
+    match line
+        .match_indices(comment_prefix)
+        .find(|&(index, _)| !opens_quoted_word(index))
+    {
+        Some((index, _)) => &line[..index],
+        None => line,
+    }
+}
+
+/// Removes macro definitions (`DEFINE name [ tokens ]`) from the token stream, and splices
+/// the recorded tokens in place of each later use of the macro's name.
+///
+/// Macros are purely textual: unlike procedures, they take no parameters, are expanded before
+/// parsing rather than called at runtime, and may contain any tokens, including partial
+/// expressions. Expanded tokens take the line of the macro's use, so errors point to the use
+/// and the body's first line continues the statement it is used in. Later lines of the body
+/// remain separate statements. Macros may use other macros, but a macro which expands to itself
+/// is an error. The name following TO always names a procedure, so it is never expanded.
+fn expand_macros(mut tokens: VecDeque<Token>) -> Result<VecDeque<Token>, LexerError> {
+    let mut macros = HashMap::<String, Vec<Token>>::new();
+    let mut expanded = VecDeque::<Token>::new();
+
+    while let Some(token) = tokens.pop_front() {
+        if token.kind == TokenKind::DEFINE {
+            let (name, body) = macro_definition(&token, &mut tokens)?;
+            macros.insert(name, body);
+        } else if token.kind == TokenKind::PROCSTART {
+            expanded.push_back(token);
+            expanded.extend(tokens.pop_front());
+        } else {
+            expand_token(token, &macros, &mut Vec::new(), &mut expanded)?;
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Parses the name and bracketed body of a macro definition following a DEFINE token
+fn macro_definition(
+    define_token: &Token,
+    tokens: &mut VecDeque<Token>,
+) -> Result<(String, Vec<Token>), LexerError> {
+    let invalid = |reason: &str| {
+        LexerError::InvalidMacro(format!("[Line {}]: {}", define_token.line, reason))
+    };
+
+    let name = match tokens.pop_front() {
+        Some(token) if token.kind == TokenKind::PROCNAME => token.value,
+        _ => return Err(invalid("DEFINE must be followed by a macro name")),
+    };
+    match tokens.pop_front() {
+        Some(token) if token.kind == TokenKind::LPAREN => (),
+        _ => return Err(invalid("Macro body must be enclosed in [ ]")),
+    }
+
+    // Collect the body up to the matching closing bracket
+    let mut body = Vec::<Token>::new();
+    let mut depth = 0;
+    loop {
+        let token = tokens
+            .pop_front()
+            .ok_or_else(|| invalid("Macro body is missing a closing ]"))?;
+        match token.kind {
+            TokenKind::LPAREN => depth += 1,
+            TokenKind::RPAREN if depth == 0 => {
+                if tokens.front().is_some_and(|next| next.line == token.line) {
+                    return Err(invalid("Unexpected tokens after the end of the macro body"));
+                }
+                break;
+            }
+            TokenKind::RPAREN => depth -= 1,
+            // Macros are only defined at the top level, so a nested DEFINE would reach the parser
+            TokenKind::DEFINE => {
+                return Err(invalid("Macros cannot be defined inside a macro body"))
+            }
+            _ => (),
+        }
+        body.push(token);
+    }
+
+    Ok((name, body))
+}
+
+/// Appends a token to the output, recursively replacing uses of macros by their bodies.
+/// `expanding` holds the macros currently being expanded, to detect cycles.
+fn expand_token(
+    token: Token,
+    macros: &HashMap<String, Vec<Token>>,
+    expanding: &mut Vec<String>,
+    expanded: &mut VecDeque<Token>,
+) -> Result<(), LexerError> {
+    let body = match macros.get(&token.value) {
+        Some(body) if token.kind == TokenKind::PROCNAME => body,
+        _ => {
+            expanded.push_back(token);
+            return Ok(());
+        }
+    };
+
+    if expanding.contains(&token.value) {
+        return Err(LexerError::InvalidMacro(format!(
+            "[Line {}]: Macro '{}' expands to itself ({} -> {})",
+            token.line,
+            token.value,
+            expanding.join(" -> "),
+            token.value
+        )));
+    }
+
+    expanding.push(token.value.clone());
+    let first_line = body.first().map(|body_token| body_token.line);
+    for body_token in body {
+        let statement_line = if Some(body_token.line) == first_line {
+            token.statement_line
+        } else {
+            body_token.statement_line
+        };
+        let body_token = Token {
+            line: token.line,
+            statement_line,
+            col: token.col,
+            ..body_token.clone()
+        };
+        expand_token(body_token, macros, expanding, expanded)?;
+    }
+    expanding.pop();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tokenizes a program with no aliases and "//" comments
+    fn lex(program: &str) -> Result<VecDeque<Token>, LexerError> {
+        tokenize_str(program, &HashMap::new(), "//")
+    }
+
+    #[test]
+    fn compass_words_lex_to_headings() {
+        let tokens = lex("NORTH EAST SOUTH WEST").unwrap();
+        let values = tokens
+            .iter()
+            .map(|token| token.value.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["0", "90", "180", "270"]);
+        assert!(tokens.iter().all(|token| token.kind == TokenKind::NUM));
+    }
+
+    #[test]
+    fn define_inside_a_macro_body_is_rejected() {
+        let program = "DEFINE Outer [\nDEFINE Inner [\nFORWARD \"1\n]\n]\nOuter\n";
+        let error = lex(program).err().unwrap();
+        assert!(matches!(error, LexerError::InvalidMacro(_)));
+        assert!(error
+            .to_string()
+            .contains("Macros cannot be defined inside a macro body"));
+    }
+
+    #[test]
+    fn radix_literals_reject_signs() {
+        assert_eq!(lex("\"0xFF \"0b101").unwrap()[0].value, "255");
+        for literal in ["\"0x+FF", "\"0x-1", "\"0b+101", "\"0x"] {
+            assert!(
+                matches!(lex(literal), Err(LexerError::InvalidTokenError(..))),
+                "{} was accepted",
+                literal
+            );
+        }
+    }
+
+    #[test]
+    fn multibyte_characters_after_a_prefix_lex_whole() {
+        let tokens = lex(include_str!("../../logo_examples/6_08_unicode_word.lg")).unwrap();
+        let words = tokens
+            .iter()
+            .filter(|token| token.kind == TokenKind::IDENT)
+            .map(|token| token.value.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(words, ["w", "éclair", "éclair"]);
+
+        let tokens = lex(":été").unwrap();
+        assert_eq!(tokens[0].kind, TokenKind::IDENTREF);
+        assert_eq!(tokens[0].value, "été");
+        assert!(matches!(lex("\"€"), Err(LexerError::InvalidTokenError(..))));
+    }
+
+    #[test]
+    fn comment_prefix_example_lexes_only_with_its_prefix() {
+        let example = include_str!("../../logo_examples/6_19_comment_prefix.lg");
+        let tokens = tokenize_str(example, &HashMap::new(), "#").unwrap();
+        let values = tokens
+            .iter()
+            .map(|token| token.value.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["PENDOWN", "FORWARD", "20"]);
+        assert!(lex(example).is_err());
+    }
+
+    #[test]
+    fn macro_cycle_example_is_rejected() {
+        let error = lex(include_str!("../../logo_examples/6_22_macro_cycle_err.lg"))
+            .err()
+            .unwrap();
+        assert!(matches!(error, LexerError::InvalidMacro(_)));
+        assert!(error.to_string().contains("A -> B -> A"), "{}", error);
+    }
+
+    #[test]
+    fn inline_comment_examples_strip_comments_but_not_quoted_words() {
+        let tokens = lex(include_str!("../../logo_examples/6_33_inline_comments.lg")).unwrap();
+        let values = tokens
+            .iter()
+            .map(|token| token.value.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            ["PENDOWN", "FORWARD", "20", "RIGHT", "20", "BACK", "20"]
+        );
+
+        let error = lex(include_str!(
+            "../../logo_examples/6_34_quoted_comment_prefix_err.lg"
+        ))
+        .err()
+        .unwrap();
+        assert!(
+            matches!(error, LexerError::InvalidTokenError(ref word, 4, _) if word == "\"//foo")
+        );
+    }
+
+    #[test]
+    fn macro_tokens_take_the_line_of_their_use() {
+        let program = "DEFINE Pen [\nSETPENCOLOR \"4\nPENDOWN\n]\nDEFINE Side [ \"25 ]\n\nPen\nFORWARD Side\n";
+        let tokens = lex(program).unwrap();
+        let lines = tokens
+            .iter()
+            .map(|token| (token.value.as_str(), token.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                ("SETPENCOLOR", 7),
+                ("4", 7),
+                ("PENDOWN", 7),
+                ("FORWARD", 8),
+                ("25", 8)
+            ]
+        );
+        // The body's later lines stay separate statements, and its first continues the use's
+        assert_ne!(tokens[2].statement_line, tokens[0].statement_line);
+        assert_eq!(tokens[4].statement_line, tokens[3].statement_line);
+    }
+
+    #[test]
+    fn procedure_names_are_not_expanded_as_macros() {
+        let program = "DEFINE Square [ FORWARD \"1 ]\nTO Square\nEND\nSquare\n";
+        let values = lex(program)
+            .unwrap()
+            .iter()
+            .map(|token| token.value.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["TO", "Square", "END", "FORWARD", "1"]);
+    }
 }
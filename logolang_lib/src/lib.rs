@@ -1,4 +1,80 @@
+pub mod analysis;
+pub mod checker;
+pub mod folder;
+pub mod gcode;
+mod glyphs;
+pub mod inliner;
 pub mod interpreter;
 pub mod lexer;
 pub mod logolang_errors;
 pub mod parser;
+mod raster;
+mod rng;
+pub mod visitor;
+
+use crate::checker::check;
+use crate::interpreter::Interpreter;
+use crate::lexer::tokenize_str;
+use crate::logolang_errors::ProgramError;
+use crate::parser::Parser;
+use std::collections::HashMap;
+use unsvg::Image;
+
+/// Runs a program given as a string on a new image of the given size, returning the drawn
+/// image. Comments start with "//" and no aliases are used, as with the defaults of rslogo.
+///
+/// ```
+/// use logolang_lib::logolang_errors::ProgramError;
+/// use logolang_lib::run_program;
+///
+/// let program = "PENDOWN\n\
+///     REPEAT \"4 [\n\
+///         FORWARD \"50\n\
+///         TURN \"90\n\
+///     ]\n";
+/// let image = run_program(program, 200, 100).unwrap();
+/// assert_eq!(image.get_dimensions(), (200, 100));
+///
+/// // Errors are reported from whichever stage raised them
+/// let error = run_program("FORWARD \"1$0\n", 200, 100).err().unwrap();
+/// assert!(matches!(error, ProgramError::Lexer(_)));
+/// let error = run_program("FORWARD TRUE\n", 200, 100).err().unwrap();
+/// assert!(matches!(error, ProgramError::Parser(_)));
+/// let error = run_program("FORWARD / \"1 \"0\n", 200, 100).err().unwrap();
+/// assert!(matches!(error, ProgramError::Interpreter(_)));
+/// ```
+pub fn run_program(source: &str, width: u32, height: u32) -> Result<Image, ProgramError> {
+    let tokens = tokenize_str(source, &HashMap::new(), "//")?;
+    let ast = Parser::new().parse(tokens)?;
+
+    let mut image = Image::new(width, height);
+    Interpreter::new(&mut image).run(&ast)?;
+    Ok(image)
+}
+
+/// Checks a program given as a string for errors without running it, as `run_program` would
+/// lex and parse it. Variables and procedure calls are resolved by `checker::check`, so no
+/// image is drawn.
+///
+/// ```
+/// use logolang_lib::check_program;
+/// use logolang_lib::logolang_errors::ProgramError;
+///
+/// assert!(check_program("MAKE \"side \"50\nPENDOWN\nFORWARD :side\n").is_ok());
+///
+/// // Errors which would only be found when running the program are caught
+/// let error = check_program("PENDOWN\nFORWARD :side\n").err().unwrap();
+/// assert!(matches!(error, ProgramError::Interpreter(_)));
+/// assert!(error.to_string().contains("Variable side does not exist."));
+///
+/// // Calls with the wrong number of arguments are caught by the parser
+/// let program = "TO Square \"side\n    FORWARD :side\nEND\nSquare \"10 \"20\n";
+/// let error = check_program(program).err().unwrap();
+/// assert!(matches!(error, ProgramError::Parser(_)));
+/// ```
+pub fn check_program(source: &str) -> Result<(), ProgramError> {
+    let tokens = tokenize_str(source, &HashMap::new(), "//")?;
+    let ast = Parser::new().parse(tokens)?;
+    check(&ast)?;
+    Ok(())
+}
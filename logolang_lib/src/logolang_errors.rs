@@ -6,24 +6,31 @@ use thiserror::Error;
 pub enum ImgFileError {
     #[error("Provided image file extension is not supported, could not save image. Please use .svg or .png")]
     UnsupportedFileExtension,
+
+    #[error("Supersampling is only supported for .png images, as .svg images are not rasterised")]
+    SupersampleRequiresPng,
 }
 
 // LEXER errors: File read errors, unsupported tokens
 #[derive(Debug, Error)]
 pub enum LexerError {
-    #[error("Failed to lex input file: '{0}' is not a valid token")]
-    InvalidTokenError(String),
+    #[error("[Line {1}, Col {2}]: Failed to lex input file: '{0}' is not a valid token")]
+    InvalidTokenError(String, i32, usize),
 
     #[error("Error while trying to read file")]
     IoError(#[from] io::Error),
+
+    #[error("Invalid macro: {0}")]
+    InvalidMacro(String),
 }
 
 // PARSER errors: syntactic errors
 #[derive(Debug, Error)]
 pub enum ParserError {
-    // All <anyhow::Error> are cast to ParseError to catch the context chain
+    // All <anyhow::Error> are cast to ParseError to catch the context chain, keeping the code
+    // of the error at its root
     #[error("{0}")]
-    ParseError(String),
+    ParseError(String, &'static str),
 
     #[error("Unexpected ending  while parsing program.\n")]
     UnexpectedEnding,
@@ -43,9 +50,15 @@ pub enum ParserError {
     #[error("[Line {0}]: {1}\n")]
     InvalidToken(String, String),
 
+    #[error("[Line {0}]: Invalid MAKE statement: '{1}' is a reserved keyword and cannot be used as a variable name.\n")]
+    ReservedName(String, String),
+
     #[error("[Line {0}]: {1} statement is missing parenthesis: expected {2}, received {3}.\n")]
     MissingParenthesis(String, String, String, String),
 
+    #[error("[Line {0}]: Unbalanced parentheses: expected ')' to close the '(' on this line, received {1}.\n")]
+    UnbalancedGroup(String, String),
+
     #[error("[Line{0}]: Invalid ADDASSIGN operation. Expected identifier, received {1}.\n")]
     InvalidAddAssign(String, String),
 
@@ -55,43 +68,203 @@ pub enum ParserError {
     #[error("[Line {0}]: Invalid procedure: Expected END, received: {1}.\n")]
     MissingProcEnd(String, String),
 
+    #[error("[Line {0}]: Invalid procedure: {1} has already been defined.\n")]
+    DuplicateProcedure(String, String),
+
     #[error("[Line{0}]: Invalid procedurereference: {1} does not exist.\n")]
     InvalidProcReference(String, String),
+
+    #[error("[Line {0}]: Procedure {1} expects {2} argument(s), but received {3}.\n")]
+    WrongArgCount(String, String, usize, usize),
+
+    #[error("[Line {0}]: Unexpected token '{1}' outside of an enclosing block.\n")]
+    UnexpectedToken(String, String),
+
+    #[error("[Line {0}]: OUTPUT can only be used within a procedure body.\n")]
+    OutputOutsideProcedure(String),
+}
+
+impl LexerError {
+    /// Returns the stable code identifying this kind of error, for tools which handle errors
+    /// without matching on their messages.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexerError::InvalidTokenError(..) => "E0101",
+            LexerError::IoError(_) => "E0102",
+            LexerError::InvalidMacro(_) => "E0103",
+        }
+    }
+}
+
+impl ParserError {
+    /// Returns the stable code identifying this kind of error, for tools which handle errors
+    /// without matching on their messages. Errors to which context was added keep the code of
+    /// the error they wrap.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParserError::ParseError(_, code) => code,
+            ParserError::UnexpectedEnding => "E0201",
+            ParserError::ExtraArguments(..) => "E0202",
+            ParserError::NonNumericExpr(..) => "E0203",
+            ParserError::NonBooleanExpr(..) => "E0204",
+            ParserError::IncorrectArgType(..) => "E0205",
+            ParserError::InvalidToken(..) => "E0206",
+            ParserError::ReservedName(..) => "E0207",
+            ParserError::MissingParenthesis(..) => "E0208",
+            ParserError::UnbalancedGroup(..) => "E0209",
+            ParserError::InvalidAddAssign(..) => "E0210",
+            ParserError::InvalidProcName(..) => "E0211",
+            ParserError::MissingProcEnd(..) => "E0212",
+            ParserError::DuplicateProcedure(..) => "E0213",
+            ParserError::InvalidProcReference(..) => "E0214",
+            ParserError::WrongArgCount(..) => "E0215",
+            ParserError::UnexpectedToken(..) => "E0216",
+            ParserError::OutputOutsideProcedure(_) => "E0217",
+        }
+    }
 }
 
 // Error propogation
 impl From<anyhow::Error> for ParserError {
     fn from(error: anyhow::Error) -> Self {
-        ParserError::ParseError(format!("{:?}", error))
+        let code = error
+            .root_cause()
+            .downcast_ref::<ParserError>()
+            .map_or("E0200", ParserError::code);
+        ParserError::ParseError(format!("{:?}", error), code)
+    }
+}
+
+// PROGRAM errors: any error raised while running a program from its source
+#[derive(Debug, Error)]
+pub enum ProgramError {
+    #[error(transparent)]
+    Lexer(#[from] LexerError),
+
+    #[error(transparent)]
+    Parser(#[from] ParserError),
+
+    #[error(transparent)]
+    Interpreter(#[from] InterpreterError),
+}
+
+impl ProgramError {
+    /// Returns the stable code of the error, from whichever stage raised it. Codes start with
+    /// E01 for lexer errors, E02 for parser errors and E03 for interpreter errors.
+    ///
+    /// ```
+    /// use logolang_lib::run_program;
+    ///
+    /// let code = |program: &str| run_program(program, 100, 100).err().unwrap().code();
+    ///
+    /// assert_eq!(code("FORWARD \"1$0\n"), "E0101");
+    /// assert_eq!(code("TO Line \"len\n    FORWARD :len\nEND\nLine\n"), "E0215");
+    /// // Errors keep their code however much context is added as they are reported
+    /// assert_eq!(code("PENDOWN\nREPEAT \"2 [\n FORWARD / \"1 \"0\n]\n"), "E0310");
+    /// assert_eq!(code("GOMARK \"start\n"), "E0313");
+    /// ```
+    pub fn code(&self) -> &'static str {
+        match self {
+            ProgramError::Lexer(error) => error.code(),
+            ProgramError::Parser(error) => error.code(),
+            ProgramError::Interpreter(error) => error.code(),
+        }
     }
 }
 
 // INTERPRETER errors: semantic errors
 #[derive(Debug, Error)]
 pub enum InterpreterError {
-    // All <anyhow::Error> are cast to InterpError to catch the context chain
+    // All <anyhow::Error> are cast to InterpError to catch the context chain, keeping the code
+    // of the error at its root
     #[error("{0}")]
-    InterpError(String),
+    InterpError(String, &'static str),
 
     #[error("{0}")]
     TypeError(String),
 
+    // Reported without the surrounding context chain, as the message is self-describing
+    #[error("{0}")]
+    VariableTypeError(String),
+
     #[error("Variable {0} does not exist.")]
     InvalidVariableRef(String),
 
     #[error("{0} {1}")]
     DrawLineError(String, String),
 
-    #[error("{0} is not a valid color. Enter an integer between 0 and 15.")]
+    #[error("{0} is out of range for a color. Enter an integer between 0 and 15.")]
     InvalidPenColor(String),
 
+    #[error("{0} is not a whole number. Enter an integer color between 0 and 15.")]
+    FractionalPenColor(String),
+
     #[error("{0}")]
     InvalidProcedureRef(String),
+
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    #[error("{0}")]
+    ArithmeticOverflow(String),
+
+    #[error("{0}")]
+    DivideByZero(String),
+
+    #[error("{0}")]
+    NegativeSqrt(String),
+
+    #[error("{0}")]
+    InvalidPenPreset(String),
+
+    #[error("{0}")]
+    InvalidMark(String),
+
+    #[error("{0}")]
+    InkBudgetExceeded(String),
+
+    #[error("{0}")]
+    StepLimitExceeded(String),
+
+    #[error("{0}")]
+    OutOfBounds(String),
+}
+
+impl InterpreterError {
+    /// Returns the stable code identifying this kind of error, for tools which handle errors
+    /// without matching on their messages. Errors to which context was added keep the code of
+    /// the error they wrap.
+    pub fn code(&self) -> &'static str {
+        match self {
+            InterpreterError::InterpError(_, code) => code,
+            InterpreterError::TypeError(_) => "E0301",
+            InterpreterError::VariableTypeError(_) => "E0302",
+            InterpreterError::InvalidVariableRef(_) => "E0303",
+            InterpreterError::DrawLineError(..) => "E0304",
+            InterpreterError::InvalidPenColor(_) => "E0305",
+            InterpreterError::FractionalPenColor(_) => "E0306",
+            InterpreterError::InvalidProcedureRef(_) => "E0307",
+            InterpreterError::InvalidArgument(_) => "E0308",
+            InterpreterError::ArithmeticOverflow(_) => "E0309",
+            InterpreterError::DivideByZero(_) => "E0310",
+            InterpreterError::NegativeSqrt(_) => "E0311",
+            InterpreterError::InvalidPenPreset(_) => "E0312",
+            InterpreterError::InvalidMark(_) => "E0313",
+            InterpreterError::InkBudgetExceeded(_) => "E0314",
+            InterpreterError::StepLimitExceeded(_) => "E0315",
+            InterpreterError::OutOfBounds(_) => "E0316",
+        }
+    }
 }
 
 // Error propogation
 impl From<anyhow::Error> for InterpreterError {
     fn from(error: anyhow::Error) -> Self {
-        InterpreterError::InterpError(format!("{:?}", error))
+        let root = error.root_cause().downcast_ref::<InterpreterError>();
+        if let Some(InterpreterError::VariableTypeError(msg)) = root {
+            return InterpreterError::VariableTypeError(msg.clone());
+        }
+        let code = root.map_or("E0300", InterpreterError::code);
+        InterpreterError::InterpError(format!("{:?}", error), code)
     }
 }
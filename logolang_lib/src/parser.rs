@@ -1,22 +1,27 @@
-use crate::lexer::{Token, TokenKind};
+use crate::analysis::unreachable_statements;
+use crate::lexer::{is_reserved_word, Token, TokenKind};
 use crate::logolang_errors::ParserError;
 use anyhow::{Context, Result};
-use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 
 /// Represents arithmetic operations
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ArithOp {
     ADD,
     SUB,
     MUL,
     DIV,
+    MOD,
+    POW,
+    MIN,
+    MAX,
 }
 
 /// Represents comparison operations
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum CompOp {
     EQ,
     NE,
@@ -25,14 +30,14 @@ pub enum CompOp {
 }
 
 /// Represents boolean operations
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum BoolOp {
     AND,
     OR,
 }
 
 /// Represents drawing directions
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Direction {
     FORWARD,
     BACK,
@@ -41,7 +46,7 @@ pub enum Direction {
 }
 
 /// Represents pen position
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PenPos {
     SETX,
     SETY,
@@ -49,18 +54,50 @@ pub enum PenPos {
     TURN,
 }
 
+/// Represents trigonometric functions, which take an angle in degrees
+#[derive(Debug, Clone)]
+pub enum TrigFn {
+    SIN,
+    COS,
+    TAN,
+}
+
+/// Represents the unit in which a program gives and receives angles
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngleUnit {
+    /// Angles are given in degrees (default)
+    Degrees,
+    /// Angles are given in radians
+    Radians,
+}
+
+/// Represents functions rounding a number to a whole number
+#[derive(Debug, Clone)]
+pub enum RoundFn {
+    FLOOR,
+    ROUND,
+    INT,
+}
+
 /// Represents types of queries
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum QueryKind {
     XCOR,
     YCOR,
     HEADING,
+    /// The palette index of the pen color, or -1 while an RGB color set by SETRGB is active,
+    /// as such colors have no index. Programs may branch on `LT COLOR "0` to tell them apart.
     COLOR,
+    NUMCOLORS,
+    TOTALDIST,
+    PIXELHERE,
+    /// The pen width set by SETPENWIDTH, so a procedure may restore the width it changes
+    PENWIDTH,
 }
 
 /// Represents abstract syntax tree nodes
 // Line corresponds to line number at the start of the expression/statement
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AstNode {
     /// Make statements
     MakeStmnt {
@@ -68,20 +105,55 @@ pub enum AstNode {
         expr: Box<AstNode>,
         line: i32,
     },
-    /// Arithmetic expressions
+    /// Arithmetic expressions. Like any expression, operands may be grouped in parentheses for
+    /// readability: `( <expr> )` parses to the same node as `<expr>`.
     ArithExpr {
         operator: ArithOp,
         left: Box<AstNode>,
         right: Box<AstNode>,
         line: i32,
     },
-    /// Comparison expressions
+    /// Comparison expressions. EQ and NE compare values of any types, and values of different
+    /// types are never equal: no conversion between numbers, words and booleans is attempted.
+    /// LT and GT require two numbers.
     CompExpr {
         operator: CompOp,
         left: Box<AstNode>,
         right: Box<AstNode>,
         line: i32,
     },
+    /// Trigonometric expressions
+    TrigExpr {
+        function: TrigFn,
+        operand: Box<AstNode>,
+        line: i32,
+    },
+    /// Rounding expressions, giving whole numbers for commands such as SETPENCOLOR
+    RoundExpr {
+        function: RoundFn,
+        operand: Box<AstNode>,
+        line: i32,
+    },
+    /// Negation of a numeric expression
+    Negate {
+        operand: Box<AstNode>,
+        line: i32,
+    },
+    /// Absolute value of a numeric expression
+    Abs {
+        operand: Box<AstNode>,
+        line: i32,
+    },
+    /// Square root of a numeric expression, which must not be negative
+    Sqrt {
+        operand: Box<AstNode>,
+        line: i32,
+    },
+    /// Random number in [0, max)
+    Random {
+        max: Box<AstNode>,
+        line: i32,
+    },
     /// Boolean expressions
     BoolExpr {
         operator: BoolOp,
@@ -89,6 +161,11 @@ pub enum AstNode {
         right: Box<AstNode>,
         line: i32,
     },
+    /// Logical negation of a boolean expression
+    NotExpr {
+        operand: Box<AstNode>,
+        line: i32,
+    },
     /// Reference to identifier
     IdentRef(String),
     /// Addition assignment
@@ -104,53 +181,443 @@ pub enum AstNode {
     },
     /// Number
     Num(f32),
+    /// Boolean literal (TRUE / FALSE)
+    BoolLiteral(bool),
     /// If statement
     IfStmnt {
         condition: Box<AstNode>,
         body: Box<Vec<AstNode>>,
         line: i32,
     },
+    /// If-else statement, evaluating the first body if its condition is true and the second
+    /// body otherwise. The second body may be given as another IFELSE without brackets, which
+    /// chains conditions as `IFELSE a [ .. ] IFELSE b [ .. ] [ .. ]`.
+    IfElseStmnt {
+        condition: Box<AstNode>,
+        then_body: Box<Vec<AstNode>>,
+        else_body: Box<Vec<AstNode>>,
+        line: i32,
+    },
     /// While statement
     WhileStmnt {
         condition: Box<AstNode>,
         body: Box<Vec<AstNode>>,
         line: i32,
     },
+    /// Repeat statement, evaluating its body a fixed number of times
+    RepeatStmnt {
+        count: Box<AstNode>,
+        body: Box<Vec<AstNode>>,
+        line: i32,
+    },
+    /// For statement, evaluating its body with a variable bound to each value from start to
+    /// end (inclusive) in increments of step. A negative step counts down. The variable is
+    /// bound in the current scope, as with MAKE, and keeps its last value after the loop.
+    ForStmnt {
+        var: String,
+        start: Box<AstNode>,
+        end: Box<AstNode>,
+        step: Box<AstNode>,
+        body: Box<Vec<AstNode>>,
+        line: i32,
+    },
+    /// For-each statement, evaluating its body with a variable bound to each value of a
+    /// bracketed list in turn. The values are all evaluated before the body is first run, and
+    /// the variable is bound as in a FOR statement.
+    ForEachStmnt {
+        var: String,
+        values: Vec<AstNode>,
+        body: Box<Vec<AstNode>>,
+        line: i32,
+    },
     /// Pen status (penup/pendown)
     PenStatusUpdate(bool),
+    /// Turtle visibility (showturtle/hideturtle). The turtle is hidden by default; if it is
+    /// shown when the program finishes, it is drawn as a STAMP marker at its final position.
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::parser::{AstNode, Direction};
+    /// use unsvg::Image;
+    ///
+    /// // Draws a line up from the centre, leaving the turtle visible or hidden at its end
+    /// let draw_with_turtle = |visible| {
+    ///     let ast = vec![
+    ///         AstNode::TurtleVisibility(visible),
+    ///         AstNode::PenStatusUpdate(true),
+    ///         AstNode::DrawInstruction {
+    ///             direction: Direction::FORWARD,
+    ///             num_pixels: Box::new(AstNode::Num(30.0)),
+    ///             line: 3,
+    ///         },
+    ///     ];
+    ///     let mut image = Image::new(100, 100);
+    ///     let mut interpreter = Interpreter::new(&mut image);
+    ///     interpreter.run(&ast).unwrap();
+    ///     (interpreter.drawn_segments().len(), interpreter.pixel_color(46.0, 24.0))
+    /// };
+    ///
+    /// // Only the shown turtle adds the marker's three sides, whose base passes through (46, 24)
+    /// assert_eq!(draw_with_turtle(false), (1, None));
+    /// let (segments, base_pixel) = draw_with_turtle(true);
+    /// assert_eq!(segments, 4);
+    /// assert!(base_pixel.is_some());
+    /// ```
+    TurtleVisibility(bool),
+    /// Angle units (angleunits DEGREES/RADIANS). Selects the unit in which SETHEADING, TURN,
+    /// DRAWAT, ARC and trigonometric functions take angles, and in which HEADING reports them.
+    /// Compass headings such as EAST are always in degrees, while PI suits radians.
+    AngleUnits(AngleUnit),
     PenColorUpdate {
         color: Box<AstNode>,
         line: i32,
     },
-    /// Pen position 
+    /// Pen color given as red, green and blue channels (0 to 255)
+    PenRgbUpdate {
+        red: Box<AstNode>,
+        green: Box<AstNode>,
+        blue: Box<AstNode>,
+        line: i32,
+    },
+    /// Pen opacity (0 transparent to 1 opaque)
+    PenOpacityUpdate {
+        opacity: Box<AstNode>,
+        line: i32,
+    },
+    /// Pen width in pixels, greater than 0 and at most 1000
+    PenWidthUpdate {
+        width: Box<AstNode>,
+        line: i32,
+    },
+    /// Dash pattern of the pen, as the lengths in pixels of each dash and of the gap after it.
+    /// Every straight line drawn starts with a dash, and a gap of 0 draws solid lines again.
+    PenDashUpdate {
+        on: Box<AstNode>,
+        off: Box<AstNode>,
+        line: i32,
+    },
+    /// Palette color of the image background, repainted whenever the image is cleared
+    BackgroundUpdate {
+        color: Box<AstNode>,
+        line: i32,
+    },
+    /// Save the pen configuration under a name
+    SavePen {
+        name: String,
+        line: i32,
+    },
+    /// Restore a named pen configuration
+    LoadPen {
+        name: String,
+        line: i32,
+    },
+    /// Mark the turtle's position and heading under a name, for a later GOMARK to return to
+    Mark {
+        name: String,
+        line: i32,
+    },
+    /// Return to a named mark, drawing a line to it if the pen is down
+    GoMark {
+        name: String,
+        line: i32,
+    },
+    /// Pen position or heading. SETX and SETY move the turtle, drawing a line if the pen is down
     PenPosUpdate {
         update_type: PenPos,
         value: Box<AstNode>,
         line: i32,
     },
+    /// Move to absolute coordinates, drawing a line if the pen is down
+    SetPosition {
+        x: Box<AstNode>,
+        y: Box<AstNode>,
+        line: i32,
+    },
     /// Type of query
     Query(QueryKind),
-    /// Procedure definition
+    /// Procedure definition, with the names of its parameters in order
     Procedure {
         name: String,
+        params: Rc<Vec<String>>,
         body: Rc<Vec<AstNode>>,
     },
-    /// Reference to procedure
+    /// Return early from the current procedure, or end the program at the top level
+    Stop {
+        line: i32,
+    },
+    /// Return a value from the current procedure, ending it early as with STOP
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::parser::{AstNode, ArithOp};
+    /// use std::rc::Rc;
+    /// use unsvg::Image;
+    ///
+    /// // TO Double "x OUTPUT * :x "2 END, then MAKE "y Double "21
+    /// let ast = vec![
+    ///     AstNode::Procedure {
+    ///         name: String::from("Double"),
+    ///         params: Rc::new(vec![String::from("x")]),
+    ///         body: Rc::new(vec![AstNode::Output {
+    ///             value: Box::new(AstNode::ArithExpr {
+    ///                 operator: ArithOp::MUL,
+    ///                 left: Box::new(AstNode::IdentRef(String::from("x"))),
+    ///                 right: Box::new(AstNode::Num(2.0)),
+    ///                 line: 2,
+    ///             }),
+    ///             line: 2,
+    ///         }]),
+    ///     },
+    ///     AstNode::MakeStmnt {
+    ///         var: String::from("y"),
+    ///         expr: Box::new(AstNode::ProcedureRef {
+    ///             name_ref: String::from("Double"),
+    ///             args: Rc::new(vec![AstNode::MakeStmnt {
+    ///                 var: String::from("x"),
+    ///                 expr: Box::new(AstNode::Num(21.0)),
+    ///                 line: 4,
+    ///             }]),
+    ///             line: 4,
+    ///         }),
+    ///         line: 4,
+    ///     },
+    ///     AstNode::PenStatusUpdate(true),
+    ///     AstNode::PenPosUpdate {
+    ///         update_type: logolang_lib::parser::PenPos::SETX,
+    ///         value: Box::new(AstNode::IdentRef(String::from("y"))),
+    ///         line: 5,
+    ///     },
+    /// ];
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.run(&ast).unwrap();
+    /// assert_eq!(interpreter.position(), (42.0, 50.0));
+    /// ```
+    Output {
+        value: Box<AstNode>,
+        line: i32,
+    },
+    /// Reference to procedure. Used as an expression, it evaluates to the value the
+    /// procedure OUTPUTs. Arguments may be of any type, including words.
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::lexer::tokenize_str;
+    /// use logolang_lib::parser::Parser;
+    /// use std::collections::HashMap;
+    /// use unsvg::Image;
+    ///
+    /// // Words passed directly or through a variable are compared by value within the body
+    /// let program = "TO TURNIFSQUARE \"shape\n\
+    ///     IF EQ :shape \"square [\n TURN \"90\n]\n\
+    ///     END\n\
+    ///     MAKE \"kind \"square\n\
+    ///     TURNIFSQUARE :kind\n\
+    ///     TURNIFSQUARE \"circle\n\
+    ///     TURNIFSQUARE \"square\n";
+    /// let tokens = tokenize_str(program, &HashMap::new(), "//").unwrap();
+    /// let ast = Parser::new().parse(tokens).unwrap();
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.run(&ast).unwrap();
+    /// assert_eq!(interpreter.heading(), 180.0);
+    /// ```
     ProcedureRef {
         name_ref: String,
         args: Rc<Vec<AstNode>>,
         line: i32,
     },
     /// Draw instruction (direction instructions)
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::Interpreter;
+    /// use logolang_lib::parser::{AstNode, Direction};
+    /// use unsvg::Image;
+    ///
+    /// // A length which is not a number is reported before drawing is attempted
+    /// let ast = vec![
+    ///     AstNode::PenStatusUpdate(true),
+    ///     AstNode::DrawInstruction {
+    ///         direction: Direction::FORWARD,
+    ///         num_pixels: Box::new(AstNode::Num(f32::NAN)),
+    ///         line: 2,
+    ///     },
+    /// ];
+    ///
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// let error = interpreter.run(&ast).err().unwrap();
+    /// assert!(error.to_string().contains("coordinates are not a number (NaN)"));
+    /// ```
     DrawInstruction {
         direction: Direction,
         num_pixels: Box<AstNode>,
         line: i32,
     },
+    /// Draw at an absolute heading without turning the turtle
+    DrawAt {
+        angle: Box<AstNode>,
+        length: Box<AstNode>,
+        line: i32,
+    },
+    /// Conditional expression: returns then_val if the condition holds, else else_val
+    IfVal {
+        condition: Box<AstNode>,
+        then_val: Box<AstNode>,
+        else_val: Box<AstNode>,
+        line: i32,
+    },
+    /// Move forward, placing dots at regular intervals
+    ForwardDots {
+        length: Box<AstNode>,
+        spacing: Box<AstNode>,
+        line: i32,
+    },
+    /// Star polygon drawn from the current pose
+    Star {
+        points: Box<AstNode>,
+        length: Box<AstNode>,
+        line: i32,
+    },
+    /// Smooth curve from the current pose to a target point
+    CurveTo {
+        x: Box<AstNode>,
+        y: Box<AstNode>,
+        line: i32,
+    },
+    /// Circular arc of the given radius, turning through the given number of degrees
+    Arc {
+        radius: Box<AstNode>,
+        degrees: Box<AstNode>,
+        line: i32,
+    },
+    /// Text drawn in the current pen color, starting at the turtle's position
+    Label {
+        text: Box<AstNode>,
+        line: i32,
+    },
+    /// Flood fill of the region around the turtle with the current pen color
+    Fill {
+        line: i32,
+    },
+    /// Draw a triangle marking the turtle's position and heading, in the current pen color
+    ///
+    /// ```
+    /// use logolang_lib::interpreter::{Interpreter, PenColor};
+    /// use logolang_lib::parser::AstNode;
+    /// use unsvg::Image;
+    ///
+    /// // The marker is drawn with the pen up, pointing up from the centre of the image
+    /// let mut image = Image::new(100, 100);
+    /// let mut interpreter = Interpreter::new(&mut image);
+    /// interpreter.run(&vec![AstNode::Stamp { line: 1 }]).unwrap();
+    /// assert_eq!(interpreter.drawn_segments().len(), 3);
+    /// // Its tip is 8px ahead of the turtle, and its base 4px behind
+    /// assert_eq!(interpreter.pixel_color(50.0, 42.0), Some(PenColor::Indexed(7)));
+    /// assert_eq!(interpreter.pixel_color(50.0, 54.0), Some(PenColor::Indexed(7)));
+    /// assert_eq!(interpreter.pixel_color(50.0, 48.0), None);
+    ///
+    /// // The turtle is left where it was
+    /// assert_eq!(interpreter.position(), (50.0, 50.0));
+    /// assert_eq!(interpreter.heading(), 0.0);
+    /// assert!(!interpreter.is_pen_down());
+    /// ```
+    Stamp {
+        line: i32,
+    },
+    /// Reset of all interpreter state, optionally clearing the image
+    ResetAll {
+        clear_image: bool,
+    },
+    /// Return the turtle to the centre facing up, drawing a line there if the pen is down
+    Home {
+        line: i32,
+    },
+    /// Blank the image and return the turtle to the centre
+    ClearScreen {
+        line: i32,
+    },
+    /// Draw the turtle's path history, including pen-up moves
+    ShowPath {
+        line: i32,
+    },
+    /// Draw the coordinate axes overlay
+    Axes {
+        line: i32,
+    },
     /// String literals
     Word(String),
 }
 
+impl AstNode {
+    /// Returns the line a node starts at, or None for nodes which do not record one, such as
+    /// literals and PENUP / PENDOWN
+    pub fn line(&self) -> Option<i32> {
+        match self {
+            AstNode::MakeStmnt { line, .. }
+            | AstNode::ArithExpr { line, .. }
+            | AstNode::CompExpr { line, .. }
+            | AstNode::TrigExpr { line, .. }
+            | AstNode::RoundExpr { line, .. }
+            | AstNode::Negate { line, .. }
+            | AstNode::Abs { line, .. }
+            | AstNode::Sqrt { line, .. }
+            | AstNode::Random { line, .. }
+            | AstNode::BoolExpr { line, .. }
+            | AstNode::NotExpr { line, .. }
+            | AstNode::AddAssign { line, .. }
+            | AstNode::Ident { line, .. }
+            | AstNode::IfStmnt { line, .. }
+            | AstNode::IfElseStmnt { line, .. }
+            | AstNode::WhileStmnt { line, .. }
+            | AstNode::RepeatStmnt { line, .. }
+            | AstNode::ForStmnt { line, .. }
+            | AstNode::ForEachStmnt { line, .. }
+            | AstNode::PenColorUpdate { line, .. }
+            | AstNode::PenRgbUpdate { line, .. }
+            | AstNode::PenOpacityUpdate { line, .. }
+            | AstNode::PenWidthUpdate { line, .. }
+            | AstNode::PenDashUpdate { line, .. }
+            | AstNode::BackgroundUpdate { line, .. }
+            | AstNode::SavePen { line, .. }
+            | AstNode::LoadPen { line, .. }
+            | AstNode::Mark { line, .. }
+            | AstNode::GoMark { line, .. }
+            | AstNode::PenPosUpdate { line, .. }
+            | AstNode::SetPosition { line, .. }
+            | AstNode::Stop { line, .. }
+            | AstNode::Output { line, .. }
+            | AstNode::ProcedureRef { line, .. }
+            | AstNode::DrawInstruction { line, .. }
+            | AstNode::DrawAt { line, .. }
+            | AstNode::IfVal { line, .. }
+            | AstNode::ForwardDots { line, .. }
+            | AstNode::Star { line, .. }
+            | AstNode::CurveTo { line, .. }
+            | AstNode::Arc { line, .. }
+            | AstNode::Label { line, .. }
+            | AstNode::Fill { line, .. }
+            | AstNode::Stamp { line, .. }
+            | AstNode::Home { line, .. }
+            | AstNode::ClearScreen { line, .. }
+            | AstNode::ShowPath { line, .. }
+            | AstNode::Axes { line, .. } => Some(*line),
+            AstNode::IdentRef(_)
+            | AstNode::Num(_)
+            | AstNode::BoolLiteral(_)
+            | AstNode::Word(_)
+            | AstNode::PenStatusUpdate(_)
+            | AstNode::TurtleVisibility(_)
+            | AstNode::AngleUnits(_)
+            | AstNode::Query(_)
+            | AstNode::Procedure { .. }
+            | AstNode::ResetAll { .. } => None,
+        }
+    }
+}
+
 /// A trait implementation that defines the operations inherited by the node
 pub trait NodeType {
     fn is_numeric(&self) -> bool {
@@ -166,19 +633,50 @@ pub trait NodeType {
 
 impl NodeType for AstNode {
     fn is_numeric(&self) -> bool {
-        matches!(
-            self,
-            AstNode::Num(_) | AstNode::ArithExpr { .. } | AstNode::Query(_) | AstNode::IdentRef(_)
-        )
+        match self {
+            // Conditional expressions return the type shared by both branches
+            AstNode::IfVal {
+                then_val, else_val, ..
+            } => then_val.is_numeric() && else_val.is_numeric(),
+            _ => matches!(
+                self,
+                AstNode::Num(_)
+                    | AstNode::ArithExpr { .. }
+                    | AstNode::TrigExpr { .. }
+                    | AstNode::RoundExpr { .. }
+                    | AstNode::Negate { .. }
+                    | AstNode::Abs { .. }
+                    | AstNode::Sqrt { .. }
+                    | AstNode::Random { .. }
+                    | AstNode::Query(_)
+                    | AstNode::IdentRef(_)
+                    | AstNode::ProcedureRef { .. }
+            ),
+        }
     }
     fn is_boolean(&self) -> bool {
-        matches!(
-            &self,
-            AstNode::CompExpr { .. } | AstNode::BoolExpr { .. } | AstNode::IdentRef(_)
-        )
+        match self {
+            AstNode::IfVal {
+                then_val, else_val, ..
+            } => then_val.is_boolean() && else_val.is_boolean(),
+            _ => matches!(
+                &self,
+                AstNode::CompExpr { .. }
+                    | AstNode::BoolExpr { .. }
+                    | AstNode::NotExpr { .. }
+                    | AstNode::BoolLiteral(_)
+                    | AstNode::IdentRef(_)
+                    | AstNode::ProcedureRef { .. }
+            ),
+        }
     }
     fn is_word(&self) -> bool {
-        matches!(&self, AstNode::Word(_) | AstNode::IdentRef(_))
+        match self {
+            AstNode::IfVal {
+                then_val, else_val, ..
+            } => then_val.is_word() && else_val.is_word(),
+            _ => matches!(&self, AstNode::Word(_) | AstNode::IdentRef(_)),
+        }
     }
 }
 
@@ -187,6 +685,12 @@ impl NodeType for AstNode {
 pub struct Parser {
     // Keep track of the parameter names for each procedure
     proc_arg_map: HashMap<String, Rc<Vec<String>>>,
+    // Names of the procedures whose definitions have been parsed
+    defined_procs: HashSet<String>,
+    // Warnings from static analysis of the parsed program
+    warnings: Vec<String>,
+    // Whether a procedure body is being parsed, as OUTPUT is only valid within one
+    in_procedure: bool,
 }
 
 impl Default for Parser {
@@ -200,23 +704,69 @@ impl Parser {
     pub fn new() -> Self {
         Self {
             proc_arg_map: HashMap::new(),
+            defined_procs: HashSet::new(),
+            warnings: Vec::new(),
+            in_procedure: false,
         }
     }
 
+    /// Returns the warnings found while parsing the program, such as unreachable statements.
+    /// Warnings do not prevent the program from running.
+    pub fn warnings(&self) -> &Vec<String> {
+        &self.warnings
+    }
+
     /// Parses a given sequence of tokens into an abstract syntax tree (AST), as a collection of
-    /// AST nodes.
+    /// AST nodes. Procedures defined at the top level may be referenced before their
+    /// definitions.
     /// Returns a `ParserError` if any syntactic errors are encountered.
     pub fn parse(&mut self, tokens: VecDeque<Token>) -> Result<Vec<AstNode>, ParserError> {
         let mut tokens = tokens;
         let mut ast = Vec::new();
 
+        self.register_procedures(&tokens);
+
         while tokens.front().is_some() {
-            ast.push(self.expr(&mut tokens)?);
+            ast.push(self.statement(&mut tokens)?);
         }
 
+        self.warnings.extend(unreachable_statements(&ast));
+
         Ok(ast)
     }
 
+    /// Registers the parameters of each procedure defined at the top level of the program
+    /// before it is parsed, so references may appear before a procedure's definition.
+    /// Definitions are otherwise validated as they are parsed.
+    fn register_procedures(&mut self, tokens: &VecDeque<Token>) {
+        let mut depth = 0;
+        let mut tokens = tokens.iter().peekable();
+
+        while let Some(token) = tokens.next() {
+            match token.kind {
+                TokenKind::LPAREN => depth += 1,
+                TokenKind::RPAREN => depth -= 1,
+                TokenKind::PROCSTART if depth == 0 => {
+                    let Some(name) = tokens.next_if(|token| token.kind == TokenKind::PROCNAME)
+                    else {
+                        continue;
+                    };
+
+                    let mut params = Vec::new();
+                    while let Some(param) = tokens.next_if(|token| token.kind == TokenKind::IDENT) {
+                        params.push(param.value.to_string());
+                    }
+
+                    // Keep the first definition, as any later one is rejected when parsed
+                    self.proc_arg_map
+                        .entry(name.value.to_string())
+                        .or_insert_with(|| Rc::new(params));
+                }
+                _ => (),
+            }
+        }
+    }
+
     /// Parses tokens recursively to return valid AST nodes.
     fn expr(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
         if let Some(token) = tokens.front() {
@@ -224,27 +774,79 @@ impl Parser {
                 // num_expressions
                 TokenKind::ARITHOP => self.binary_op(tokens),
                 TokenKind::QUERY => self.query(tokens),
+                TokenKind::RANDOM => self.random(tokens),
+                TokenKind::TRIGFN => self.trig_expr(tokens),
+                TokenKind::ROUNDFN => self.round_expr(tokens),
+                TokenKind::NEGATE => self.negate(tokens),
+                TokenKind::ABS => self.abs(tokens),
+                TokenKind::SQRT => self.sqrt(tokens),
                 // bool_expressions
                 TokenKind::COMPOP => self.binary_op(tokens),
                 TokenKind::BOOLOP => self.binary_op(tokens),
+                TokenKind::NOTOP => self.not_expr(tokens),
+                // expression of either type
+                TokenKind::IFVAL => self.if_val(tokens),
                 // num or bool expression
                 TokenKind::IDENTREF => self.ident_ref(tokens),
+                // grouped expression
+                TokenKind::LGROUP => self.group(tokens),
                 // statements
                 TokenKind::MAKEOP => self.make_op(tokens),
                 TokenKind::ADDASSIGN => self.add_assign(tokens),
                 TokenKind::DIRECTION => self.draw_line(tokens),
+                TokenKind::DRAWAT => self.draw_at(tokens),
+                TokenKind::FORWARDDOTS => self.forward_dots(tokens),
+                TokenKind::STAR => self.star(tokens),
+                TokenKind::CURVETO => self.curve_to(tokens),
+                TokenKind::ARC => self.arc(tokens),
+                TokenKind::LABEL => self.label(tokens),
+                TokenKind::FILL => self.fill(tokens),
+                TokenKind::STAMP => self.stamp(tokens),
                 TokenKind::IFSTMNT => self.if_while_statement(tokens),
                 TokenKind::WHILESTMNT => self.if_while_statement(tokens),
+                TokenKind::IFELSE => self.if_else_statement(tokens),
+                TokenKind::REPEAT => self.repeat_statement(tokens),
+                TokenKind::FOR => self.for_statement(tokens),
+                TokenKind::FOREACH => self.for_each_statement(tokens),
                 TokenKind::PENSTATUS => self.pen_status_update(tokens),
+                TokenKind::TURTLEVISIBILITY => self.turtle_visibility(tokens),
+                TokenKind::ANGLEUNITS => self.angle_units(tokens),
                 TokenKind::PENCOLOR => self.pen_color_update(tokens),
+                TokenKind::PENRGB => self.pen_rgb_update(tokens),
+                TokenKind::PENOPACITY => self.pen_opacity_update(tokens),
+                TokenKind::PENWIDTH => self.pen_width_update(tokens),
+                TokenKind::PENDASH => self.pen_dash_update(tokens),
                 TokenKind::PENPOS => self.pen_position_update(tokens),
+                TokenKind::SETPOS => self.set_position(tokens),
+                TokenKind::PENPRESET => self.pen_preset(tokens),
+                TokenKind::MARK => self.mark(tokens),
+                TokenKind::BACKGROUND => self.background_update(tokens),
+                TokenKind::RESETALL => self.reset_all(tokens),
+                TokenKind::CLEARSCREEN => self.clear_screen(tokens),
+                TokenKind::HOME => self.home(tokens),
+                TokenKind::SHOWPATH | TokenKind::AXES => self.debug_overlay(tokens),
                 TokenKind::PROCSTART => self.procedure(tokens),
                 TokenKind::PROCNAME => self.procedure_reference(tokens),
+                TokenKind::STOP => self.stop(tokens),
+                TokenKind::OUTPUT => self.output(tokens),
                 // Terminal
                 TokenKind::NUM => self.num(tokens),
+                TokenKind::BOOL => self.bool_literal(tokens),
                 // If an ident it received here, it is not bound: treat it as a raw string
                 TokenKind::IDENT => self.raw_string(tokens),
-                _ => unreachable!("LPAREN, RPAREN & PROCEND are handled within PROCSTART match"),
+                // Brackets and END are only valid as part of an enclosing block, a ')' only as
+                // the end of a group and an angle unit only as the argument to ANGLEUNITS
+                TokenKind::LPAREN
+                | TokenKind::RPAREN
+                | TokenKind::RGROUP
+                | TokenKind::ANGLEUNIT
+                | TokenKind::PROCEND => Err(ParserError::UnexpectedToken(
+                    token.line.to_string(),
+                    token.value.to_string(),
+                )),
+                TokenKind::DEFINE => {
+                    unreachable!("Macro definitions are removed from the token stream by the lexer")
+                }
             }
         } else {
             Err(ParserError::UnexpectedEnding)
@@ -269,6 +871,14 @@ impl Parser {
             ));
         }
 
+        // Variables must not shadow keywords or queries
+        if is_reserved_word(&ident_token.value) {
+            return Err(ParserError::ReservedName(
+                ident_token.line.to_string(),
+                ident_token.value,
+            ));
+        }
+
         // Parse the expression which is bound to the identifier
         let expr = self.expr(tokens).with_context(|| {
             format!(
@@ -325,12 +935,26 @@ impl Parser {
                     ));
                 }
             }
+            // Equality is defined between values of any types, so only statements are rejected
+            TokenKind::COMPOP if matches!(operator_token.value.as_str(), "EQ" | "NE") => {
+                let is_value =
+                    |arg: &AstNode| arg.is_numeric() || arg.is_boolean() || arg.is_word();
+                if !is_value(&left) || !is_value(&right) {
+                    return Err(ParserError::IncorrectArgType(
+                        operator_token.line.to_string(),
+                        format!(
+                            "Arguments to '{}' must be expressions which return a value.",
+                            operator_token.value
+                        ),
+                    ));
+                }
+            }
             // Note: if left and right are both IDENTREF's whos underlying values types are mismatched, they will not return an
             // error here, as they return true for both is_boolean() and is_numeric(). This is intended, as the parser only checks
             // for syntactic errors, while the interpreter will check for semantic errors.
             TokenKind::COMPOP => {
-                if !(left.is_boolean() && left.is_numeric())
-                    && !(right.is_boolean() && right.is_numeric())
+                if !(left.is_boolean() && left.is_numeric()
+                    || right.is_boolean() && right.is_numeric())
                     && (left.is_boolean() != right.is_boolean()
                         || left.is_numeric() != right.is_numeric()
                         || left.is_word() != right.is_word())
@@ -360,6 +984,10 @@ impl Parser {
                     "-" => ArithOp::SUB,
                     "*" => ArithOp::MUL,
                     "/" => ArithOp::DIV,
+                    "%" | "MOD" => ArithOp::MOD,
+                    "POW" => ArithOp::POW,
+                    "MIN" => ArithOp::MIN,
+                    "MAX" => ArithOp::MAX,
                     _ => unreachable!("Lexer only produces these binary operators"),
                 },
                 left: Box::new(left),
@@ -404,6 +1032,14 @@ impl Parser {
             .expect("Num tokens are already verified as parsing to f32 in lexer");
         Ok(AstNode::Num(num_value))
     }
+    /// Parses a token into a boolean literal node
+    fn bool_literal(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let bool_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        Ok(AstNode::BoolLiteral(bool_token.value == "TRUE"))
+    }
     /// Parses a token into a identifier reference (the value bound a the identifier) node
     fn ident_ref(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
         let ident_token = tokens
@@ -426,7 +1062,7 @@ impl Parser {
         let parsed_value = self.expr(tokens)?;
 
         // Handle extra arguments
-        check_extra_args(tokens, pos_token.line)
+        check_extra_args(tokens, &pos_token)
             .with_context(|| format!("Error parsing '{}' expression", pos_token.value))?;
 
         Ok(AstNode::PenPosUpdate {
@@ -441,6 +1077,21 @@ impl Parser {
             line: pos_token.line,
         })
     }
+    /// Parses tokens into a SETPOS node
+    fn set_position(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let pos_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let [x, y] = self.numeric_args(tokens, &pos_token)?;
+
+        Ok(AstNode::SetPosition {
+            x: Box::new(x),
+            y: Box::new(y),
+            line: pos_token.line,
+        })
+    }
+
     /// Parses tokens into a pen status update node (penup / pendown)
     fn pen_status_update(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
         let status_token = tokens
@@ -448,7 +1099,7 @@ impl Parser {
             .expect("Token must have been verified to be passed to fn");
 
         // Handle extra arguments
-        check_extra_args(tokens, status_token.line)
+        check_extra_args(tokens, &status_token)
             .with_context(|| format!("Error parsing '{}' expression", status_token.value))?;
 
         Ok(AstNode::PenStatusUpdate(
@@ -459,6 +1110,55 @@ impl Parser {
             },
         ))
     }
+    /// Parses tokens into a turtle visibility node
+    fn turtle_visibility(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let visibility_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Handle extra arguments
+        check_extra_args(tokens, &visibility_token)
+            .with_context(|| format!("Error parsing '{}' expression", visibility_token.value))?;
+
+        Ok(AstNode::TurtleVisibility(
+            match visibility_token.value.as_str() {
+                "HIDETURTLE" => false,
+                "SHOWTURTLE" => true,
+                _ => unreachable!("Lexer only produces these visibility commands"),
+            },
+        ))
+    }
+
+    /// Parses tokens into an angle units node
+    fn angle_units(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let units_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let unit_token = tokens.pop_front().ok_or(ParserError::UnexpectedEnding)?;
+
+        // Verify a unit was provided
+        if unit_token.kind != TokenKind::ANGLEUNIT {
+            return Err(ParserError::IncorrectArgType(
+                units_token.line.to_string(),
+                format!(
+                    "Invalid ANGLEUNITS statement. Expected DEGREES or RADIANS, instead received: {}.",
+                    unit_token.value
+                ),
+            ));
+        }
+
+        // Handle extra arguments
+        check_extra_args(tokens, &units_token)
+            .with_context(|| format!("Error parsing '{}' expression", units_token.value))?;
+
+        Ok(AstNode::AngleUnits(match unit_token.value.as_str() {
+            "DEGREES" => AngleUnit::Degrees,
+            "RADIANS" => AngleUnit::Radians,
+            _ => unreachable!("Lexer only produces these angle units"),
+        }))
+    }
+
     /// Parses tokens into a pen colour update node
     fn pen_color_update(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
         let col_token = tokens
@@ -469,7 +1169,7 @@ impl Parser {
         let parsed_value = self.expr(tokens)?;
 
         // Handle extra arguments
-        check_extra_args(tokens, col_token.line)
+        check_extra_args(tokens, &col_token)
             .with_context(|| format!("Error parsing '{}' expression", col_token.value))?;
 
         Ok(AstNode::PenColorUpdate {
@@ -477,120 +1177,949 @@ impl Parser {
             line: col_token.line,
         })
     }
-    /// Parses tokens into a query node (xcor, ycor, heading, color)
-    fn query(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
-        let query_token = tokens
+
+    /// Parses tokens into a SETRGB node (red, green, blue)
+    fn pen_rgb_update(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let rgb_token = tokens
             .pop_front()
             .expect("Token must have been verified to be passed to fn");
 
-        Ok(AstNode::Query(match query_token.value.as_str() {
-            "XCOR" => QueryKind::XCOR,
-            "YCOR" => QueryKind::YCOR,
-            "HEADING" => QueryKind::HEADING,
-            "COLOR" => QueryKind::COLOR,
-            _ => unreachable!("Lexer only produces these binary operators"),
-        }))
+        let [red, green, blue] = self.numeric_args(tokens, &rgb_token)?;
+
+        Ok(AstNode::PenRgbUpdate {
+            red: Box::new(red),
+            green: Box::new(green),
+            blue: Box::new(blue),
+            line: rgb_token.line,
+        })
     }
-    /// Parses tokens into an if / while statement node
-    fn if_while_statement(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
-        let if_while_token = tokens
+
+    /// Parses tokens into a SETOPACITY node
+    fn pen_opacity_update(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let opacity_token = tokens
             .pop_front()
             .expect("Token must have been verified to be passed to fn");
 
-        let statement_type = if if_while_token.kind == TokenKind::IFSTMNT {
-            "IF"
-        } else {
-            "WHILE"
-        };
-        // Parse the condition which if statement checks
-        let condition_token = self.expr(tokens).with_context(|| {
-            format!(
-                "\t[Line {0}]: Invalid {1} statement: Failed to parse expression provided to {1}",
-                if_while_token.line, statement_type
-            )
-        })?;
+        let [opacity] = self.numeric_args(tokens, &opacity_token)?;
 
-        // Check the validity of the provided expressions
-        if !condition_token.is_boolean() {
-            return Err(ParserError::NonBooleanExpr(
-                if_while_token.line.to_string(),
-                if_while_token.value.to_string(),
-            ));
-        }
+        Ok(AstNode::PenOpacityUpdate {
+            opacity: Box::new(opacity),
+            line: opacity_token.line,
+        })
+    }
 
-        // Parse body opening parenthesis
-        let l_paren_token = tokens
+    /// Parses tokens into a SETBACKGROUND node
+    fn background_update(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let background_token = tokens
             .pop_front()
-            .ok_or(ParserError::UnexpectedEnding)
-            .expect("Checked validity in ok_or");
-
-        if l_paren_token.kind != TokenKind::LPAREN {
-            return Err(ParserError::MissingParenthesis(
-                l_paren_token.line.to_string(),
-                if_while_token.value.to_string(),
-                "[".to_string(),
-                l_paren_token.value.to_string(),
-            ));
-        };
+            .expect("Token must have been verified to be passed to fn");
 
-        // Store the expressions/statements within the body of the if statement
-        let mut body_tokens = Vec::<AstNode>::new();
+        let [color] = self.numeric_args(tokens, &background_token)?;
 
-        // Parse body until closing parenthesis is seen.
-        while let Some(token) = tokens.front() {
-            if token.kind == TokenKind::RPAREN {
-                break;
-            }
-            let current_expr = self.expr(tokens).with_context(|| {
-                format!(
-                    "\t[Line {}]: Invalid expression found within {} statement body.",
-                    l_paren_token.line, statement_type
-                )
-            })?;
-            body_tokens.push(current_expr);
-        }
+        Ok(AstNode::BackgroundUpdate {
+            color: Box::new(color),
+            line: background_token.line,
+        })
+    }
 
-        // Verify if we saw the closing parenthesis, or if we ran out of tokens
-        let r_paren_token = tokens
+    /// Parses tokens into a SETPENWIDTH node
+    fn pen_width_update(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let width_token = tokens
             .pop_front()
-            .ok_or(ParserError::UnexpectedEnding)
-            .expect("Checked validity in ok_or");
+            .expect("Token must have been verified to be passed to fn");
 
-        if r_paren_token.kind != TokenKind::RPAREN {
-            return Err(ParserError::MissingParenthesis(
-                r_paren_token.line.to_string(),
-                if_while_token.value.to_string(),
-                "]".to_string(),
-                l_paren_token.value.to_string(),
-            ));
-        };
+        let [width] = self.numeric_args(tokens, &width_token)?;
 
-        // Return node based on token kind
-        if if_while_token.kind == TokenKind::IFSTMNT {
-            Ok(AstNode::IfStmnt {
-                condition: Box::new(condition_token),
-                body: Box::new(body_tokens),
-                line: if_while_token.line,
-            })
-        } else {
-            Ok(AstNode::WhileStmnt {
-                condition: Box::new(condition_token),
-                body: Box::new(body_tokens),
-                line: if_while_token.line,
-            })
-        }
+        Ok(AstNode::PenWidthUpdate {
+            width: Box::new(width),
+            line: width_token.line,
+        })
     }
-    /// Parses tokens into an addition assignment node
-    fn add_assign(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
-        // Consume the operator token
-        tokens
+
+    /// Parses tokens into a pen dash update node
+    fn pen_dash_update(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let dash_token = tokens
             .pop_front()
             .expect("Token must have been verified to be passed to fn");
 
-        // Parse the next token
-        let var_token = tokens
-            .pop_front()
-            .ok_or(ParserError::UnexpectedEnding)
+        let [on, off] = self.numeric_args(tokens, &dash_token)?;
+
+        Ok(AstNode::PenDashUpdate {
+            on: Box::new(on),
+            off: Box::new(off),
+            line: dash_token.line,
+        })
+    }
+
+    /// Parses tokens into a RESETALL node. An optional "CLEAR argument also clears the image.
+    fn reset_all(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let reset_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let clear_image = matches!(
+            tokens.front(),
+            Some(token) if token.statement_line == reset_token.statement_line
+                && token.kind == TokenKind::IDENT
+                && token.value == "CLEAR"
+        );
+        if clear_image {
+            tokens.pop_front();
+        }
+
+        // Handle extra arguments
+        check_extra_args(tokens, &reset_token)
+            .with_context(|| format!("Error parsing '{}' expression", reset_token.value))?;
+
+        Ok(AstNode::ResetAll { clear_image })
+    }
+
+    /// Parses tokens into a debug overlay node (SHOWPATH / AXES)
+    fn debug_overlay(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let overlay_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Handle extra arguments
+        check_extra_args(tokens, &overlay_token)
+            .with_context(|| format!("Error parsing '{}' expression", overlay_token.value))?;
+
+        let line = overlay_token.line;
+        Ok(match overlay_token.kind {
+            TokenKind::SHOWPATH => AstNode::ShowPath { line },
+            TokenKind::AXES => AstNode::Axes { line },
+            _ => unreachable!("Lexer only produces these debug overlays"),
+        })
+    }
+
+    /// Parses tokens into a HOME node
+    fn home(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let home_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Handle extra arguments
+        check_extra_args(tokens, &home_token)
+            .with_context(|| format!("Error parsing '{}' expression", home_token.value))?;
+
+        Ok(AstNode::Home {
+            line: home_token.line,
+        })
+    }
+
+    /// Parses tokens into a FILL node
+    fn fill(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let fill_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Handle extra arguments
+        check_extra_args(tokens, &fill_token)
+            .with_context(|| format!("Error parsing '{}' expression", fill_token.value))?;
+
+        Ok(AstNode::Fill {
+            line: fill_token.line,
+        })
+    }
+
+    /// Parses tokens into a STAMP node
+    fn stamp(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let stamp_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Handle extra arguments
+        check_extra_args(tokens, &stamp_token)
+            .with_context(|| format!("Error parsing '{}' expression", stamp_token.value))?;
+
+        Ok(AstNode::Stamp {
+            line: stamp_token.line,
+        })
+    }
+
+    /// Parses tokens into a CLEARSCREEN node
+    fn clear_screen(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let clear_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Handle extra arguments
+        check_extra_args(tokens, &clear_token)
+            .with_context(|| format!("Error parsing '{}' expression", clear_token.value))?;
+
+        Ok(AstNode::ClearScreen {
+            line: clear_token.line,
+        })
+    }
+
+    /// Parses tokens into a STOP node
+    fn stop(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let stop_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Handle extra arguments
+        check_extra_args(tokens, &stop_token)
+            .with_context(|| format!("Error parsing '{}' expression", stop_token.value))?;
+
+        Ok(AstNode::Stop {
+            line: stop_token.line,
+        })
+    }
+
+    /// Parses tokens into an OUTPUT node. OUTPUT is only valid within a procedure body.
+    fn output(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let output_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        if !self.in_procedure {
+            return Err(ParserError::OutputOutsideProcedure(
+                output_token.line.to_string(),
+            ));
+        }
+
+        let value = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid argument to {}\n",
+                output_token.line, output_token.value
+            )
+        })?;
+
+        // The output must be an expression, which returns a value
+        if !value.is_numeric() && !value.is_boolean() && !value.is_word() {
+            return Err(ParserError::IncorrectArgType(
+                output_token.line.to_string(),
+                String::from("Invalid OUTPUT statement. OUTPUT received an argument which does not return a value."),
+            ));
+        }
+
+        Ok(AstNode::Output {
+            value: Box::new(value),
+            line: output_token.line,
+        })
+    }
+
+    /// Parses tokens into a pen preset node (savepen / loadpen)
+    fn pen_preset(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let preset_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let name_token = tokens.pop_front().ok_or(ParserError::UnexpectedEnding)?;
+
+        // Verify a preset name was provided
+        if name_token.kind != TokenKind::IDENT {
+            return Err(ParserError::IncorrectArgType(
+                preset_token.line.to_string(),
+                format!(
+                    "Invalid {} statement. {} did not receive a preset name, instead received: {}.",
+                    preset_token.value, preset_token.value, name_token.value
+                ),
+            ));
+        }
+
+        // Handle extra arguments
+        check_extra_args(tokens, &preset_token)
+            .with_context(|| format!("Error parsing '{}' expression", preset_token.value))?;
+
+        Ok(match preset_token.value.as_str() {
+            "SAVEPEN" => AstNode::SavePen {
+                name: name_token.value,
+                line: preset_token.line,
+            },
+            "LOADPEN" => AstNode::LoadPen {
+                name: name_token.value,
+                line: preset_token.line,
+            },
+            _ => unreachable!("Lexer only produces these pen presets"),
+        })
+    }
+
+    /// Parses tokens into a mark node (mark / gomark)
+    fn mark(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let mark_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let name_token = tokens.pop_front().ok_or(ParserError::UnexpectedEnding)?;
+
+        // Verify a mark name was provided
+        if name_token.kind != TokenKind::IDENT {
+            return Err(ParserError::IncorrectArgType(
+                mark_token.line.to_string(),
+                format!(
+                    "Invalid {} statement. {} did not receive a mark name, instead received: {}.",
+                    mark_token.value, mark_token.value, name_token.value
+                ),
+            ));
+        }
+
+        // Handle extra arguments
+        check_extra_args(tokens, &mark_token)
+            .with_context(|| format!("Error parsing '{}' expression", mark_token.value))?;
+
+        Ok(match mark_token.value.as_str() {
+            "MARK" => AstNode::Mark {
+                name: name_token.value,
+                line: mark_token.line,
+            },
+            "GOMARK" => AstNode::GoMark {
+                name: name_token.value,
+                line: mark_token.line,
+            },
+            _ => unreachable!("Lexer only produces these mark commands"),
+        })
+    }
+    /// Parses tokens into a query node (xcor, ycor, heading, color)
+    fn query(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let query_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        Ok(AstNode::Query(match query_token.value.as_str() {
+            "XCOR" => QueryKind::XCOR,
+            "YCOR" => QueryKind::YCOR,
+            "HEADING" => QueryKind::HEADING,
+            "COLOR" => QueryKind::COLOR,
+            "NUMCOLORS" => QueryKind::NUMCOLORS,
+            "TOTALDIST" => QueryKind::TOTALDIST,
+            "PENWIDTH" => QueryKind::PENWIDTH,
+            "PIXELHERE" => QueryKind::PIXELHERE,
+            _ => unreachable!("Lexer only produces these binary operators"),
+        }))
+    }
+    /// Parses tokens into a random number node
+    fn random(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let random_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let max = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid RANDOM expression: Failed to parse expression provided to RANDOM",
+                random_token.line
+            )
+        })?;
+
+        // Check the validity of the provided expression
+        if !max.is_numeric() {
+            return Err(ParserError::NonNumericExpr(
+                random_token.line.to_string(),
+                random_token.value.to_string(),
+            ));
+        }
+
+        Ok(AstNode::Random {
+            max: Box::new(max),
+            line: random_token.line,
+        })
+    }
+    /// Parses tokens into a conditional expression node (IFVAL condition a b)
+    fn if_val(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let if_val_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let condition = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid IFVAL expression: Failed to parse condition",
+                if_val_token.line
+            )
+        })?;
+
+        // Check the validity of the condition
+        if !condition.is_boolean() {
+            return Err(ParserError::NonBooleanExpr(
+                if_val_token.line.to_string(),
+                if_val_token.value.to_string(),
+            ));
+        }
+
+        let then_val = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid IFVAL expression: Failed to parse the first value",
+                if_val_token.line
+            )
+        })?;
+
+        let else_val = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid IFVAL expression: Failed to parse the second value",
+                if_val_token.line
+            )
+        })?;
+
+        // Both branches must return a value
+        for branch in [&then_val, &else_val] {
+            if !branch.is_numeric() && !branch.is_boolean() && !branch.is_word() {
+                return Err(ParserError::IncorrectArgType(
+                    if_val_token.line.to_string(),
+                    "Invalid IFVAL expression. Both values must be expressions which return a float, a boolean or a word.".to_string(),
+                ));
+            }
+        }
+
+        Ok(AstNode::IfVal {
+            condition: Box::new(condition),
+            then_val: Box::new(then_val),
+            else_val: Box::new(else_val),
+            line: if_val_token.line,
+        })
+    }
+    /// Parses tokens into an if / while statement node
+    fn if_while_statement(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let if_while_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let statement_type = if if_while_token.kind == TokenKind::IFSTMNT {
+            "IF"
+        } else {
+            "WHILE"
+        };
+        // Parse the condition which if statement checks
+        let condition_token = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {0}]: Invalid {1} statement: Failed to parse expression provided to {1}",
+                if_while_token.line, statement_type
+            )
+        })?;
+
+        // Check the validity of the provided expressions
+        if !condition_token.is_boolean() {
+            return Err(ParserError::NonBooleanExpr(
+                if_while_token.line.to_string(),
+                if_while_token.value.to_string(),
+            ));
+        }
+
+        let body_tokens = self.statement_body(tokens, &if_while_token, statement_type)?;
+
+        // Return node based on token kind
+        if if_while_token.kind == TokenKind::IFSTMNT {
+            Ok(AstNode::IfStmnt {
+                condition: Box::new(condition_token),
+                body: Box::new(body_tokens),
+                line: if_while_token.line,
+            })
+        } else {
+            Ok(AstNode::WhileStmnt {
+                condition: Box::new(condition_token),
+                body: Box::new(body_tokens),
+                line: if_while_token.line,
+            })
+        }
+    }
+    /// Parses tokens into an if-else statement node
+    fn if_else_statement(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let if_else_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let condition_token = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid IFELSE statement: Failed to parse expression provided to IFELSE",
+                if_else_token.line
+            )
+        })?;
+
+        // Check the validity of the provided expressions
+        if !condition_token.is_boolean() {
+            return Err(ParserError::NonBooleanExpr(
+                if_else_token.line.to_string(),
+                if_else_token.value.to_string(),
+            ));
+        }
+
+        let then_tokens = self.statement_body(tokens, &if_else_token, "IFELSE")?;
+
+        // An IFELSE in place of the else body continues a chain of conditions
+        let else_tokens = match tokens.front() {
+            Some(token) if token.kind == TokenKind::IFELSE => {
+                vec![self.if_else_statement(tokens).with_context(|| {
+                    format!(
+                        "\t[Line {}]: Invalid IFELSE statement: Failed to parse the chained IFELSE",
+                        if_else_token.line
+                    )
+                })?]
+            }
+            _ => self.statement_body(tokens, &if_else_token, "IFELSE")?,
+        };
+
+        Ok(AstNode::IfElseStmnt {
+            condition: Box::new(condition_token),
+            then_body: Box::new(then_tokens),
+            else_body: Box::new(else_tokens),
+            line: if_else_token.line,
+        })
+    }
+    /// Parses tokens into a trigonometric expression node
+    fn trig_expr(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let trig_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let operand = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {0}]: Invalid {1} expression: Failed to parse expression provided to {1}",
+                trig_token.line, trig_token.value
+            )
+        })?;
+
+        // Check the validity of the provided expression
+        if !operand.is_numeric() {
+            return Err(ParserError::NonNumericExpr(
+                trig_token.line.to_string(),
+                trig_token.value.to_string(),
+            ));
+        }
+
+        Ok(AstNode::TrigExpr {
+            function: match trig_token.value.as_str() {
+                "SIN" => TrigFn::SIN,
+                "COS" => TrigFn::COS,
+                "TAN" => TrigFn::TAN,
+                _ => unreachable!("Lexer only produces these trigonometric functions"),
+            },
+            operand: Box::new(operand),
+            line: trig_token.line,
+        })
+    }
+
+    /// Parses tokens into a rounding expression node
+    fn round_expr(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let round_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let operand = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {0}]: Invalid {1} expression: Failed to parse expression provided to {1}",
+                round_token.line, round_token.value
+            )
+        })?;
+
+        // Check the validity of the provided expression
+        if !operand.is_numeric() {
+            return Err(ParserError::NonNumericExpr(
+                round_token.line.to_string(),
+                round_token.value.to_string(),
+            ));
+        }
+
+        Ok(AstNode::RoundExpr {
+            function: match round_token.value.as_str() {
+                "FLOOR" => RoundFn::FLOOR,
+                "ROUND" => RoundFn::ROUND,
+                "INT" => RoundFn::INT,
+                _ => unreachable!("Lexer only produces these rounding functions"),
+            },
+            operand: Box::new(operand),
+            line: round_token.line,
+        })
+    }
+
+    /// Parses tokens into a negation node
+    fn negate(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let negate_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let operand = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid NEG expression: Failed to parse expression provided to NEG",
+                negate_token.line
+            )
+        })?;
+
+        // Check the validity of the provided expression
+        if !operand.is_numeric() {
+            return Err(ParserError::NonNumericExpr(
+                negate_token.line.to_string(),
+                negate_token.value.to_string(),
+            ));
+        }
+
+        Ok(AstNode::Negate {
+            operand: Box::new(operand),
+            line: negate_token.line,
+        })
+    }
+
+    /// Parses tokens into an absolute value node
+    fn abs(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let abs_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let operand = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid ABS expression: Failed to parse expression provided to ABS",
+                abs_token.line
+            )
+        })?;
+
+        // Check the validity of the provided expression
+        if !operand.is_numeric() {
+            return Err(ParserError::NonNumericExpr(
+                abs_token.line.to_string(),
+                abs_token.value.to_string(),
+            ));
+        }
+
+        Ok(AstNode::Abs {
+            operand: Box::new(operand),
+            line: abs_token.line,
+        })
+    }
+
+    /// Parses tokens into a square root node
+    fn sqrt(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let sqrt_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let operand = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid SQRT expression: Failed to parse expression provided to SQRT",
+                sqrt_token.line
+            )
+        })?;
+
+        // Check the validity of the provided expression
+        if !operand.is_numeric() {
+            return Err(ParserError::NonNumericExpr(
+                sqrt_token.line.to_string(),
+                sqrt_token.value.to_string(),
+            ));
+        }
+
+        Ok(AstNode::Sqrt {
+            operand: Box::new(operand),
+            line: sqrt_token.line,
+        })
+    }
+
+    /// Parses a parenthesised expression, which is equivalent to the expression it encloses
+    fn group(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let l_group_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let expr = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Failed to parse expression within parentheses",
+                l_group_token.line
+            )
+        })?;
+
+        // Only expressions, which return a value, may be grouped
+        if !expr.is_numeric() && !expr.is_boolean() && !expr.is_word() {
+            return Err(ParserError::IncorrectArgType(
+                l_group_token.line.to_string(),
+                "Parentheses may only enclose an expression which returns a value.".to_string(),
+            ));
+        }
+
+        match tokens.pop_front() {
+            Some(token) if token.kind == TokenKind::RGROUP => Ok(expr),
+            Some(token) => Err(ParserError::UnbalancedGroup(
+                l_group_token.line.to_string(),
+                token.value,
+            )),
+            None => Err(ParserError::UnbalancedGroup(
+                l_group_token.line.to_string(),
+                "the end of the file".to_string(),
+            )),
+        }
+    }
+
+    /// Parses tokens into a logical negation node
+    fn not_expr(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let not_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let operand = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid NOT expression: Failed to parse expression provided to NOT",
+                not_token.line
+            )
+        })?;
+
+        // Check the validity of the provided expression
+        if !operand.is_boolean() {
+            return Err(ParserError::NonBooleanExpr(
+                not_token.line.to_string(),
+                not_token.value.to_string(),
+            ));
+        }
+
+        Ok(AstNode::NotExpr {
+            operand: Box::new(operand),
+            line: not_token.line,
+        })
+    }
+
+    /// Parses tokens into a repeat statement node
+    fn repeat_statement(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let repeat_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Parse the number of times to evaluate the body
+        let count = self.expr(tokens).with_context(|| {
+            format!(
+                "\t[Line {}]: Invalid REPEAT statement: Failed to parse expression provided to REPEAT",
+                repeat_token.line
+            )
+        })?;
+
+        // Check the validity of the provided expressions
+        if !count.is_numeric() {
+            return Err(ParserError::NonNumericExpr(
+                repeat_token.line.to_string(),
+                repeat_token.value.to_string(),
+            ));
+        }
+
+        let body = self.statement_body(tokens, &repeat_token, "REPEAT")?;
+
+        Ok(AstNode::RepeatStmnt {
+            count: Box::new(count),
+            body: Box::new(body),
+            line: repeat_token.line,
+        })
+    }
+
+    /// Parses tokens into a for statement node
+    fn for_statement(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let for_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Parse the variable bound to each value in turn
+        let var_token = tokens.pop_front().ok_or(ParserError::UnexpectedEnding)?;
+        if var_token.kind != TokenKind::IDENT {
+            return Err(ParserError::IncorrectArgType(
+                for_token.line.to_string(),
+                format!(
+                    "Invalid FOR statement. FOR did not receive a variable, instead received: {}.",
+                    var_token.value
+                ),
+            ));
+        }
+
+        // Variables must not shadow keywords or queries
+        if is_reserved_word(&var_token.value) {
+            return Err(ParserError::ReservedName(
+                var_token.line.to_string(),
+                var_token.value,
+            ));
+        }
+
+        // Parse the start, end and step of the range
+        let mut bounds = Vec::with_capacity(3);
+        for bound in ["start", "end", "step"] {
+            let expr = self.expr(tokens).with_context(|| {
+                format!(
+                    "\t[Line {}]: Invalid FOR statement: Failed to parse the {} of the range",
+                    for_token.line, bound
+                )
+            })?;
+
+            // Check the validity of the provided expressions
+            if !expr.is_numeric() {
+                return Err(ParserError::NonNumericExpr(
+                    for_token.line.to_string(),
+                    for_token.value.to_string(),
+                ));
+            }
+            bounds.push(Box::new(expr));
+        }
+        let [start, end, step]: [Box<AstNode>; 3] = bounds
+            .try_into()
+            .expect("Exactly three bounds are parsed above");
+
+        let body = self.statement_body(tokens, &for_token, "FOR")?;
+
+        Ok(AstNode::ForStmnt {
+            var: var_token.value,
+            start,
+            end,
+            step,
+            body: Box::new(body),
+            line: for_token.line,
+        })
+    }
+
+    /// Parses tokens into a for-each statement node
+    fn for_each_statement(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let for_each_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Parse the variable bound to each value in turn
+        let var_token = tokens.pop_front().ok_or(ParserError::UnexpectedEnding)?;
+        if var_token.kind != TokenKind::IDENT {
+            return Err(ParserError::IncorrectArgType(
+                for_each_token.line.to_string(),
+                format!(
+                    "Invalid FOREACH statement. FOREACH did not receive a variable, instead received: {}.",
+                    var_token.value
+                ),
+            ));
+        }
+
+        // Variables must not shadow keywords or queries
+        if is_reserved_word(&var_token.value) {
+            return Err(ParserError::ReservedName(
+                var_token.line.to_string(),
+                var_token.value,
+            ));
+        }
+
+        let values = self.value_list(tokens, &for_each_token)?;
+        let body = self.statement_body(tokens, &for_each_token, "FOREACH")?;
+
+        Ok(AstNode::ForEachStmnt {
+            var: var_token.value,
+            values,
+            body: Box::new(body),
+            line: for_each_token.line,
+        })
+    }
+
+    /// Parses a bracketed list of numeric expressions, such as the values of a FOREACH statement
+    fn value_list(
+        &mut self,
+        tokens: &mut VecDeque<Token>,
+        statement_token: &Token,
+    ) -> Result<Vec<AstNode>, ParserError> {
+        // Parse list opening parenthesis
+        let l_paren_token = tokens.pop_front().ok_or(ParserError::UnexpectedEnding)?;
+        if l_paren_token.kind != TokenKind::LPAREN {
+            return Err(ParserError::MissingParenthesis(
+                l_paren_token.line.to_string(),
+                statement_token.value.to_string(),
+                "[".to_string(),
+                l_paren_token.value.to_string(),
+            ));
+        }
+
+        // Parse values until closing parenthesis is seen
+        let mut values = Vec::new();
+        while let Some(token) = tokens.front() {
+            if token.kind == TokenKind::RPAREN {
+                break;
+            }
+            let value = self.expr(tokens).with_context(|| {
+                format!(
+                    "\t[Line {}]: Invalid value found within {} list.",
+                    l_paren_token.line, statement_token.value
+                )
+            })?;
+
+            // Check the validity of the provided expression
+            if !value.is_numeric() {
+                return Err(ParserError::NonNumericExpr(
+                    statement_token.line.to_string(),
+                    statement_token.value.to_string(),
+                ));
+            }
+            values.push(value);
+        }
+
+        // Verify if we saw the closing parenthesis, or if we ran out of tokens
+        if tokens.pop_front().is_none() {
+            return Err(ParserError::MissingParenthesis(
+                l_paren_token.line.to_string(),
+                statement_token.value.to_string(),
+                "]".to_string(),
+                "the end of the file".to_string(),
+            ));
+        }
+
+        Ok(values)
+    }
+
+    /// Parses the bracketed body of an if / while / repeat statement
+    fn statement_body(
+        &mut self,
+        tokens: &mut VecDeque<Token>,
+        statement_token: &Token,
+        statement_type: &str,
+    ) -> Result<Vec<AstNode>, ParserError> {
+        // Parse body opening parenthesis
+        let Some(l_paren_token) = tokens.pop_front() else {
+            return Err(ParserError::MissingParenthesis(
+                statement_token.line.to_string(),
+                statement_token.value.to_string(),
+                "[".to_string(),
+                "the end of the file".to_string(),
+            ));
+        };
+
+        if l_paren_token.kind != TokenKind::LPAREN {
+            return Err(ParserError::MissingParenthesis(
+                l_paren_token.line.to_string(),
+                statement_token.value.to_string(),
+                "[".to_string(),
+                l_paren_token.value.to_string(),
+            ));
+        };
+
+        // Store the expressions/statements within the body of the if statement
+        let mut body_tokens = Vec::<AstNode>::new();
+
+        // Parse body until closing parenthesis is seen.
+        while let Some(token) = tokens.front() {
+            if token.kind == TokenKind::RPAREN {
+                break;
+            }
+            let current_expr = self.statement(tokens).with_context(|| {
+                format!(
+                    "\t[Line {}]: Invalid expression found within {} statement body.",
+                    l_paren_token.line, statement_type
+                )
+            })?;
+            body_tokens.push(current_expr);
+        }
+
+        // Verify if we saw the closing parenthesis, or if we ran out of tokens
+        let Some(r_paren_token) = tokens.pop_front() else {
+            return Err(ParserError::MissingParenthesis(
+                l_paren_token.line.to_string(),
+                statement_token.value.to_string(),
+                "]".to_string(),
+                "the end of the file".to_string(),
+            ));
+        };
+
+        if r_paren_token.kind != TokenKind::RPAREN {
+            return Err(ParserError::MissingParenthesis(
+                r_paren_token.line.to_string(),
+                statement_token.value.to_string(),
+                "]".to_string(),
+                l_paren_token.value.to_string(),
+            ));
+        };
+
+        Ok(body_tokens)
+    }
+
+    /// Parses tokens into an addition assignment node
+    fn add_assign(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        // Consume the operator token
+        tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        // Parse the next token
+        let var_token = tokens
+            .pop_front()
+            .ok_or(ParserError::UnexpectedEnding)
             .expect("Checked validity in ok_or");
 
         // Check valid identifier was provided to assign to
@@ -640,6 +2169,14 @@ impl Parser {
             ));
         }
 
+        // A redefinition would silently replace the earlier procedure
+        if !self.defined_procs.insert(proc_name_token.value.clone()) {
+            return Err(ParserError::DuplicateProcedure(
+                proc_name_token.line.to_string(),
+                proc_name_token.value.to_string(),
+            ));
+        }
+
         // Store procedure parameters
         let mut arg_tokens = Vec::<String>::new();
 
@@ -658,15 +2195,24 @@ impl Parser {
             );
         }
 
+        // Add to our procedure map: <procedure_name, Rc<<parameter_list>>
+        // so we can bind arguments to each parameter if a procedure reference is seen later.
+        // The procedure is added before its body is parsed, so the body may call it recursively.
+        // See procedure_reference for explanation of Rc usage
+        let params = Rc::new(arg_tokens);
+        self.proc_arg_map
+            .insert(proc_name_token.value.clone(), Rc::clone(&params));
+
         // Store procedure body
         let mut body_tokens = Vec::<AstNode>::new();
+        self.in_procedure = true;
 
         // Parse body until END token is seen
         while let Some(token) = tokens.front() {
             if token.kind == TokenKind::PROCEND {
                 break;
             }
-            let current_expr = self.expr(tokens).with_context(|| {
+            let current_expr = self.statement(tokens).with_context(|| {
                 format!(
                     "\t[Line {}]: Invalid expression found within Procedure {}'s body.",
                     proc_name_token.line, proc_name_token.value
@@ -674,25 +2220,63 @@ impl Parser {
             })?;
             body_tokens.push(current_expr);
         }
+        self.in_procedure = false;
 
         // Verify if we saw the END token, or if we ran out of tokens
         tokens
             .pop_front()
             .ok_or(ParserError::UnexpectedEnding)
-            .expect("Checked validity in ok_or");
-
-        // Add to our procedure map: <procedure_name, Rc<<parameter_list>>
-        // so we can bind arguments to each parameter if a procedure reference is seen later.
-        // See procedure_reference for explanation of Rc usage
-        self.proc_arg_map
-            .insert(proc_name_token.value.clone(), Rc::new(arg_tokens));
+            .with_context(|| {
+                format!(
+                    "\t[Line {}]: Procedure {} is missing END.",
+                    proc_name_token.line, proc_name_token.value
+                )
+            })?;
 
         Ok(AstNode::Procedure {
             name: proc_name_token.value,
+            params,
             body: Rc::new(body_tokens),
         })
     }
 
+    /// Parses a single statement of a program or block body.
+    /// Procedure references are checked for extra arguments here rather than in
+    /// procedure_reference, as the tokens following a reference used as an expression may
+    /// belong to the enclosing expression.
+    fn statement(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let statement_line = tokens.front().map(|token| token.statement_line);
+        let statement = self.expr(tokens)?;
+
+        if let AstNode::ProcedureRef {
+            name_ref,
+            args,
+            line,
+        } = &statement
+        {
+            let mut num_args = args.len();
+            while tokens
+                .front()
+                .is_some_and(|token| {
+                    statement_line.is_some_and(|line| is_argument_start(token, line))
+                })
+            {
+                self.expr(tokens)?;
+                num_args += 1;
+            }
+
+            if num_args != args.len() {
+                return Err(ParserError::WrongArgCount(
+                    line.to_string(),
+                    name_ref.to_string(),
+                    args.len(),
+                    num_args,
+                ));
+            }
+        }
+        Ok(statement)
+    }
+
     /// Parses tokens into a procedure reference node.
     // When a procedure reference is made, directly bind the provided arguments to the functions
     // parameters.
@@ -727,6 +2311,19 @@ impl Parser {
         // mutate the map, and will at most read from it, in the case another procedure is referenced.
         // As such, we take a Rc over the param_list to allow shared access to the map.
         for i in 0..param_list_rc.len() {
+            // Arguments must be supplied on the same line as the call
+            if !tokens
+                .front()
+                .is_some_and(|token| is_argument_start(token, proc_name.statement_line))
+            {
+                return Err(ParserError::WrongArgCount(
+                    proc_name.line.to_string(),
+                    proc_name.value,
+                    param_list_rc.len(),
+                    i,
+                ));
+            }
+
             let arg_value = self.expr(tokens).with_context(|| {
                 format!(
                     "\t[Line {}]: Invalid argument provided to procedure '{}'\n",
@@ -776,7 +2373,7 @@ impl Parser {
         }
 
         // Handle extra arguments
-        check_extra_args(tokens, direction_token.line)
+        check_extra_args(tokens, &direction_token)
             .with_context(|| format!("Error parsing '{}' expression", direction_token.value))?;
 
         Ok(AstNode::DrawInstruction {
@@ -792,6 +2389,143 @@ impl Parser {
         })
     }
 
+    /// Parses tokens into a DRAWAT node (absolute heading, length)
+    fn draw_at(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let draw_at_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let [angle, length] = self.numeric_args(tokens, &draw_at_token)?;
+
+        Ok(AstNode::DrawAt {
+            angle: Box::new(angle),
+            length: Box::new(length),
+            line: draw_at_token.line,
+        })
+    }
+
+    /// Parses tokens into a FORWARDDOTS node (length, spacing)
+    fn forward_dots(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let dots_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let [length, spacing] = self.numeric_args(tokens, &dots_token)?;
+
+        Ok(AstNode::ForwardDots {
+            length: Box::new(length),
+            spacing: Box::new(spacing),
+            line: dots_token.line,
+        })
+    }
+
+    /// Parses tokens into a STAR node (points, length)
+    fn star(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let star_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let [points, length] = self.numeric_args(tokens, &star_token)?;
+
+        Ok(AstNode::Star {
+            points: Box::new(points),
+            length: Box::new(length),
+            line: star_token.line,
+        })
+    }
+
+    /// Parses tokens into a CURVETO node (x, y)
+    fn curve_to(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let curve_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let [x, y] = self.numeric_args(tokens, &curve_token)?;
+
+        Ok(AstNode::CurveTo {
+            x: Box::new(x),
+            y: Box::new(y),
+            line: curve_token.line,
+        })
+    }
+
+    /// Parses tokens into an ARC node
+    fn arc(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let arc_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let [radius, degrees] = self.numeric_args(tokens, &arc_token)?;
+
+        Ok(AstNode::Arc {
+            radius: Box::new(radius),
+            degrees: Box::new(degrees),
+            line: arc_token.line,
+        })
+    }
+
+    /// Parses tokens into a LABEL node. The text must be a word, or a variable holding one.
+    fn label(&mut self, tokens: &mut VecDeque<Token>) -> Result<AstNode, ParserError> {
+        let label_token = tokens
+            .pop_front()
+            .expect("Token must have been verified to be passed to fn");
+
+        let text = self.expr(tokens).with_context(|| {
+            format!("\t[Line {}]: Invalid argument to LABEL\n", label_token.line)
+        })?;
+
+        if !text.is_word() {
+            return Err(ParserError::IncorrectArgType(
+                label_token.line.to_string(),
+                "Invalid LABEL statement. LABEL did not receive a word.".to_string(),
+            ));
+        }
+
+        // Handle extra arguments
+        check_extra_args(tokens, &label_token)
+            .with_context(|| format!("Error parsing '{}' expression", label_token.value))?;
+
+        Ok(AstNode::Label {
+            text: Box::new(text),
+            line: label_token.line,
+        })
+    }
+
+    /// Parses the N numeric arguments of a command, then checks no extra arguments were supplied.
+    fn numeric_args<const N: usize>(
+        &mut self,
+        tokens: &mut VecDeque<Token>,
+        command_token: &Token,
+    ) -> Result<[AstNode; N], ParserError> {
+        let mut args = Vec::<AstNode>::with_capacity(N);
+
+        for i in 1..=N {
+            let arg = self.expr(tokens).with_context(|| {
+                format!(
+                    "\t[Line {}]: Invalid argument {} to {}\n",
+                    command_token.line, i, command_token.value
+                )
+            })?;
+
+            // Check the validity of the provided expression
+            if !arg.is_numeric() {
+                return Err(ParserError::NonNumericExpr(
+                    command_token.line.to_string(),
+                    command_token.value.to_string(),
+                ));
+            }
+            args.push(arg);
+        }
+
+        // Handle extra arguments
+        check_extra_args(tokens, command_token)
+            .with_context(|| format!("Error parsing '{}' expression", command_token.value))?;
+
+        Ok(args
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("Exactly N arguments were parsed")))
+    }
+
     /// Parses a token into a word node (string literal)
     // Unbound variables which are not nested within an expression/statement are treated as raw
     // strings ('words')
@@ -804,12 +2538,22 @@ impl Parser {
     }
 }
 
+/// Returns whether a token may begin an argument to a command on the given statement line.
+/// Brackets and END close the enclosing block, so they never begin an argument.
+fn is_argument_start(token: &Token, statement_line: i32) -> bool {
+    token.statement_line == statement_line
+        && !matches!(
+            token.kind,
+            TokenKind::LPAREN | TokenKind::RPAREN | TokenKind::PROCEND
+        )
+}
+
 /// Returns an error if statement receives more arguments than expected.
-fn check_extra_args(tokens: &mut VecDeque<Token>, line_number: i32) -> Result<(), ParserError> {
+fn check_extra_args(tokens: &mut VecDeque<Token>, command: &Token) -> Result<(), ParserError> {
     let mut extra_args = Vec::<String>::new();
 
     while let Some(token) = tokens.pop_front() {
-        if token.line == line_number {
+        if token.statement_line == command.statement_line {
             extra_args.push(format!("\"{}\"", token.value));
         } else {
             tokens.push_front(token);
@@ -821,9 +2565,130 @@ fn check_extra_args(tokens: &mut VecDeque<Token>, line_number: i32) -> Result<()
         Ok(())
     } else {
         Err(ParserError::ExtraArguments(
-            line_number.to_string(),
+            command.line.to_string(),
             extra_args.join(", "),
         ))
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize_str;
+
+    /// Lexes and parses a program as rslogo does by default
+    fn parse(program: &str) -> Result<Vec<AstNode>, ParserError> {
+        let tokens = tokenize_str(program, &HashMap::new(), "//").unwrap();
+        Parser::new().parse(tokens)
+    }
+
+    #[test]
+    fn make_rejects_reserved_names() {
+        let example = include_str!("../../logo_examples/6_02_reserved_make_err.lg");
+        for program in [example, "MAKE \"FORWARD \"5\n", "MAKE \"XCOR \"5\n"] {
+            let error = parse(program).unwrap_err();
+            assert!(
+                error.to_string().contains("is a reserved keyword"),
+                "{}",
+                error
+            );
+        }
+        assert!(parse("MAKE \"colour \"5\n").is_ok());
+    }
+
+    #[test]
+    fn stray_closing_tokens_at_top_level_are_unexpected() {
+        let example = include_str!("../../logo_examples/6_05_stray_bracket_err.lg");
+        for (program, token) in [(example, "]"), ("FORWARD \"10\nEND\n", "END")] {
+            let error = parse(program).unwrap_err();
+            assert!(matches!(error, ParserError::UnexpectedToken(..)));
+            assert!(error
+                .to_string()
+                .contains(&format!("Unexpected token '{}'", token)));
+        }
+    }
+
+    #[test]
+    fn argument_count_examples_are_checked_at_the_call() {
+        for (example, received) in [
+            (
+                include_str!("../../logo_examples/6_58_too_few_args_err.lg"),
+                1,
+            ),
+            (
+                include_str!("../../logo_examples/6_59_too_many_args_err.lg"),
+                3,
+            ),
+        ] {
+            let error = parse(example).unwrap_err();
+            assert_eq!(error.code(), "E0215");
+            assert!(
+                error.to_string().contains(&format!(
+                    "Procedure Box expects 2 argument(s), but received {}",
+                    received
+                )),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn duplicate_procedure_example_is_rejected() {
+        let error = parse(include_str!(
+            "../../logo_examples/6_60_duplicate_procedure_err.lg"
+        ))
+        .unwrap_err();
+        assert_eq!(error.code(), "E0213");
+        assert!(error
+            .to_string()
+            .contains("[Line 8]: Invalid procedure: Box has already been defined"));
+    }
+
+    #[test]
+    fn ifelse_chains_parse_as_nested_else_bodies() {
+        let program = "IFELSE LT :x \"0 [\n TURN \"90\n]\n\
+                       IFELSE LT :x \"10 [\n TURN \"180\n]\n\
+                       [\n TURN \"270\n]\n";
+        let ast = parse(program).unwrap();
+        assert_eq!(ast.len(), 1);
+        assert!(matches!(
+            &ast[0],
+            AstNode::IfElseStmnt { else_body, .. }
+                if matches!(else_body.as_slice(), [AstNode::IfElseStmnt { .. }])
+        ));
+    }
+
+    #[test]
+    fn parenthesised_groups_parse_as_their_contents() {
+        let ast = parse("MAKE \"x + ( * \"2 \"3 ) \"1\n").unwrap();
+        let AstNode::MakeStmnt { expr, .. } = &ast[0] else {
+            panic!("expected MAKE, got {:?}", ast[0])
+        };
+        let AstNode::ArithExpr {
+            operator: ArithOp::ADD,
+            left,
+            right,
+            ..
+        } = &**expr
+        else {
+            panic!("expected an addition, got {:?}", expr)
+        };
+        assert!(matches!(
+            **left,
+            AstNode::ArithExpr {
+                operator: ArithOp::MUL,
+                ..
+            }
+        ));
+        assert!(matches!(**right, AstNode::Num(n) if n == 1.0));
+
+        // Groups may be nested, but every '(' must be closed by its own ')'
+        assert!(parse("FORWARD ( ( + \"2 ( \"3 ) ) )\n").is_ok());
+        for program in ["FORWARD ( + \"2 \"3\n", "FORWARD ( \"2 \"3 )\n"] {
+            let error = parse(program).unwrap_err();
+            assert!(error.to_string().contains("Unbalanced parentheses"));
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Module for tracking which pixels of the image have been drawn.
+//!
+//! `unsvg` images are vector drawings which cannot be read back, so the interpreter mirrors
+//! every line it draws into a `Raster`, recording the pen color drawn at each pixel.
+//! Commands which sense the image (such as PIXELHERE) query the raster instead.
+
+use crate::interpreter::PenColor;
+use unsvg::COLORS;
+
+/// Part of a line drawn in a single color, as (start, end, color)
+pub(crate) type Run = ((f32, f32), (f32, f32), PenColor);
+
+/// Pen color drawn at each pixel of an image, or None where nothing has been drawn
+pub(crate) struct Raster {
+    width: u32,
+    height: u32,
+    pixels: Vec<Option<PenColor>>,
+}
+
+impl Raster {
+    /// Creates an empty raster of the given dimensions
+    pub(crate) fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![None; width as usize * height as usize],
+        }
+    }
+
+    /// Returns the index of the pixel containing the given point, if it lies in the raster.
+    /// Points are rounded to the nearest pixel, so positions on a drawn line are found even
+    /// with small floating point errors.
+    fn index(&self, x: f32, y: f32) -> Option<usize> {
+        let (x, y) = (x.round(), y.round());
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+        Some(y as usize * self.width as usize + x as usize)
+    }
+
+    /// Returns the pen color drawn at the given point, if any. Points outside the raster
+    /// are treated as undrawn.
+    pub(crate) fn get_pixel(&self, x: f32, y: f32) -> Option<PenColor> {
+        self.index(x, y).and_then(|index| self.pixels[index])
+    }
+
+    /// Returns the width and height of the raster
+    pub(crate) fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the red, green and blue channels of each pixel, row by row. Undrawn pixels are
+    /// black, as in a blank unsvg image.
+    pub(crate) fn rgb_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| {
+                let color = pixel.map_or(COLORS[0], PenColor::to_color);
+                [color.red, color.green, color.blue]
+            })
+            .collect()
+    }
+
+    /// Records every pixel as drawn in the given pen color
+    pub(crate) fn fill(&mut self, color: PenColor) {
+        self.pixels.fill(Some(color));
+    }
+
+    /// Records a straight line between two points drawn in the given pen color.
+    /// The line is clipped to the raster first, so lines reaching far outside it are recorded
+    /// as quickly as those within it.
+    pub(crate) fn draw_line(&mut self, start: (f32, f32), end: (f32, f32), color: PenColor) {
+        let Some((start, end)) = self.clip(start, end) else {
+            return;
+        };
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as usize;
+
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            if let Some(index) = self.index(start.0 + dx * t, start.1 + dy * t) {
+                self.pixels[index] = Some(color);
+            }
+        }
+    }
+
+    /// Records a straight line between two points drawn in the given pen color and opacity,
+    /// blending it over the color already drawn at each pixel. Each pixel is blended once.
+    /// Returns the recorded part of the line as runs of (start, end, blended color), with
+    /// each run ending where the next begins.
+    pub(crate) fn blend_line(
+        &mut self,
+        start: (f32, f32),
+        end: (f32, f32),
+        color: PenColor,
+        opacity: f32,
+    ) -> Vec<Run> {
+        let Some((start, end)) = self.clip(start, end) else {
+            return Vec::new();
+        };
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as usize;
+        let point = |t: f32| (start.0 + dx * t, start.1 + dy * t);
+
+        let mut runs: Vec<Run> = Vec::new();
+        let mut previous = None;
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            let (x, y) = point(t);
+            let Some(index) = self.index(x, y).filter(|&index| Some(index) != previous) else {
+                continue;
+            };
+            previous = Some(index);
+            let blended = color.blended_over(self.pixels[index], opacity);
+            self.pixels[index] = Some(blended);
+
+            match runs.last_mut() {
+                Some(run) if run.2 == blended => {}
+                Some(run) => {
+                    run.1 = (x, y);
+                    runs.push(((x, y), end, blended));
+                }
+                None => runs.push((start, end, blended)),
+            }
+        }
+        runs
+    }
+
+    /// Returns the part of the line between two points which lies over the raster's pixels,
+    /// or None if the line misses the raster, using the Liang-Barsky algorithm
+    fn clip(&self, start: (f32, f32), end: (f32, f32)) -> Option<((f32, f32), (f32, f32))> {
+        // Points round to the nearest pixel, so the raster covers half a pixel past each edge
+        let (max_x, max_y) = (self.width as f32 - 0.5, self.height as f32 - 0.5);
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+
+        // Each edge limits the fraction of the line which lies on its inner side
+        let (mut enter, mut leave) = (0.0_f32, 1.0_f32);
+        let edges = [
+            (-dx, start.0 + 0.5),
+            (dx, max_x - start.0),
+            (-dy, start.1 + 0.5),
+            (dy, max_y - start.1),
+        ];
+        for (towards_edge, to_edge) in edges {
+            if towards_edge == 0.0 {
+                // Parallel to the edge, so the line lies wholly inside or outside it
+                if to_edge < 0.0 {
+                    return None;
+                }
+            } else if towards_edge < 0.0 {
+                enter = enter.max(to_edge / towards_edge);
+            } else {
+                leave = leave.min(to_edge / towards_edge);
+            }
+        }
+
+        let point = |t: f32| (start.0 + dx * t, start.1 + dy * t);
+        (enter <= leave).then(|| (point(enter), point(leave)))
+    }
+
+    /// Records a flood fill in the given pen color of the region of same-colored pixels
+    /// containing the given point. Pixels are connected to the four pixels beside, above and
+    /// below them, so regions are closed by diagonal lines.
+    /// Returns the filled pixels as horizontal runs of (row, first column, last column), or
+    /// None if the point lies outside the raster.
+    pub(crate) fn flood_fill(
+        &mut self,
+        x: f32,
+        y: f32,
+        color: PenColor,
+    ) -> Option<Vec<(u32, u32, u32)>> {
+        let start = self.index(x, y)?;
+        let target = self.pixels[start];
+        if target == Some(color) {
+            return Some(Vec::new());
+        }
+
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut runs = Vec::new();
+        let mut seeds = vec![start];
+        while let Some(seed) = seeds.pop() {
+            if self.pixels[seed] != target {
+                continue;
+            }
+
+            // Extend the seed to the whole run of target pixels in its row
+            let (row, row_start) = (seed / width, seed / width * width);
+            let (mut first, mut last) = (seed, seed);
+            while first > row_start && self.pixels[first - 1] == target {
+                first -= 1;
+            }
+            while last + 1 < row_start + width && self.pixels[last + 1] == target {
+                last += 1;
+            }
+            self.pixels[first..=last].fill(Some(color));
+            let (first_column, last_column) = (first - row_start, last - row_start);
+            runs.push((row as u32, first_column as u32, last_column as u32));
+
+            // Seed each run of target pixels touching this one in the rows above and below
+            let neighbour_rows = [row.checked_sub(1), Some(row + 1).filter(|&r| r < height)];
+            for neighbour_row in neighbour_rows.into_iter().flatten() {
+                let offset = (neighbour_row as isize - row as isize) * width as isize;
+                for index in first..=last {
+                    let neighbour = (index as isize + offset) as usize;
+                    let run_start = index == first || self.pixels[neighbour - 1] != target;
+                    if self.pixels[neighbour] == target && run_start {
+                        seeds.push(neighbour);
+                    }
+                }
+            }
+        }
+        Some(runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the pixels of a raster which have been drawn, row by row
+    fn drawn_pixels(raster: &Raster) -> Vec<(u32, u32)> {
+        let (width, height) = raster.dimensions();
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|&(x, y)| raster.get_pixel(x as f32, y as f32).is_some())
+            .collect()
+    }
+
+    #[test]
+    fn far_off_canvas_line_is_clipped_to_the_raster() {
+        let mut raster = Raster::new(10, 10);
+        let color = PenColor::Indexed(7);
+        raster.draw_line((5.0, 5.0), (5.0, -1e9), color);
+        raster.draw_line((3.0, 2.0), (1e30, 2.0), color);
+
+        let column = (0..=5).map(|y| (5, y));
+        let row = (3..10).map(|x| (x, 2));
+        let mut expected = column.chain(row).collect::<Vec<_>>();
+        expected.sort_by_key(|&(x, y)| (y, x));
+        expected.dedup();
+        assert_eq!(drawn_pixels(&raster), expected);
+    }
+
+    #[test]
+    fn line_missing_the_raster_draws_nothing() {
+        let mut raster = Raster::new(10, 10);
+        raster.draw_line((-5.0, -5.0), (-5.0, 1e9), PenColor::Indexed(7));
+        raster.draw_line((20.0, 0.0), (0.0, -20.0), PenColor::Indexed(7));
+        assert!(drawn_pixels(&raster).is_empty());
+    }
+}
@@ -0,0 +1,40 @@
+//! Module providing the random number generator behind RANDOM.
+//!
+//! A small SplitMix64 generator is used rather than an external crate: RANDOM only needs
+//! uniformly distributed floats, and a fixed algorithm keeps seeded sequences reproducible
+//! across platforms and releases.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// A seedable pseudo-random number generator
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a generator which produces the same sequence for the same seed
+    pub(crate) fn with_seed(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Creates a generator seeded from the operating system's randomness, via the random keys
+    /// the standard library uses for hash maps
+    pub(crate) fn from_entropy() -> Self {
+        Self::with_seed(RandomState::new().build_hasher().finish())
+    }
+
+    /// Returns the next 64 random bits
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random float in [0, 1), using the 24 bits an f32 can represent exactly
+    pub(crate) fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+}
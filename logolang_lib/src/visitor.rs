@@ -0,0 +1,343 @@
+//! Module providing a reusable traversal over the RSLOGO AST for analysis passes.
+//!
+//! Passes implement the `Visitor` trait, overriding the methods for the node kinds they are
+//! interested in, and call `walk` to visit a node and every node nested within it. Every
+//! method defaults to doing nothing, so a pass only needs to describe what it inspects.
+//!
+//! # Examples
+//!
+//! ```
+//! use logolang_lib::parser::{AstNode, Direction};
+//! use logolang_lib::visitor::{walk_all, Visitor};
+//!
+//! struct DrawCounter(usize);
+//!
+//! impl Visitor for DrawCounter {
+//!     fn visit_draw_instruction(&mut self, _node: &AstNode) {
+//!         self.0 += 1;
+//!     }
+//! }
+//!
+//! let ast = vec![
+//!     AstNode::DrawInstruction {
+//!         direction: Direction::FORWARD,
+//!         num_pixels: Box::new(AstNode::Num(10.0)),
+//!         line: 1,
+//!     },
+//!     AstNode::IfStmnt {
+//!         condition: Box::new(AstNode::IdentRef(String::from("x"))),
+//!         body: Box::new(vec![AstNode::DrawInstruction {
+//!             direction: Direction::LEFT,
+//!             num_pixels: Box::new(AstNode::Num(5.0)),
+//!             line: 3,
+//!         }]),
+//!         line: 2,
+//!     },
+//! ];
+//!
+//! let mut counter = DrawCounter(0);
+//! walk_all(&ast, &mut counter);
+//! assert_eq!(counter.0, 2);
+//! ```
+
+use crate::parser::AstNode;
+
+/// A pass over the AST. Each method is called with a node of the matching kind before the
+/// nodes nested within it are visited.
+#[allow(unused_variables)]
+pub trait Visitor {
+    /// Called with every node, before the method for its kind
+    fn visit_node(&mut self, node: &AstNode) {}
+
+    fn visit_make_stmnt(&mut self, node: &AstNode) {}
+    fn visit_arith_expr(&mut self, node: &AstNode) {}
+    fn visit_comp_expr(&mut self, node: &AstNode) {}
+    fn visit_bool_expr(&mut self, node: &AstNode) {}
+    fn visit_trig_expr(&mut self, node: &AstNode) {}
+    fn visit_round_expr(&mut self, node: &AstNode) {}
+    fn visit_negate(&mut self, node: &AstNode) {}
+    fn visit_abs(&mut self, node: &AstNode) {}
+    fn visit_sqrt(&mut self, node: &AstNode) {}
+    fn visit_not_expr(&mut self, node: &AstNode) {}
+    fn visit_random(&mut self, node: &AstNode) {}
+    fn visit_ident_ref(&mut self, node: &AstNode) {}
+    fn visit_add_assign(&mut self, node: &AstNode) {}
+    fn visit_ident(&mut self, node: &AstNode) {}
+    fn visit_num(&mut self, node: &AstNode) {}
+    fn visit_bool_literal(&mut self, node: &AstNode) {}
+    fn visit_if_stmnt(&mut self, node: &AstNode) {}
+    fn visit_if_else_stmnt(&mut self, node: &AstNode) {}
+    fn visit_while_stmnt(&mut self, node: &AstNode) {}
+    fn visit_repeat_stmnt(&mut self, node: &AstNode) {}
+    fn visit_for_stmnt(&mut self, node: &AstNode) {}
+    fn visit_for_each_stmnt(&mut self, node: &AstNode) {}
+    fn visit_pen_status_update(&mut self, node: &AstNode) {}
+    fn visit_turtle_visibility(&mut self, node: &AstNode) {}
+    fn visit_angle_units(&mut self, node: &AstNode) {}
+    fn visit_pen_color_update(&mut self, node: &AstNode) {}
+    fn visit_pen_rgb_update(&mut self, node: &AstNode) {}
+    fn visit_pen_opacity_update(&mut self, node: &AstNode) {}
+    fn visit_pen_width_update(&mut self, node: &AstNode) {}
+    fn visit_pen_dash_update(&mut self, node: &AstNode) {}
+    fn visit_background_update(&mut self, node: &AstNode) {}
+    fn visit_save_pen(&mut self, node: &AstNode) {}
+    fn visit_load_pen(&mut self, node: &AstNode) {}
+    fn visit_mark(&mut self, node: &AstNode) {}
+    fn visit_go_mark(&mut self, node: &AstNode) {}
+    fn visit_pen_pos_update(&mut self, node: &AstNode) {}
+    fn visit_query(&mut self, node: &AstNode) {}
+    fn visit_procedure(&mut self, node: &AstNode) {}
+    fn visit_procedure_ref(&mut self, node: &AstNode) {}
+    fn visit_stop(&mut self, node: &AstNode) {}
+    fn visit_output(&mut self, node: &AstNode) {}
+    fn visit_draw_instruction(&mut self, node: &AstNode) {}
+    fn visit_draw_at(&mut self, node: &AstNode) {}
+    fn visit_if_val(&mut self, node: &AstNode) {}
+    fn visit_forward_dots(&mut self, node: &AstNode) {}
+    fn visit_star(&mut self, node: &AstNode) {}
+    fn visit_curve_to(&mut self, node: &AstNode) {}
+    fn visit_arc(&mut self, node: &AstNode) {}
+    fn visit_set_position(&mut self, node: &AstNode) {}
+    fn visit_label(&mut self, node: &AstNode) {}
+    fn visit_fill(&mut self, node: &AstNode) {}
+    fn visit_stamp(&mut self, node: &AstNode) {}
+    fn visit_reset_all(&mut self, node: &AstNode) {}
+    fn visit_home(&mut self, node: &AstNode) {}
+    fn visit_clear_screen(&mut self, node: &AstNode) {}
+    fn visit_show_path(&mut self, node: &AstNode) {}
+    fn visit_axes(&mut self, node: &AstNode) {}
+    fn visit_word(&mut self, node: &AstNode) {}
+}
+
+/// Visits a node with the given visitor, then recursively visits every expression and
+/// statement nested within it, in program order.
+pub fn walk<V: Visitor + ?Sized>(node: &AstNode, visitor: &mut V) {
+    visitor.visit_node(node);
+    match node {
+        AstNode::MakeStmnt { expr, .. } => {
+            visitor.visit_make_stmnt(node);
+            walk(expr, visitor);
+        }
+        AstNode::ArithExpr { left, right, .. } => {
+            visitor.visit_arith_expr(node);
+            walk(left, visitor);
+            walk(right, visitor);
+        }
+        AstNode::CompExpr { left, right, .. } => {
+            visitor.visit_comp_expr(node);
+            walk(left, visitor);
+            walk(right, visitor);
+        }
+        AstNode::BoolExpr { left, right, .. } => {
+            visitor.visit_bool_expr(node);
+            walk(left, visitor);
+            walk(right, visitor);
+        }
+        AstNode::TrigExpr { operand, .. } => {
+            visitor.visit_trig_expr(node);
+            walk(operand, visitor);
+        }
+        AstNode::RoundExpr { operand, .. } => {
+            visitor.visit_round_expr(node);
+            walk(operand, visitor);
+        }
+        AstNode::Negate { operand, .. } => {
+            visitor.visit_negate(node);
+            walk(operand, visitor);
+        }
+        AstNode::NotExpr { operand, .. } => {
+            visitor.visit_not_expr(node);
+            walk(operand, visitor);
+        }
+        AstNode::Abs { operand, .. } => {
+            visitor.visit_abs(node);
+            walk(operand, visitor);
+        }
+        AstNode::Sqrt { operand, .. } => {
+            visitor.visit_sqrt(node);
+            walk(operand, visitor);
+        }
+        AstNode::Random { max, .. } => {
+            visitor.visit_random(node);
+            walk(max, visitor);
+        }
+        AstNode::IdentRef(_) => visitor.visit_ident_ref(node),
+        AstNode::AddAssign { expr, .. } => {
+            visitor.visit_add_assign(node);
+            walk(expr, visitor);
+        }
+        AstNode::Ident { .. } => visitor.visit_ident(node),
+        AstNode::Num(_) => visitor.visit_num(node),
+        AstNode::BoolLiteral(_) => visitor.visit_bool_literal(node),
+        AstNode::IfStmnt {
+            condition, body, ..
+        } => {
+            visitor.visit_if_stmnt(node);
+            walk(condition, visitor);
+            walk_all(body, visitor);
+        }
+        AstNode::IfElseStmnt {
+            condition,
+            then_body,
+            else_body,
+            ..
+        } => {
+            visitor.visit_if_else_stmnt(node);
+            walk(condition, visitor);
+            walk_all(then_body, visitor);
+            walk_all(else_body, visitor);
+        }
+        AstNode::WhileStmnt {
+            condition, body, ..
+        } => {
+            visitor.visit_while_stmnt(node);
+            walk(condition, visitor);
+            walk_all(body, visitor);
+        }
+        AstNode::RepeatStmnt { count, body, .. } => {
+            visitor.visit_repeat_stmnt(node);
+            walk(count, visitor);
+            walk_all(body, visitor);
+        }
+        AstNode::ForStmnt {
+            start,
+            end,
+            step,
+            body,
+            ..
+        } => {
+            visitor.visit_for_stmnt(node);
+            walk(start, visitor);
+            walk(end, visitor);
+            walk(step, visitor);
+            walk_all(body, visitor);
+        }
+        AstNode::ForEachStmnt { values, body, .. } => {
+            visitor.visit_for_each_stmnt(node);
+            walk_all(values, visitor);
+            walk_all(body, visitor);
+        }
+        AstNode::PenStatusUpdate(_) => visitor.visit_pen_status_update(node),
+        AstNode::TurtleVisibility(_) => visitor.visit_turtle_visibility(node),
+        AstNode::AngleUnits(_) => visitor.visit_angle_units(node),
+        AstNode::PenColorUpdate { color, .. } => {
+            visitor.visit_pen_color_update(node);
+            walk(color, visitor);
+        }
+        AstNode::PenRgbUpdate {
+            red, green, blue, ..
+        } => {
+            visitor.visit_pen_rgb_update(node);
+            walk(red, visitor);
+            walk(green, visitor);
+            walk(blue, visitor);
+        }
+        AstNode::PenOpacityUpdate { opacity, .. } => {
+            visitor.visit_pen_opacity_update(node);
+            walk(opacity, visitor);
+        }
+        AstNode::BackgroundUpdate { color, .. } => {
+            visitor.visit_background_update(node);
+            walk(color, visitor);
+        }
+        AstNode::PenWidthUpdate { width, .. } => {
+            visitor.visit_pen_width_update(node);
+            walk(width, visitor);
+        }
+        AstNode::PenDashUpdate { on, off, .. } => {
+            visitor.visit_pen_dash_update(node);
+            walk(on, visitor);
+            walk(off, visitor);
+        }
+        AstNode::SavePen { .. } => visitor.visit_save_pen(node),
+        AstNode::LoadPen { .. } => visitor.visit_load_pen(node),
+        AstNode::Mark { .. } => visitor.visit_mark(node),
+        AstNode::GoMark { .. } => visitor.visit_go_mark(node),
+        AstNode::PenPosUpdate { value, .. } => {
+            visitor.visit_pen_pos_update(node);
+            walk(value, visitor);
+        }
+        AstNode::Query(_) => visitor.visit_query(node),
+        AstNode::Procedure { body, .. } => {
+            visitor.visit_procedure(node);
+            walk_all(body, visitor);
+        }
+        AstNode::ProcedureRef { args, .. } => {
+            visitor.visit_procedure_ref(node);
+            walk_all(args, visitor);
+        }
+        AstNode::Stop { .. } => visitor.visit_stop(node),
+        AstNode::Output { value, .. } => {
+            visitor.visit_output(node);
+            walk(value, visitor);
+        }
+        AstNode::DrawInstruction { num_pixels, .. } => {
+            visitor.visit_draw_instruction(node);
+            walk(num_pixels, visitor);
+        }
+        AstNode::DrawAt { angle, length, .. } => {
+            visitor.visit_draw_at(node);
+            walk(angle, visitor);
+            walk(length, visitor);
+        }
+        AstNode::IfVal {
+            condition,
+            then_val,
+            else_val,
+            ..
+        } => {
+            visitor.visit_if_val(node);
+            walk(condition, visitor);
+            walk(then_val, visitor);
+            walk(else_val, visitor);
+        }
+        AstNode::ForwardDots {
+            length, spacing, ..
+        } => {
+            visitor.visit_forward_dots(node);
+            walk(length, visitor);
+            walk(spacing, visitor);
+        }
+        AstNode::Star { points, length, .. } => {
+            visitor.visit_star(node);
+            walk(points, visitor);
+            walk(length, visitor);
+        }
+        AstNode::CurveTo { x, y, .. } => {
+            visitor.visit_curve_to(node);
+            walk(x, visitor);
+            walk(y, visitor);
+        }
+        AstNode::Arc {
+            radius, degrees, ..
+        } => {
+            visitor.visit_arc(node);
+            walk(radius, visitor);
+            walk(degrees, visitor);
+        }
+        AstNode::SetPosition { x, y, .. } => {
+            visitor.visit_set_position(node);
+            walk(x, visitor);
+            walk(y, visitor);
+        }
+        AstNode::Label { text, .. } => {
+            visitor.visit_label(node);
+            walk(text, visitor);
+        }
+        AstNode::ResetAll { .. } => visitor.visit_reset_all(node),
+        AstNode::Fill { .. } => visitor.visit_fill(node),
+        AstNode::Stamp { .. } => visitor.visit_stamp(node),
+        AstNode::Home { .. } => visitor.visit_home(node),
+        AstNode::ClearScreen { .. } => visitor.visit_clear_screen(node),
+        AstNode::ShowPath { .. } => visitor.visit_show_path(node),
+        AstNode::Axes { .. } => visitor.visit_axes(node),
+        AstNode::Word(_) => visitor.visit_word(node),
+    }
+}
+
+/// Visits each node in a block of statements in order
+pub fn walk_all<V: Visitor + ?Sized>(nodes: &[AstNode], visitor: &mut V) {
+    for node in nodes {
+        walk(node, visitor);
+    }
+}
@@ -1,10 +1,17 @@
+use analysis::{call_graph, call_graph_dot, metrics, recursive_cycles};
 use anyhow::Result;
 use clap::Parser as clapParser;
-use interpreter::Interpreter;
+use folder::fold_constants;
+use inliner::inline_procedures;
+use interpreter::{BoundaryMode, Interpreter, OverflowMode, PenColorMode, Value};
 use lexer::tokenize;
 use logolang_lib::logolang_errors::ImgFileError;
-use logolang_lib::{interpreter, lexer, parser};
-use parser::Parser;
+use logolang_lib::{analysis, folder, inliner, interpreter, lexer, parser};
+use parser::{AstNode, Parser};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
 use unsvg::Image;
 
 /// A simple program to parse four arguments using clap.
@@ -21,18 +28,215 @@ struct Args {
 
     /// Width
     width: u32,
+
+    /// Continue past errors in top-level statements, reporting them all after rendering
+    #[arg(long)]
+    lenient: bool,
+
+    /// Debug mode: draw each procedure's output in a distinct color
+    #[arg(long)]
+    color_by_proc: bool,
+
+    /// Saturate overflowing arithmetic to the largest finite value instead of erroring
+    #[arg(long)]
+    saturate: bool,
+
+    /// Clamp out of range pen colors to the nearest palette index instead of erroring
+    #[arg(long, conflicts_with = "modulo_pencolor")]
+    clamp_pencolor: bool,
+
+    /// Wrap out of range pen colors around the palette (-1 is 15, 16 is 0) instead of erroring
+    #[arg(long)]
+    modulo_pencolor: bool,
+
+    /// Stop with an error when the turtle would leave the image, instead of clipping its lines
+    #[arg(long, conflicts_with = "wrap")]
+    fence: bool,
+
+    /// Bring the turtle back in at the opposite edge when it leaves the image
+    #[arg(long)]
+    wrap: bool,
+
+    /// Blank margin in pixels added around every side of the drawing
+    #[arg(long, default_value_t = 0)]
+    margin: u32,
+
+    /// Define an alias for a keyword, e.g. fd=FORWARD (repeatable)
+    #[arg(long = "alias", value_parser = parse_alias)]
+    aliases: Vec<(String, String)>,
+
+    /// Prefix marking a line as a comment
+    #[arg(long, default_value = "//", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    comment_prefix: String,
+
+    /// Inline procedure calls before running, reducing call overhead in tight loops
+    #[arg(long, conflicts_with = "color_by_proc")]
+    inline: bool,
+
+    /// Render png images at this many times their size, then downsample for smoother edges
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=8))]
+    supersample: u32,
+
+    /// Draw coordinate axes through the centre of the image before running the program
+    #[arg(long)]
+    axes: bool,
+
+    /// Print the procedure call graph in the DOT language instead of running the program
+    #[arg(long)]
+    call_graph: bool,
+
+    /// Print size and structure metrics of the program instead of running it
+    #[arg(long, conflicts_with = "call_graph")]
+    metrics: bool,
+
+    /// Run the program once per value of a variable, as name=start:end:step. Each image is
+    /// saved with the run number appended to its name, e.g. out_0.svg, out_1.svg
+    #[arg(long, value_parser = parse_param_sweep)]
+    param: Option<ParamSweep>,
+
+    /// Bind a variable before the program runs, e.g. count=5 (repeatable). Values which read
+    /// as numbers are numbers, and any others are words
+    #[arg(long = "arg", value_parser = parse_program_arg)]
+    program_args: Vec<(String, Value)>,
+
+    /// Also write the drawn lines as G-code for a pen plotter to this path
+    #[arg(long)]
+    gcode: Option<PathBuf>,
+
+    /// Size of one pixel in millimetres when writing G-code
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive_scale, requires = "gcode")]
+    scale: f32,
+}
+
+/// Largest number of values a parameter sweep may run the program for
+const MAX_SWEEP_VALUES: usize = 1000;
+
+/// A variable swept over a range of values, one program run per value
+#[derive(Clone)]
+struct ParamSweep {
+    name: String,
+    values: Vec<f32>,
+}
+
+/// Parses a parameter sweep of the form name=start:end:step
+fn parse_param_sweep(definition: &str) -> Result<ParamSweep, String> {
+    let invalid =
+        || format!("invalid parameter '{definition}': expected the form name=start:end:step");
+
+    let (name, range) = definition.split_once('=').ok_or_else(invalid)?;
+    let bounds = range
+        .split(':')
+        .map(|bound| bound.parse::<f32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<f32>, String>>()?;
+    let [start, end, step] = bounds[..] else {
+        return Err(invalid());
+    };
+    let bounds_finite = start.is_finite() && end.is_finite() && step.is_finite();
+    if name.is_empty() || !bounds_finite || step <= 0.0 || end < start {
+        return Err(format!(
+            "invalid parameter '{definition}': the name must be non-empty, and step positive with start <= end"
+        ));
+    }
+
+    // Values are computed from the start so rounding errors don't accumulate
+    let num_values = ((end - start) / step + 1e-4).floor() + 1.0;
+    if num_values > MAX_SWEEP_VALUES as f32 {
+        return Err(format!(
+            "invalid parameter '{definition}': the sweep has more than {MAX_SWEEP_VALUES} values"
+        ));
+    }
+    let num_values = num_values as usize;
+    let values = (0..num_values).map(|i| start + i as f32 * step).collect();
+    Ok(ParamSweep {
+        name: name.to_string(),
+        values,
+    })
+}
+
+/// Parses a program argument of the form name=value
+fn parse_program_arg(definition: &str) -> Result<(String, Value), String> {
+    match definition.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), Value::parse(value))),
+        _ => Err(format!(
+            "invalid argument '{definition}': expected the form name=value"
+        )),
+    }
+}
+
+/// Parses a G-code scale, which must be a positive number of millimetres
+fn parse_positive_scale(scale: &str) -> Result<f32, String> {
+    match scale.parse::<f32>() {
+        Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(scale),
+        _ => Err(format!(
+            "invalid scale '{scale}': expected a positive number"
+        )),
+    }
+}
+
+/// Appends a run number to an image path, e.g. out.svg becomes out_2.svg
+fn numbered_path(image_path: &Path, run: usize) -> PathBuf {
+    let stem = image_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let mut path = image_path.with_file_name(format!("{stem}_{run}"));
+    if let Some(extension) = image_path.extension() {
+        path.set_extension(extension);
+    }
+    path
+}
+
+/// Parses an alias definition of the form alias=KEYWORD
+fn parse_alias(definition: &str) -> Result<(String, String), String> {
+    match definition.split_once('=') {
+        Some((alias, keyword)) if !alias.is_empty() && !keyword.is_empty() => {
+            Ok((alias.to_string(), keyword.to_string()))
+        }
+        _ => Err(format!(
+            "invalid alias '{definition}': expected the form alias=KEYWORD"
+        )),
+    }
+}
+
+/// Shrinks the png image at the given path by an integer factor, averaging each
+/// factor x factor block of pixels into one.
+fn downsample_png(path: &std::path::Path, factor: u32) -> Result<()> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    let channels = info.color_type.samples();
+
+    let (width, height) = (info.width / factor, info.height / factor);
+    let mut pixels = Vec::with_capacity((width * height) as usize * channels);
+    for y in 0..height {
+        for x in 0..width {
+            for c in 0..channels {
+                let mut sum = 0;
+                for dy in 0..factor {
+                    for dx in 0..factor {
+                        let (src_x, src_y) = (x * factor + dx, y * factor + dy);
+                        sum += buf[(src_y * info.width + src_x) as usize * channels + c] as u32;
+                    }
+                }
+                pixels.push((sum / (factor * factor)) as u8);
+            }
+        }
+    }
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(info.color_type);
+    encoder.set_depth(info.bit_depth);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(())
 }
 
 fn main() -> Result<()> {
     let args: Args = Args::parse();
-    // Access the parsed arguments
-    let file_path = args.file_path;
-    let image_path = args.image_path;
-    let image_width = args.width;
-    let image_height = args.height;
 
     // Generate Tokens, manage errors
-    let tokens = match tokenize(file_path) {
+    let aliases: HashMap<String, String> = args.aliases.iter().cloned().collect();
+    let tokens = match tokenize(args.file_path.clone(), &aliases, &args.comment_prefix) {
         Ok(tokens) => tokens,
         Err(e) => {
             return Err(e.into());
@@ -47,26 +251,129 @@ fn main() -> Result<()> {
             return Err(e.into());
         }
     };
+    for warning in parser.warnings() {
+        eprintln!("Warning: {warning}");
+    }
+
+    if args.call_graph {
+        let graph = call_graph(&ast);
+        for cycle in recursive_cycles(&graph) {
+            match cycle.as_slice() {
+                [name] => eprintln!("Warning: {} calls itself recursively", name),
+                _ => eprintln!("Warning: recursive cycle between {}", cycle.join(", ")),
+            }
+        }
+        print!("{}", call_graph_dot(&graph));
+        return Ok(());
+    }
+
+    if args.metrics {
+        print!("{}", metrics(&ast));
+        return Ok(());
+    }
+
+    let ast = if args.inline {
+        inline_procedures(ast)
+    } else {
+        ast
+    };
+    let ast = fold_constants(ast);
+
+    let is_png = args.image_path.extension().and_then(|s| s.to_str()) == Some("png");
+    if args.supersample > 1 && !is_png {
+        return Err(ImgFileError::SupersampleRequiresPng.into());
+    }
+
+    match &args.param {
+        Some(sweep) => {
+            for (run, value) in sweep.values.iter().enumerate() {
+                let image_path = numbered_path(&args.image_path, run);
+                let gcode_path = args.gcode.as_ref().map(|path| numbered_path(path, run));
+                render(
+                    &args,
+                    &ast,
+                    &image_path,
+                    gcode_path.as_deref(),
+                    Some((&sweep.name, *value)),
+                )?;
+            }
+            Ok(())
+        }
+        None => render(&args, &ast, &args.image_path, args.gcode.as_deref(), None),
+    }
+}
 
-    let mut empty_image = Image::new(image_width, image_height);
+/// Runs the program on a new image and saves it to the given path, along with the G-code
+/// for the drawing if a path is given. Variables given with --arg are bound before the program
+/// runs, followed by the optional binding of a swept parameter.
+fn render(
+    args: &Args,
+    ast: &Vec<AstNode>,
+    image_path: &Path,
+    gcode_path: Option<&Path>,
+    preset: Option<(&str, f32)>,
+) -> Result<()> {
+    let mut empty_image = Image::new(args.width, args.height);
 
     // Loop nodes and evaluate
     let mut interpreter = Interpreter::new(&mut empty_image);
-    match interpreter.run(&ast) {
+    interpreter.set_color_by_proc(args.color_by_proc);
+    if args.margin > 0 {
+        interpreter.set_margin(args.margin);
+    }
+    if args.supersample > 1 {
+        interpreter.set_supersample(args.supersample);
+    }
+    if args.axes {
+        interpreter.draw_axes()?;
+    }
+    if args.saturate {
+        interpreter.set_overflow_mode(OverflowMode::Saturate);
+    }
+    if args.clamp_pencolor {
+        interpreter.set_pen_color_mode(PenColorMode::Clamp);
+    } else if args.modulo_pencolor {
+        interpreter.set_pen_color_mode(PenColorMode::Modulo);
+    }
+    if args.fence {
+        interpreter.set_boundary_mode(BoundaryMode::Fence);
+    } else if args.wrap {
+        interpreter.set_boundary_mode(BoundaryMode::Wrap);
+    }
+    for (name, value) in &args.program_args {
+        interpreter.preset_variable(name, value.clone());
+    }
+    if let Some((name, value)) = preset {
+        interpreter.preset_variable(name, Value::Float(value));
+    }
+    let result = if args.lenient {
+        let (image, errors) = interpreter.run_lenient(ast);
+        for e in &errors {
+            eprintln!("{e}");
+        }
+        Ok(image)
+    } else {
+        interpreter.run(ast)
+    };
+
+    match result {
         Ok(image) => match image_path.extension().and_then(|s| s.to_str()) {
             Some("svg") => {
-                let res = image.save_svg(&image_path);
+                let res = image.save_svg(image_path);
                 if let Err(e) = res {
                     eprintln!("Error saving svg: {e}");
                     return Err(e.into());
                 }
             }
             Some("png") => {
-                let res = image.save_png(&image_path);
+                let res = image.save_png(image_path);
                 if let Err(e) = res {
                     eprintln!("Error saving png: {e}");
                     return Err(e.into());
                 }
+                if args.supersample > 1 {
+                    downsample_png(image_path, args.supersample)?;
+                }
             }
             _ => {
                 eprintln!("File extension not supported");
@@ -78,5 +385,36 @@ fn main() -> Result<()> {
             }
     }
 
+    for warning in interpreter.warnings() {
+        eprintln!("Warning: {warning}");
+    }
+
+    if let Some(gcode_path) = gcode_path {
+        std::fs::write(gcode_path, interpreter.gcode(args.scale))?;
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn param_sweep_rejects_invalid_steps_and_huge_sweeps() {
+        let values = |definition: &str| parse_param_sweep(definition).map(|sweep| sweep.values);
+        assert_eq!(values("x=0:1:0.5"), Ok(vec![0.0, 0.5, 1.0]));
+        assert_eq!(values("x=0:999:1").map(|values| values.len()), Ok(1000));
+
+        for definition in [
+            "x=0:10:NaN",
+            "x=0:10:inf",
+            "x=0:10:0",
+            "x=0:1e12:1",
+            "x=0:1000:1",
+        ] {
+            let error = parse_param_sweep(definition).err().unwrap();
+            assert!(error.starts_with("invalid parameter"), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Tests of the rslogo command line, running the built binary on programs written to a
+//! temporary directory.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Returns a new empty directory for a test's files
+fn test_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rslogo_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs rslogo on a program drawing to the given image, with 100x100 dimensions and any
+/// further arguments, and asserts that it succeeds
+fn rslogo(dir: &Path, program: &str, image: &Path, args: &[&str]) {
+    let program_path = dir.join("program.lg");
+    fs::write(&program_path, program).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rslogo"))
+        .arg(&program_path)
+        .arg(image)
+        .args(["100", "100"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "rslogo failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Returns the width, height and red channel of each pixel of a png image
+fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let mut reader = png::Decoder::new(File::open(path).unwrap())
+        .read_info()
+        .unwrap();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    let channels = info.color_type.samples();
+    let red = buf[..info.buffer_size()]
+        .iter()
+        .step_by(channels)
+        .copied()
+        .collect();
+    (info.width, info.height, red)
+}
+
+#[test]
+fn supersampling_keeps_the_size_and_smooths_edges() {
+    let dir = test_dir("supersample");
+    let program = "PENDOWN\nTURN \"30\nFORWARD \"40\n";
+    let (plain, supersampled) = (dir.join("plain.png"), dir.join("supersampled.png"));
+    rslogo(&dir, program, &plain, &[]);
+    rslogo(&dir, program, &supersampled, &["--supersample", "2"]);
+
+    let (width, height, plain) = read_png(&plain);
+    assert_eq!((width, height), (100, 100));
+    let (width, height, supersampled) = read_png(&supersampled);
+    assert_eq!((width, height), (100, 100));
+
+    // Smoother edges blend into the background over more partly covered pixels
+    let partly_covered = |red: &[u8]| red.iter().filter(|&&v| v > 0 && v < 255).count();
+    assert!(partly_covered(&supersampled) > partly_covered(&plain));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn param_sweep_writes_a_numbered_image_per_value() {
+    let dir = test_dir("param_sweep");
+    let program = "PENDOWN\nFORWARD :len\n";
+    rslogo(
+        &dir,
+        program,
+        &dir.join("out.svg"),
+        &["--param", "len=10:30:10"],
+    );
+
+    let images = (0..3)
+        .map(|run| fs::read_to_string(dir.join(format!("out_{}.svg", run))).unwrap())
+        .collect::<Vec<_>>();
+    assert!(!dir.join("out_3.svg").exists());
+    assert_ne!(images[0], images[1]);
+    assert_ne!(images[1], images[2]);
+    assert_ne!(images[0], images[2]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn aliases_example_draws_as_the_keywords_it_aliases() {
+    let dir = test_dir("aliases");
+    let (aliased, plain) = (dir.join("aliased.png"), dir.join("plain.png"));
+    let aliases = [
+        "--alias",
+        "pd=PENDOWN",
+        "--alias",
+        "fd=FORWARD",
+        "--alias",
+        "rt=RIGHT",
+        "--alias",
+        "bk=BACK",
+    ];
+    rslogo(
+        &dir,
+        include_str!("../logo_examples/6_17_aliases.lg"),
+        &aliased,
+        &aliases,
+    );
+    rslogo(
+        &dir,
+        "PENDOWN\nFORWARD \"10\nRIGHT \"10\nBACK \"10\n",
+        &plain,
+        &[],
+    );
+
+    let (_, _, red) = read_png(&aliased);
+    assert!(red.iter().any(|&v| v != red[0]));
+    assert_eq!(red, read_png(&plain).2);
+    fs::remove_dir_all(dir).unwrap();
+}